@@ -0,0 +1,530 @@
+//! JSON RPC batch requests.
+//!
+//! A [`BatchRequest`] accumulates method calls, possibly of different types, and sends them to the server
+//! in a single JSON RPC batch. Each call returns a [`BatchHandle`] that's used to retrieve its typed result
+//! from the [`BatchResponse`].
+//!
+//! If the server doesn't support batches, or leaves some of the calls in a batch unanswered, those calls
+//! are sent individually and concurrently instead.
+//! Unanswered calls to [`NON_IDEMPOTENT_METHODS`] are the exception, since the server may have run them
+//! anyway, they fail with [`JsonRpcTransportRecvError::UnansweredBatchCall`] instead.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use near_jsonrpc_client::{methods, JsonRpcClient};
+//! use near_primitives::types::{BlockReference, Finality};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = JsonRpcClient::connect("https://rpc.testnet.near.org");
+//!
+//! let mut batch = client.batch();
+//!
+//! let status = batch.add(methods::status::RpcStatusRequest);
+//! let block = batch.add(methods::block::RpcBlockRequest {
+//!     block_reference: BlockReference::Finality(Finality::Final),
+//! });
+//!
+//! let mut responses = batch.send().await?;
+//!
+//! let status = responses.take(status)?;
+//! let block = responses.take(block)?;
+//!
+//! println!("{} is at #{}", status.chain_id, block.header.height);
+//! # Ok(())
+//! # }
+//! ```
+use std::any::Any;
+use std::convert::Infallible;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use near_jsonrpc_primitives::errors::RpcError;
+use near_jsonrpc_primitives::message::Message;
+use serde_json::Value;
+
+use crate::errors::*;
+use crate::middleware::CallInfo;
+use crate::options::CallOptions;
+use crate::retry::NON_IDEMPOTENT_METHODS;
+use crate::{methods, JsonRpcClient, MethodCallResult};
+
+static NEXT_BATCH_ID: AtomicUsize = AtomicUsize::new(0);
+
+type RawResult = Result<Result<Value, RpcError>, JsonRpcError<Infallible>>;
+
+/// The [`MethodCallResult`] of a call, erased to fit in a batch of calls of different types.
+type TypedResult = Box<dyn Any + Send>;
+
+type Fallback = for<'a> fn(&'a JsonRpcClient, &'a str, Value) -> BoxFuture<'a, TypedResult>;
+
+/// A batch of method calls waiting to be sent.
+///
+/// See the [`batch`](self) module documentation for more information.
+#[derive(Debug)]
+pub struct BatchRequest {
+    client: JsonRpcClient,
+    batch_id: usize,
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    method_name: String,
+    payload: Option<Value>,
+    result: Option<RawResult>,
+    parse: fn(RawResult) -> TypedResult,
+    fallback: Fallback,
+}
+
+/// A handle to the result of a method call in a batch.
+#[derive(Debug)]
+pub struct BatchHandle<M> {
+    batch_id: usize,
+    index: usize,
+    _method: PhantomData<fn() -> M>,
+}
+
+/// The results of a batch of method calls.
+#[derive(Debug)]
+pub struct BatchResponse {
+    batch_id: usize,
+    results: Vec<Option<TypedResult>>,
+}
+
+impl JsonRpcClient {
+    /// Start a new batch of method calls.
+    ///
+    /// See the [`batch`](crate::batch) module documentation for more information.
+    pub fn batch(&self) -> BatchRequest {
+        BatchRequest {
+            client: self.clone(),
+            batch_id: NEXT_BATCH_ID.fetch_add(1, Ordering::Relaxed),
+            entries: vec![],
+        }
+    }
+}
+
+/// Parse the result of a call to `M` answered in the batch.
+fn parse<M>(result: RawResult) -> TypedResult
+where
+    M: methods::RpcMethod,
+    M::Response: Send + 'static,
+    M::Error: Send,
+{
+    let result: MethodCallResult<M::Response, M::Error> = result
+        .map_err(JsonRpcError::never_handler)
+        .and_then(crate::parse_result::<M>);
+    Box::new(result)
+}
+
+/// Send a call to `M` left unanswered by the batch on its own.
+fn fallback<'a, M>(
+    client: &'a JsonRpcClient,
+    method_name: &'a str,
+    payload: Value,
+) -> BoxFuture<'a, TypedResult>
+where
+    M: methods::RpcMethod,
+    M::Response: Send + 'static,
+    M::Error: Send,
+{
+    let call = CallInfo::new(method_name, None);
+    let options = client.resolve_options(method_name, CallOptions::default());
+    client
+        .call_traced(call, move |mut call| async move {
+            call.set_params(payload["params"].clone());
+            let result = client
+                .call_raw(
+                    &mut call,
+                    client.max_attempts(method_name),
+                    payload,
+                    &options,
+                )
+                .await
+                .and_then(crate::parse_result::<M>);
+            (call, result)
+        })
+        .map(|result| Box::new(result) as TypedResult)
+        .boxed()
+}
+
+impl BatchRequest {
+    /// Add a method call to the batch.
+    pub fn add<M>(&mut self, method: M) -> BatchHandle<M>
+    where
+        M: methods::RpcMethod,
+        M::Response: Send + 'static,
+        M::Error: Send,
+    {
+        let index = self.entries.len();
+
+        let (payload, result) = match methods::to_json(&method) {
+            Ok(mut payload) => {
+                payload["id"] = Value::from(index.to_string());
+                (Some(payload), None)
+            }
+            Err(err) => (
+                None,
+                Some(Err(JsonRpcError::TransportError(
+                    RpcTransportError::SendError(JsonRpcTransportSendError::PayloadSerializeError(
+                        err,
+                    )),
+                ))),
+            ),
+        };
+
+        self.entries.push(Entry {
+            method_name: method.method_name().to_string(),
+            payload,
+            result,
+            parse: parse::<M>,
+            fallback: fallback::<M>,
+        });
+
+        BatchHandle {
+            batch_id: self.batch_id,
+            index,
+            _method: PhantomData,
+        }
+    }
+
+    /// The number of method calls in the batch.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether or not the batch is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Send the batch to the server.
+    ///
+    /// This only fails if the batch as a whole couldn't be delivered,
+    /// errors specific to a single call are returned by [`BatchResponse::take`].
+    ///
+    /// Middleware sees the batch as a single call to `batch`, followed by
+    /// a call of its own for every method call sent individually.
+    pub async fn send(self) -> Result<BatchResponse, JsonRpcError<Infallible>> {
+        let client = &self.client;
+        let mut entries = self.entries;
+
+        let payloads = entries
+            .iter()
+            .filter_map(|entry| entry.payload.clone())
+            .collect::<Vec<_>>();
+
+        if !payloads.is_empty() {
+            // a batch is only as retryable as its least retryable call
+            let max_attempts = entries
+                .iter()
+                .map(|entry| client.max_attempts(&entry.method_name))
+                .min()
+                .unwrap_or(1);
+
+            // and takes as long as its slowest call
+            let options = CallOptions {
+                timeout: entries
+                    .iter()
+                    .map(|entry| client.timeout_for(&entry.method_name))
+                    .collect::<Option<Vec<_>>>()
+                    .and_then(|timeouts| timeouts.into_iter().max()),
+                ..Default::default()
+            };
+
+            let answered = &mut entries;
+            let delivered = client
+                .call_traced(CallInfo::new("batch", None), |mut call| async move {
+                    call.set_params(Value::Array(payloads.clone()));
+                    let result = deliver(
+                        client,
+                        &mut call,
+                        max_attempts,
+                        payloads,
+                        &options,
+                        answered,
+                    )
+                    .await;
+                    (call, result)
+                })
+                .await?;
+
+            if delivered {
+                for entry in entries.iter_mut() {
+                    if entry.result.is_none()
+                        && NON_IDEMPOTENT_METHODS.contains(&entry.method_name.as_str())
+                    {
+                        entry.result.replace(Err(JsonRpcError::TransportError(
+                            RpcTransportError::RecvError(
+                                JsonRpcTransportRecvError::UnansweredBatchCall(
+                                    entry.method_name.clone(),
+                                ),
+                            ),
+                        )));
+                    }
+                }
+            }
+        }
+
+        let results = futures::future::join_all(entries.into_iter().map(|entry| async move {
+            match entry.result {
+                Some(result) => (entry.parse)(result),
+                None => {
+                    let payload = entry.payload.expect("unanswered entries were serialized");
+                    (entry.fallback)(client, &entry.method_name, payload).await
+                }
+            }
+        }))
+        .await;
+
+        Ok(BatchResponse {
+            batch_id: self.batch_id,
+            results: results.into_iter().map(Some).collect(),
+        })
+    }
+}
+
+/// Send the batch, filling in the results of the calls answered by the server.
+///
+/// Returns whether or not the server ran the batch, if only partially.
+async fn deliver(
+    client: &JsonRpcClient,
+    call: &mut CallInfo,
+    max_attempts: u32,
+    payloads: Vec<Value>,
+    options: &CallOptions,
+    entries: &mut [Entry],
+) -> Result<bool, JsonRpcError<Infallible>> {
+    let response = client
+        .send_payload::<Infallible>(call, max_attempts, Value::Array(payloads), options)
+        .await?;
+
+    match response.status {
+        reqwest::StatusCode::OK => {
+            if let Ok(Value::Array(messages)) = serde_json::from_slice::<Value>(&response.body) {
+                for message in messages {
+                    if let Ok(Message::Response(response)) =
+                        near_jsonrpc_primitives::message::decoded_to_parsed(serde_json::from_value(
+                            message,
+                        ))
+                    {
+                        let index = response.id.as_str().and_then(|id| id.parse().ok());
+                        if let Some(Entry {
+                            result: slot @ None,
+                            ..
+                        }) = index.and_then(|index: usize| entries.get_mut(index))
+                        {
+                            slot.replace(Ok(response.result));
+                        }
+                    }
+                }
+                return Ok(true);
+            }
+            log::debug!("server rejected the batch, falling back to single calls");
+        }
+        // some servers reject batches outright
+        reqwest::StatusCode::BAD_REQUEST => {
+            log::debug!("server rejected the batch, falling back to single calls");
+        }
+        _ => {
+            if let Some(err) = crate::status_error(&response) {
+                return Err(JsonRpcError::ServerError(err));
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+impl BatchResponse {
+    /// Take the result of a method call in the batch.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the handle belongs to another batch.
+    #[allow(clippy::result_large_err)]
+    pub fn take<M: methods::RpcMethod>(
+        &mut self,
+        handle: BatchHandle<M>,
+    ) -> MethodCallResult<M::Response, M::Error>
+    where
+        M::Response: 'static,
+    {
+        assert_eq!(
+            handle.batch_id, self.batch_id,
+            "batch handle used with the wrong batch response"
+        );
+
+        let result = self.results[handle.index]
+            .take()
+            .expect("every batch entry has a result, and handles can't be reused");
+
+        *result
+            .downcast()
+            .expect("batch handles are typed by the method they were added with")
+    }
+
+    /// The number of method calls in the batch.
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    /// Whether or not the batch is empty.
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use reqwest::StatusCode;
+    use serde_json::json;
+
+    use super::*;
+    use crate::transport::{Transport, TransportFuture, TransportRequest, TransportResponse};
+
+    /// Answers batches in reverse order, or rejects them, answering single calls instead.
+    #[derive(Debug, Default)]
+    struct Echo {
+        reject_batches: bool,
+        /// A method left unanswered in batches.
+        unanswered: Option<&'static str>,
+        requests: Mutex<Vec<Value>>,
+    }
+
+    fn answer(request: &Value) -> Value {
+        match request["method"].as_str() {
+            Some("health") => json!({ "jsonrpc": "2.0", "id": request["id"], "result": null }),
+            _ => json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "error": {
+                    "code": -32601,
+                    "message": "Method not found",
+                    "name": "REQUEST_VALIDATION_ERROR",
+                    "cause": { "name": "METHOD_NOT_FOUND", "info": { "method_name": request["method"] } },
+                },
+            }),
+        }
+    }
+
+    impl Transport for Echo {
+        fn send(&self, request: TransportRequest) -> TransportFuture<'_> {
+            let payload = serde_json::from_slice::<Value>(&request.body).unwrap();
+            self.requests.lock().unwrap().push(payload.clone());
+            let response = match payload {
+                Value::Array(_) if self.reject_batches => {
+                    TransportResponse::new(StatusCode::BAD_REQUEST, vec![])
+                }
+                Value::Array(requests) => TransportResponse::new(
+                    StatusCode::OK,
+                    serde_json::to_vec(
+                        &requests
+                            .iter()
+                            .rev()
+                            .filter(|request| request["method"].as_str() != self.unanswered)
+                            .map(answer)
+                            .collect::<Vec<_>>(),
+                    )
+                    .unwrap(),
+                ),
+                request => {
+                    TransportResponse::new(StatusCode::OK, answer(&request).to_string().into())
+                }
+            };
+            Box::pin(async move { Ok(response) })
+        }
+    }
+
+    async fn roundtrip(transport: Arc<Echo>) {
+        let client = JsonRpcClient::with_transport(transport).connect("http://localhost:3030");
+
+        let mut batch = client.batch();
+        let health = batch.add(methods::health::RpcHealthRequest);
+        let status = batch.add(methods::status::RpcStatusRequest);
+        assert_eq!(batch.len(), 2);
+
+        let mut responses = batch.send().await.expect("batch must be delivered");
+
+        assert!(matches!(
+            responses.take(health),
+            Ok(methods::health::RpcHealthResponse)
+        ));
+        assert!(matches!(
+            responses.take(status),
+            Err(JsonRpcError::ServerError(
+                JsonRpcServerError::RequestValidationError(_)
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn matches_results_by_id() {
+        let transport = Arc::new(Echo::default());
+        roundtrip(transport.clone()).await;
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0][0]["id"], "0");
+        assert_eq!(requests[0][1]["id"], "1");
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_single_calls() {
+        let transport = Arc::new(Echo {
+            reject_batches: true,
+            ..Default::default()
+        });
+        roundtrip(transport.clone()).await;
+
+        assert_eq!(transport.requests.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn never_resends_non_idempotent_calls() {
+        use near_crypto::{KeyType, SecretKey, Signature};
+        use near_primitives::transaction::{SignedTransaction, Transaction, TransactionV0};
+
+        let transport = Arc::new(Echo {
+            unanswered: Some("broadcast_tx_async"),
+            ..Default::default()
+        });
+        let client =
+            JsonRpcClient::with_transport(transport.clone()).connect("http://localhost:3030");
+
+        let transaction = Transaction::V0(TransactionV0 {
+            signer_id: "alice.near".parse().unwrap(),
+            public_key: SecretKey::from_seed(KeyType::ED25519, "alice").public_key(),
+            nonce: 1,
+            receiver_id: "bob.near".parse().unwrap(),
+            block_hash: Default::default(),
+            actions: vec![],
+        });
+
+        let mut batch = client.batch();
+        let health = batch.add(methods::health::RpcHealthRequest);
+        let broadcast = batch.add(methods::broadcast_tx_async::RpcBroadcastTxAsyncRequest {
+            signed_transaction: SignedTransaction::new(
+                Signature::empty(KeyType::ED25519),
+                transaction,
+            ),
+        });
+
+        let mut responses = batch.send().await.expect("batch must be delivered");
+
+        assert!(responses.take(health).is_ok());
+        assert!(matches!(
+            responses.take(broadcast),
+            Err(JsonRpcError::TransportError(RpcTransportError::RecvError(
+                JsonRpcTransportRecvError::UnansweredBatchCall(method_name)
+            ))) if method_name == "broadcast_tx_async"
+        ));
+        assert_eq!(transport.requests.lock().unwrap().len(), 1);
+    }
+}
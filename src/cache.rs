@@ -0,0 +1,373 @@
+//! Caching responses that can't change.
+//!
+//! A [`CachingTransport`] wraps another transport and keeps the results of requests that always return
+//! the same thing, so they're only fetched once:
+//!
+//! - any request pinned to a block hash, like a `block` or `query` at `BlockId::Hash(..)`
+//! - `chunk` by chunk hash, `EXPERIMENTAL_receipt` and `EXPERIMENTAL_genesis_config`
+//! - `tx` and `EXPERIMENTAL_tx_status`, once the transaction reached the `FINAL` status
+//!
+//! Requests made at a [`Finality`](near_primitives::types::Finality), or by height, aren't cached,
+//! unless [`CachingTransport::with_finality_ttl`] is used to keep them for a short while. Errors and
+//! batches are never cached.
+//!
+//! Results are kept per server, in memory, evicting the least recently used ones past the capacity,
+//! and optionally on disk with [`CachingTransport::with_disk_cache`].
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//!
+//! use near_jsonrpc_client::{cache::CachingTransport, methods, JsonRpcClient};
+//! use near_primitives::types::{BlockId, BlockReference};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+//! let cache = Arc::new(CachingTransport::new(reqwest::Client::new(), 1024));
+//! let client = JsonRpcClient::with_transport(cache.clone()).connect("https://rpc.mainnet.near.org");
+//!
+//! let request = methods::block::RpcBlockRequest {
+//!     block_reference: BlockReference::BlockId(BlockId::Hash(
+//!         "6RV4ibLSuEXVnjJjZwkt1fQt1n2XgMtDy3vh9gsCeeEf".parse()?,
+//!     )),
+//! };
+//!
+//! let block = client.call(&request).await?;
+//! let cached = client.call(&request).await?;
+//!
+//! assert_eq!(cache.stats().hits, 1);
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use near_primitives::hash::hash;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use crate::transport::{Transport, TransportFuture, TransportRequest, TransportResponse};
+
+/// Cache hit and miss counters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Requests answered from the cache.
+    pub hits: u64,
+    /// Cacheable requests that had to be sent.
+    pub misses: u64,
+}
+
+/// A transport that caches the results of requests that can't change.
+///
+/// See the [`cache`](self) module documentation for more information.
+#[derive(Debug)]
+pub struct CachingTransport<T> {
+    inner: T,
+    capacity: usize,
+    disk: Option<PathBuf>,
+    finality_ttl: Option<Duration>,
+    entries: Mutex<Lru>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct Lru {
+    entries: HashMap<String, Entry>,
+    /// Keys by the tick they were last used at.
+    order: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    result: Value,
+    expires_at: Option<Instant>,
+    used_at: u64,
+}
+
+/// How long a result can be kept for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lifetime {
+    Forever,
+    Until(Instant),
+}
+
+impl Lru {
+    fn get(&mut self, key: &str) -> Option<Value> {
+        let entry = self.entries.get_mut(key)?;
+        if matches!(entry.expires_at, Some(expires_at) if expires_at <= Instant::now()) {
+            let used_at = entry.used_at;
+            self.entries.remove(key);
+            self.order.remove(&used_at);
+            return None;
+        }
+
+        self.tick += 1;
+        self.order.remove(&entry.used_at);
+        self.order.insert(self.tick, key.to_string());
+        entry.used_at = self.tick;
+        Some(entry.result.clone())
+    }
+
+    fn insert(&mut self, key: String, result: Value, lifetime: Lifetime, capacity: usize) {
+        self.tick += 1;
+        let entry = Entry {
+            result,
+            expires_at: match lifetime {
+                Lifetime::Forever => None,
+                Lifetime::Until(expires_at) => Some(expires_at),
+            },
+            used_at: self.tick,
+        };
+        if let Some(previous) = self.entries.insert(key.clone(), entry) {
+            self.order.remove(&previous.used_at);
+        }
+        self.order.insert(self.tick, key);
+
+        while self.entries.len() > capacity {
+            let Some((_, key)) = self.order.pop_first() else {
+                break;
+            };
+            self.entries.remove(&key);
+        }
+    }
+}
+
+impl<T: Transport> CachingTransport<T> {
+    /// Cache up to `capacity` results in memory.
+    pub fn new(inner: T, capacity: usize) -> Self {
+        Self {
+            inner,
+            capacity,
+            disk: None,
+            finality_ttl: None,
+            entries: Mutex::new(Lru::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Also keep results in the `dir` directory, so they survive restarts.
+    ///
+    /// Results cached for a limited time are only kept in memory.
+    pub fn with_disk_cache(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.disk = Some(dir.into());
+        self
+    }
+
+    /// Cache the results of requests made at a finality, or by height, for `ttl`.
+    pub fn with_finality_ttl(mut self, ttl: Duration) -> Self {
+        self.finality_ttl = Some(ttl);
+        self
+    }
+
+    /// The hit and miss counters.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Drop every result cached in memory.
+    pub fn clear(&self) {
+        *self.entries.lock().unwrap() = Lru::default();
+    }
+
+    /// The cache key and lifetime of a request to `server_addr`, `None` if it can't be cached.
+    fn cacheable(&self, server_addr: &str, payload: &Value) -> Option<(String, Lifetime)> {
+        let method = payload["method"].as_str()?;
+        let params = &payload["params"];
+
+        let lifetime = match method {
+            "EXPERIMENTAL_genesis_config" | "EXPERIMENTAL_receipt" => Lifetime::Forever,
+            // final statuses are checked on the response
+            "tx" | "EXPERIMENTAL_tx_status" => Lifetime::Forever,
+            "chunk" if params["chunk_id"].is_string() => Lifetime::Forever,
+            "send_tx" | "broadcast_tx_async" | "broadcast_tx_commit" => return None,
+            _ if params["block_id"].is_string() => Lifetime::Forever,
+            _ if params["block_id"].is_u64() || params["finality"].is_string() => {
+                Lifetime::Until(Instant::now() + self.finality_ttl?)
+            }
+            _ => return None,
+        };
+
+        Some((json!([server_addr, method, params]).to_string(), lifetime))
+    }
+
+    fn disk_path(&self, key: &str) -> Option<PathBuf> {
+        let dir = self.disk.as_ref()?;
+        Some(dir.join(hash(key.as_bytes()).to_string()))
+    }
+
+    fn lookup(&self, key: &str) -> Option<Value> {
+        if let Some(result) = self.entries.lock().unwrap().get(key) {
+            return Some(result);
+        }
+
+        let path = self.disk_path(key)?;
+        let result = serde_json::from_slice::<Value>(&std::fs::read(path).ok()?).ok()?;
+        self.entries.lock().unwrap().insert(
+            key.to_string(),
+            result.clone(),
+            Lifetime::Forever,
+            self.capacity,
+        );
+        Some(result)
+    }
+
+    fn store(&self, key: String, result: Value, lifetime: Lifetime) {
+        if lifetime == Lifetime::Forever {
+            if let Some(path) = self.disk_path(&key) {
+                let written = std::fs::create_dir_all(path.parent().unwrap())
+                    .and_then(|_| std::fs::write(&path, result.to_string()));
+                if let Err(err) = written {
+                    log::warn!(
+                        "failed to write cached result to [{}]: {}",
+                        path.display(),
+                        err
+                    );
+                }
+            }
+        }
+
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key, result, lifetime, self.capacity);
+    }
+}
+
+impl<T: Transport> Transport for CachingTransport<T> {
+    fn send(&self, request: TransportRequest) -> TransportFuture<'_> {
+        Box::pin(async move {
+            let payload = serde_json::from_slice::<Value>(&request.body).unwrap_or_default();
+            let Some((key, lifetime)) = self.cacheable(&request.server_addr, &payload) else {
+                return self.inner.send(request).await;
+            };
+
+            if let Some(result) = self.lookup(&key) {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                let message = json!({ "jsonrpc": "2.0", "id": payload["id"], "result": result });
+                return Ok(TransportResponse::new(
+                    StatusCode::OK,
+                    message.to_string().into(),
+                ));
+            }
+            self.misses.fetch_add(1, Ordering::Relaxed);
+
+            let response = self.inner.send(request).await?;
+            if response.status == StatusCode::OK {
+                if let Ok(mut message) = serde_json::from_slice::<Value>(&response.body) {
+                    let result = message["result"].take();
+                    let pending = matches!(
+                        payload["method"].as_str(),
+                        Some("tx" | "EXPERIMENTAL_tx_status")
+                    ) && result["final_execution_status"] != "FINAL";
+                    if !result.is_null() && message["error"].is_null() && !pending {
+                        self.store(key, result, lifetime);
+                    }
+                }
+            }
+
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::types::{BlockId, BlockReference, Finality};
+
+    use super::*;
+    use crate::testing::MockTransport;
+    use crate::{methods, JsonRpcClient};
+
+    fn block(block_reference: BlockReference) -> methods::block::RpcBlockRequest {
+        methods::block::RpcBlockRequest { block_reference }
+    }
+
+    #[tokio::test]
+    async fn caches_immutable_results() {
+        let mock = MockTransport::new();
+        mock.on("block").respond(json!({ "header": {} }));
+        mock.on("tx")
+            .times(1)
+            .respond(json!({ "final_execution_status": "EXECUTED" }));
+        mock.on("tx")
+            .respond(json!({ "final_execution_status": "FINAL" }));
+
+        let cache = Arc::new(CachingTransport::new(mock.clone(), 16));
+        let client = JsonRpcClient::with_transport(cache.clone()).connect("http://localhost:3030");
+
+        let by_hash = block(BlockReference::BlockId(
+            BlockId::Hash(CryptoHash::default()),
+        ));
+        let by_finality = block(BlockReference::Finality(Finality::Final));
+        for request in [&by_hash, &by_hash, &by_finality, &by_finality] {
+            // the mocked block doesn't parse, that doesn't matter here
+            let _ = client.call(request).await;
+        }
+        assert_eq!(mock.requests().len(), 3);
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 1 });
+
+        mock.clear_requests();
+        let tx = methods::tx::RpcTransactionStatusRequest {
+            transaction_info: methods::tx::TransactionInfo::TransactionId {
+                tx_hash: CryptoHash::default(),
+                sender_account_id: "alice.near".parse().unwrap(),
+            },
+            wait_until: near_primitives::views::TxExecutionStatus::Final,
+        };
+        for _ in 0..3 {
+            let _ = client.call(&tx).await;
+        }
+        // only cached once final
+        assert_eq!(mock.requests().len(), 2);
+        assert_eq!(cache.stats(), CacheStats { hits: 2, misses: 3 });
+    }
+
+    #[tokio::test]
+    async fn caches_per_server() {
+        let mock = MockTransport::new();
+        mock.on("block").respond(json!({ "header": {} }));
+
+        let cache = Arc::new(CachingTransport::new(mock.clone(), 16));
+        let connector = JsonRpcClient::with_transport(cache.clone());
+        let mainnet = connector.connect("https://rpc.mainnet.near.org");
+        let testnet = connector.connect("https://rpc.testnet.near.org");
+
+        let request = block(BlockReference::BlockId(
+            BlockId::Hash(CryptoHash::default()),
+        ));
+        for client in [&mainnet, &testnet, &mainnet, &testnet] {
+            let _ = client.call(&request).await;
+        }
+        assert_eq!(mock.requests().len(), 2);
+        assert_eq!(cache.stats(), CacheStats { hits: 2, misses: 2 });
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut lru = Lru::default();
+        lru.insert("a".into(), json!(1), Lifetime::Forever, 2);
+        lru.insert("b".into(), json!(2), Lifetime::Forever, 2);
+        assert_eq!(lru.get("a"), Some(json!(1)));
+
+        lru.insert("c".into(), json!(3), Lifetime::Forever, 2);
+        assert_eq!(lru.get("b"), None);
+        assert_eq!(lru.get("a"), Some(json!(1)));
+        assert_eq!(lru.get("c"), Some(json!(3)));
+
+        lru.insert("d".into(), json!(4), Lifetime::Until(Instant::now()), 2);
+        assert_eq!(lru.get("d"), None);
+    }
+}
@@ -0,0 +1,281 @@
+//! Probing what an endpoint can serve.
+//!
+//! Providers serve different sets of methods, archival data or not, sandbox or adversarial methods
+//! or not. [`JsonRpcClient::capabilities`] finds out once, with:
+//!
+//! - a `status` call, for the chain, the protocol version and the earliest block available
+//! - an `EXPERIMENTAL_split_storage_info` call, to tell archival nodes apart
+//! - a probe for every method in [`PROBED_METHODS`], called with `null` params, which is rejected
+//!   before doing any work by every method expecting params
+//!
+//! A method is considered supported unless its probe fails with a `METHOD_NOT_FOUND` error, so a
+//! supported method may still reject the params of a real call. A probe failing for any other reason,
+//! like the server being unreachable, fails the whole call.
+//!
+//! The resulting [`Capabilities`] are cached for the lifetime of the client, and shared by its clones.
+//! A failover or routing layer can use them to pick endpoints. Failures aren't cached, the next call
+//! probes again.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use near_jsonrpc_client::JsonRpcClient;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = JsonRpcClient::connect("https://archival-rpc.mainnet.near.org");
+//!
+//! let capabilities = client.capabilities().await?;
+//! if capabilities.archival && capabilities.supports("EXPERIMENTAL_changes") {
+//!     println!(
+//!         "archival node on {}, serving blocks from #{:?}",
+//!         capabilities.chain_id, capabilities.earliest_block_height
+//!     );
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::BTreeSet;
+use std::convert::Infallible;
+
+use futures::{StreamExt, TryStreamExt};
+use near_jsonrpc_primitives::errors::{RpcError, RpcErrorKind, RpcRequestValidationErrorKind};
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{BlockHeight, ProtocolVersion};
+use serde_json::{json, Value};
+
+use crate::errors::JsonRpcError;
+use crate::middleware::CallInfo;
+use crate::options::CallOptions;
+use crate::{methods, JsonRpcClient, MethodCallResult};
+
+/// The methods probed by [`JsonRpcClient::capabilities`].
+///
+/// Adversarial methods are only probed with `adv_get_saved_blocks`, the others have side effects.
+pub const PROBED_METHODS: &[&str] = &[
+    "block",
+    "broadcast_tx_async",
+    "broadcast_tx_commit",
+    "chunk",
+    "client_config",
+    "gas_price",
+    "health",
+    "light_client_proof",
+    "network_info",
+    "next_light_client_block",
+    "query",
+    "send_tx",
+    "tx",
+    "validators",
+    "EXPERIMENTAL_changes",
+    "EXPERIMENTAL_changes_in_block",
+    "EXPERIMENTAL_congestion_level",
+    "EXPERIMENTAL_genesis_config",
+    "EXPERIMENTAL_light_client_block_proof",
+    "EXPERIMENTAL_maintenance_windows",
+    "EXPERIMENTAL_protocol_config",
+    "EXPERIMENTAL_receipt",
+    "EXPERIMENTAL_tx_status",
+    "EXPERIMENTAL_validators_ordered",
+    "sandbox_patch_state",
+    "sandbox_fast_forward",
+    "adv_get_saved_blocks",
+];
+
+/// How many methods are probed at once.
+const CONCURRENT_PROBES: usize = 8;
+
+/// What an endpoint can serve.
+///
+/// See the [`capabilities`](self) module documentation for more information.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// The chain the node is on.
+    pub chain_id: String,
+    /// The protocol version of the chain, when the endpoint was probed.
+    pub protocol_version: ProtocolVersion,
+    /// The latest protocol version the node supports.
+    pub latest_protocol_version: ProtocolVersion,
+    /// Whether or not the node keeps the whole history of the chain.
+    pub archival: bool,
+    /// The height of the earliest block available, if the node reported it.
+    pub earliest_block_height: Option<BlockHeight>,
+    /// The hash of the earliest block available, if the node reported it.
+    pub earliest_block_hash: Option<CryptoHash>,
+    /// The methods the node serves.
+    pub methods: BTreeSet<String>,
+}
+
+impl Capabilities {
+    /// Whether or not the node serves the specified method.
+    pub fn supports(&self, method_name: &str) -> bool {
+        self.methods.contains(method_name)
+    }
+}
+
+impl JsonRpcClient {
+    /// Probe what the endpoint can serve, once.
+    ///
+    /// See the [`capabilities`](self) module documentation for more information.
+    pub async fn capabilities(
+        &self,
+    ) -> MethodCallResult<Capabilities, methods::status::RpcStatusError> {
+        self.inner
+            .capabilities
+            .get_or_try_init(|| self.probe_capabilities())
+            .await
+            .cloned()
+    }
+
+    async fn probe_capabilities(
+        &self,
+    ) -> MethodCallResult<Capabilities, methods::status::RpcStatusError> {
+        let status = self.call(methods::status::RpcStatusRequest).await?;
+
+        let split_storage = self
+            .call(methods::EXPERIMENTAL_split_storage_info::RpcSplitStorageInfoRequest {})
+            .await;
+        let archival = match split_storage {
+            Ok(info) => {
+                info.result.cold_head_height.is_some()
+                    || info.result.hot_db_kind.as_deref() == Some("Archive")
+            }
+            Err(_) => false,
+        } || status.sync_info.earliest_block_hash == Some(status.genesis_hash);
+
+        let mut methods = futures::stream::iter(PROBED_METHODS)
+            .map(|method_name| async move {
+                let supported = self.probe_method(method_name).await?;
+                Ok::<_, JsonRpcError<Infallible>>(supported.then(|| method_name.to_string()))
+            })
+            .buffer_unordered(CONCURRENT_PROBES)
+            .try_filter_map(|method_name| async move { Ok(method_name) })
+            .try_collect::<BTreeSet<_>>()
+            .await
+            .map_err(JsonRpcError::never_handler)?;
+        methods.insert("status".to_string());
+        methods.insert("EXPERIMENTAL_split_storage_info".to_string());
+
+        Ok(Capabilities {
+            chain_id: status.chain_id,
+            protocol_version: status.protocol_version,
+            latest_protocol_version: status.latest_protocol_version,
+            archival,
+            earliest_block_height: status.sync_info.earliest_block_height,
+            earliest_block_hash: status.sync_info.earliest_block_hash,
+            methods,
+        })
+    }
+
+    /// Whether or not the method exists, as far as the server lets on.
+    async fn probe_method(&self, method_name: &str) -> Result<bool, JsonRpcError<Infallible>> {
+        let payload = json!(near_jsonrpc_primitives::message::Message::request(
+            method_name.to_string(),
+            Value::Null,
+        ));
+        let mut call = CallInfo::new(method_name, Some(Value::Null));
+        match self
+            .call_raw(&mut call, 1, payload, &CallOptions::default())
+            .await?
+        {
+            Ok(_) => Ok(true),
+            Err(err) => Ok(!is_method_not_found(&err)),
+        }
+    }
+}
+
+fn is_method_not_found(err: &RpcError) -> bool {
+    matches!(
+        err.error_struct,
+        Some(RpcErrorKind::RequestValidationError(
+            RpcRequestValidationErrorKind::MethodNotFound { .. }
+        ))
+    ) || err.code == -32601
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockTransport;
+
+    fn status() -> Value {
+        let hash = CryptoHash::default().to_string();
+        json!({
+            "version": { "version": "2.4.0", "build": "2.4.0" },
+            "chain_id": "testnet",
+            "protocol_version": 73,
+            "latest_protocol_version": 74,
+            "rpc_addr": "0.0.0.0:3030",
+            "validators": [],
+            "sync_info": {
+                "latest_block_hash": hash,
+                "latest_block_height": 1000,
+                "latest_state_root": hash,
+                "latest_block_time": "2024-01-01T00:00:00Z",
+                "syncing": false,
+                "earliest_block_hash": hash,
+                "earliest_block_height": 900,
+                "earliest_block_time": "2024-01-01T00:00:00Z",
+            },
+            "validator_account_id": null,
+            "node_public_key": "ed25519:6DSjZ8mvsRZDvFqFxo8tCKePG96omXW7eVYVSySmDk8e",
+            "uptime_sec": 1,
+            "genesis_hash": "6RV4ibLSuEXVnjJjZwkt1fQt1n2XgMtDy3vh9gsCeeEf",
+        })
+    }
+
+    #[tokio::test]
+    async fn probes_once() {
+        let mock = MockTransport::new();
+        mock.on("status").respond(status());
+        mock.on("EXPERIMENTAL_split_storage_info").respond(json!({
+            "head_height": 1000,
+            "final_head_height": 998,
+            "cold_head_height": 990,
+            "hot_db_kind": "Hot",
+        }));
+        mock.on("block").respond_error(json!({
+            "code": -32602,
+            "message": "Invalid params",
+            "name": "REQUEST_VALIDATION_ERROR",
+            "cause": { "name": "PARSE_ERROR", "info": { "error_message": "invalid type: null" } },
+        }));
+        mock.on("health").respond(());
+
+        let client = mock.client();
+        let capabilities = client.capabilities().await.unwrap();
+        assert_eq!(capabilities.chain_id, "testnet");
+        assert_eq!(capabilities.protocol_version, 73);
+        assert!(capabilities.archival);
+        assert_eq!(capabilities.earliest_block_height, Some(900));
+        assert!(capabilities.supports("block") && capabilities.supports("health"));
+        assert!(!capabilities.supports("sandbox_patch_state"));
+
+        let requests = mock.requests().len();
+        assert_eq!(client.clone().capabilities().await.unwrap(), capabilities);
+        assert_eq!(mock.requests().len(), requests);
+    }
+
+    #[tokio::test]
+    async fn retries_failed_probes() {
+        let mock = MockTransport::new();
+        mock.on("status").respond(status());
+        mock.on("health")
+            .times(1)
+            .respond_status(reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        mock.on("health").respond(());
+
+        let client = mock.client();
+        assert!(matches!(
+            client.capabilities().await,
+            Err(JsonRpcError::ServerError(
+                crate::errors::JsonRpcServerError::ResponseStatusError(
+                    crate::errors::JsonRpcServerResponseStatusError::ServiceUnavailable
+                )
+            ))
+        ));
+
+        let capabilities = client.capabilities().await.unwrap();
+        assert!(capabilities.supports("health"));
+    }
+}
@@ -1,13 +1,18 @@
 //! Error types.
 use std::io;
+use std::time::Duration;
 
 use thiserror::Error;
 
 use near_jsonrpc_primitives::errors::{RpcError, RpcErrorKind, RpcRequestValidationErrorKind};
 use near_jsonrpc_primitives::message::{self, Message};
+use near_jsonrpc_primitives::types::query::QueryResponseKind;
+
+use crate::methods::RpcHandlerError;
 
 /// Potential errors returned while sending a request to the RPC server.
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum JsonRpcTransportSendError {
     /// Client is unable to serialize the request payload before sending it to the server.
     #[error("error while serializing payload: [{0}]")]
@@ -15,10 +20,14 @@ pub enum JsonRpcTransportSendError {
     /// Client is unable to send the request to the server.
     #[error("error while sending payload: [{0}]")]
     PayloadSendError(reqwest::Error),
+    /// A custom [`Transport`](crate::transport::Transport) is unable to send the request to the server.
+    #[error("error while sending payload: [{0}]")]
+    TransportSendError(Box<dyn std::error::Error + Send + Sync>),
 }
 
 /// Potential errors returned when the client has an issue parsing the response of a method call.
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum JsonRpcTransportHandlerResponseError {
     /// Client fails to deserialize the result of a method call.
     #[error("error while parsing method call result: [{0}]")]
@@ -26,10 +35,20 @@ pub enum JsonRpcTransportHandlerResponseError {
     /// Client fails to deserialize the error message returned from a method call.
     #[error("error while parsing method call error message: [{0}]")]
     ErrorMessageParseError(serde_json::Error),
+    /// A typed query, like [`ViewAccountRequest`](crate::methods::query::ViewAccountRequest), returns
+    /// a view of another kind.
+    #[error("expected a `{expected}` query response, found [{received:?}]")]
+    UnexpectedQueryKind {
+        /// The name of the query kind that was requested.
+        expected: &'static str,
+        /// The kind of view the server returned.
+        received: Box<QueryResponseKind>,
+    },
 }
 
 /// Potential errors returned while receiving responses from an RPC server.
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum JsonRpcTransportRecvError {
     /// Client receives a JSON RPC message body that isn't structured as a response.
     #[error("unexpected server response: [{0:?}]")]
@@ -37,16 +56,34 @@ pub enum JsonRpcTransportRecvError {
     /// Client is unable to read the response from the RPC server.
     #[error("error while reading response: [{0}]")]
     PayloadRecvError(reqwest::Error),
+    /// A custom [`Transport`](crate::transport::Transport) is unable to read the response from the server.
+    #[error("error while reading response: [{0}]")]
+    TransportRecvError(Box<dyn std::error::Error + Send + Sync>),
     /// The base response structure is malformed e.g. meta properties like RPC version are missing.
     #[error("error while parsing server response: [{0:?}]")]
     PayloadParseError(message::Broken),
     /// Potential errors returned when the client has an issue parsing the response of a method call.
     #[error(transparent)]
     ResponseParseError(JsonRpcTransportHandlerResponseError),
+    /// The server left a non-idempotent call in a batch unanswered, so it may or may not have run.
+    #[error("the server left a call to `{0}` in the batch unanswered, it's unsafe to send again")]
+    UnansweredBatchCall(String),
+}
+
+/// Potential errors returned when a method call doesn't complete in time.
+#[derive(Debug, Error)]
+pub enum JsonRpcTimeoutError {
+    /// An attempt at sending the request got no response within the timeout.
+    #[error("no response within the timeout of [{0:?}]")]
+    AttemptTimeout(Duration),
+    /// The deadline of the method call passed before it completed.
+    #[error("the deadline of the method call passed")]
+    DeadlineExceeded,
 }
 
 /// Potential errors returned while sending requests to or receiving responses from the RPC server.
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum RpcTransportError {
     /// Potential errors returned while sending a request to the RPC server.
     #[error(transparent)]
@@ -54,6 +91,22 @@ pub enum RpcTransportError {
     /// Potential errors returned while receiving a response from an RPC server.
     #[error(transparent)]
     RecvError(JsonRpcTransportRecvError),
+    /// The method call didn't complete in time, see [`CallOptions`](crate::options::CallOptions).
+    #[error(transparent)]
+    TimeoutError(JsonRpcTimeoutError),
+}
+
+impl RpcTransportError {
+    /// Whether or not the request may go through if sent again.
+    pub(crate) fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::SendError(
+                JsonRpcTransportSendError::PayloadSendError(_)
+                    | JsonRpcTransportSendError::TransportSendError(_)
+            ) | Self::TimeoutError(JsonRpcTimeoutError::AttemptTimeout(_))
+        )
+    }
 }
 
 /// Unexpected status codes returned by the RPC server.
@@ -64,7 +117,10 @@ pub enum JsonRpcServerResponseStatusError {
     Unauthorized,
     /// The RPC client exceeds the rate limit by sending too many requests.
     #[error("this client has exceeded the rate limit")]
-    TooManyRequests,
+    TooManyRequests {
+        /// How long the server asked to wait before trying again, if it did.
+        retry_after: Option<Duration>,
+    },
     #[error("the server returned status code 400 - bad request")]
     BadRequest,
     #[error("the request failed with timeout error")]
@@ -76,6 +132,16 @@ pub enum JsonRpcServerResponseStatusError {
     Unexpected { status: reqwest::StatusCode },
 }
 
+impl JsonRpcServerResponseStatusError {
+    /// Whether or not the server may accept the request later.
+    pub(crate) fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::TooManyRequests { .. } | Self::ServiceUnavailable | Self::TimeoutError
+        )
+    }
+}
+
 /// Potential errors returned by the RPC server.
 #[derive(Debug, Error)]
 pub enum JsonRpcServerError<E> {
@@ -114,6 +180,135 @@ impl<E> JsonRpcError<E> {
         }
         None
     }
+
+    /// Whether or not the request couldn't be sent, or its response received, see [`RpcTransportError`].
+    pub fn is_transport(&self) -> bool {
+        matches!(self, Self::TransportError(_))
+    }
+
+    /// Whether or not the call didn't complete in time, see [`JsonRpcTimeoutError`].
+    pub fn is_timeout(&self) -> bool {
+        matches!(
+            self,
+            Self::TransportError(RpcTransportError::TimeoutError(_))
+        )
+    }
+
+    /// Whether or not the server responded with `429 Too Many Requests`.
+    pub fn is_rate_limited(&self) -> bool {
+        matches!(
+            self,
+            Self::ServerError(JsonRpcServerError::ResponseStatusError(
+                JsonRpcServerResponseStatusError::TooManyRequests { .. }
+            ))
+        )
+    }
+
+    /// How long the server asked to wait before trying again, if it did.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::ServerError(JsonRpcServerError::ResponseStatusError(
+                JsonRpcServerResponseStatusError::TooManyRequests { retry_after },
+            )) => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// Which part of the error hierarchy the error belongs to.
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            Self::TransportError(RpcTransportError::SendError(_)) => ErrorClass::TransportSend,
+            Self::TransportError(RpcTransportError::RecvError(_)) => ErrorClass::TransportRecv,
+            Self::TransportError(RpcTransportError::TimeoutError(_)) => ErrorClass::Timeout,
+            Self::ServerError(JsonRpcServerError::HandlerError(_)) => ErrorClass::Handler,
+            Self::ServerError(JsonRpcServerError::ResponseStatusError(_)) => ErrorClass::Status,
+            Self::ServerError(_) => ErrorClass::Server,
+        }
+    }
+}
+
+impl<E: RpcHandlerError> JsonRpcError<E> {
+    /// Whether or not the same call may succeed if tried again later.
+    ///
+    /// That's the case when the request couldn't be sent or an attempt timed out, when the server is
+    /// rate limiting or unavailable, and for transient handler errors, see [`RpcHandlerError::is_retryable`].
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::TransportError(err) => err.is_retryable(),
+            Self::ServerError(JsonRpcServerError::ResponseStatusError(status)) => {
+                status.is_retryable()
+            }
+            Self::ServerError(JsonRpcServerError::HandlerError(err)) => err.is_retryable(),
+            _ => false,
+        }
+    }
+
+    /// Whether or not the block referenced isn't known to the server, see [`RpcHandlerError::is_unknown_block`].
+    pub fn is_unknown_block(&self) -> bool {
+        self.handler_error()
+            .map_or(false, RpcHandlerError::is_unknown_block)
+    }
+
+    /// Whether or not what was requested doesn't exist, see [`RpcHandlerError::is_not_found`].
+    pub fn is_not_found(&self) -> bool {
+        self.handler_error()
+            .map_or(false, RpcHandlerError::is_not_found)
+    }
+}
+
+/// A coarse classification of [`JsonRpcError`]s, following their hierarchy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ErrorClass {
+    /// The request couldn't be sent, see [`JsonRpcTransportSendError`].
+    TransportSend,
+    /// The response couldn't be received or parsed, see [`JsonRpcTransportRecvError`].
+    TransportRecv,
+    /// The call didn't complete in time, see [`JsonRpcTimeoutError`].
+    Timeout,
+    /// The method failed, see [`JsonRpcServerError::HandlerError`].
+    Handler,
+    /// The server responded with an unexpected status code, see [`JsonRpcServerResponseStatusError`].
+    Status,
+    /// The server rejected the request or failed internally.
+    Server,
+}
+
+impl ErrorClass {
+    /// A short, stable name for the class, suitable as a metric label.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::TransportSend => "transport_send",
+            Self::TransportRecv => "transport_recv",
+            Self::Timeout => "timeout",
+            Self::Handler => "handler",
+            Self::Status => "status",
+            Self::Server => "server",
+        }
+    }
+}
+
+impl JsonRpcError<std::convert::Infallible> {
+    /// Widen an error that can't possibly be a handler error.
+    pub(crate) fn never_handler<E>(self) -> JsonRpcError<E> {
+        match self {
+            Self::TransportError(err) => JsonRpcError::TransportError(err),
+            Self::ServerError(err) => JsonRpcError::ServerError(match err {
+                JsonRpcServerError::RequestValidationError(err) => {
+                    JsonRpcServerError::RequestValidationError(err)
+                }
+                JsonRpcServerError::HandlerError(never) => match never {},
+                JsonRpcServerError::InternalError { info } => {
+                    JsonRpcServerError::InternalError { info }
+                }
+                JsonRpcServerError::NonContextualError(err) => {
+                    JsonRpcServerError::NonContextualError(err)
+                }
+                JsonRpcServerError::ResponseStatusError(err) => {
+                    JsonRpcServerError::ResponseStatusError(err)
+                }
+            }),
+        }
+    }
 }
 
 impl<E: super::methods::RpcHandlerError> From<RpcError> for JsonRpcError<E> {
@@ -167,3 +362,57 @@ impl<E: super::methods::RpcHandlerError> From<RpcError> for JsonRpcError<E> {
         JsonRpcError::ServerError(JsonRpcServerError::NonContextualError(err))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::types::{BlockReference, Finality};
+    use reqwest::StatusCode;
+    use serde_json::json;
+
+    use super::*;
+    use crate::methods;
+    use crate::testing::MockTransport;
+
+    #[tokio::test]
+    async fn classifies_errors() {
+        let mock = MockTransport::new();
+        mock.on("health")
+            .with_response_header(reqwest::header::RETRY_AFTER, "3")
+            .respond_status(StatusCode::TOO_MANY_REQUESTS);
+        let err = mock
+            .client()
+            .call(methods::health::RpcHealthRequest)
+            .await
+            .unwrap_err();
+        assert!(err.is_rate_limited() && err.is_retryable());
+        assert!(!err.is_transport());
+        assert_eq!(err.retry_after(), Some(Duration::from_secs(3)));
+
+        let mock = MockTransport::new();
+        mock.on("block").respond_error(json!({
+            "code": -32000,
+            "message": "Server error",
+            "name": "HANDLER_ERROR",
+            "cause": { "name": "UNKNOWN_BLOCK", "info": {} },
+        }));
+        let err = mock
+            .client()
+            .call(methods::block::RpcBlockRequest {
+                block_reference: BlockReference::Finality(Finality::Final),
+            })
+            .await
+            .unwrap_err();
+        assert!(err.is_unknown_block());
+        assert!(!err.is_not_found() && !err.is_retryable() && !err.is_rate_limited());
+        assert_eq!(err.class(), ErrorClass::Handler);
+
+        let err = JsonRpcError::<methods::query::RpcQueryError>::ServerError(
+            JsonRpcServerError::HandlerError(methods::query::RpcQueryError::UnknownAccount {
+                requested_account_id: "alice.near".parse().unwrap(),
+                block_height: 1,
+                block_hash: Default::default(),
+            }),
+        );
+        assert!(err.is_not_found() && !err.is_unknown_block());
+    }
+}
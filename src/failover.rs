@@ -0,0 +1,565 @@
+//! Multi-endpoint failover.
+//!
+//! A [`FailoverClient`] holds an ordered list of clients, each connected to a different RPC server.
+//! Every call is routed to the healthiest endpoint, preferring those listed first, and if that endpoint
+//! fails to serve the request, the next one is tried.
+//!
+//! An endpoint is marked unhealthy when it fails with a transport error, an internal server error
+//! or an unexpected status code. Unhealthy endpoints are only used as a last resort, until a
+//! health probe (see [`FailoverClient::probe`] and [`FailoverClient::spawn_health_checks`]) or
+//! a successful call marks them healthy again.
+//!
+//! Calls to [`NON_IDEMPOTENT_METHODS`] only fail over if the request was never sent, since the
+//! endpoint may have accepted the transaction otherwise, unless retries were opted into for the method
+//! with [`RetryPolicy::method`](crate::retry::RetryPolicy::method).
+//!
+//! Archival endpoints are tried after all regular endpoints. Requests that pin a specific block
+//! and methods registered with [`FailoverClient::with_archival_method`] go to archival endpoints first.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use near_jsonrpc_client::{failover::FailoverClient, methods, JsonRpcClient};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = FailoverClient::new([
+//!     JsonRpcClient::connect("https://rpc.mainnet.near.org"),
+//!     JsonRpcClient::connect("https://near.lava.build"),
+//! ])
+//! .with_archival(JsonRpcClient::connect("https://archival-rpc.mainnet.near.org"));
+//!
+//! client.spawn_health_checks(Duration::from_secs(30));
+//!
+//! let status = client.call(methods::status::RpcStatusRequest).await?;
+//!
+//! println!("{:?}", status);
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use crate::errors::*;
+use crate::retry::NON_IDEMPOTENT_METHODS;
+use crate::{methods, JsonRpcClient, MethodCallResult};
+
+/// The method used to probe the health of endpoints.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum HealthProbe {
+    /// Probe endpoints with [`methods::health::RpcHealthRequest`].
+    Health,
+    /// Probe endpoints with [`methods::status::RpcStatusRequest`],
+    /// considering endpoints that are still syncing unhealthy.
+    Status,
+}
+
+/// A snapshot of the health of an endpoint.
+#[derive(Debug, Clone)]
+pub struct EndpointStatus {
+    /// The server address of the endpoint.
+    pub server_addr: String,
+    /// Whether or not this is an archival endpoint.
+    pub archival: bool,
+    /// Whether or not the endpoint is currently considered healthy.
+    pub healthy: bool,
+    /// The number of consecutive failures since the last successful request.
+    pub consecutive_failures: u32,
+    /// The latency of the last successful request.
+    pub latency: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    client: JsonRpcClient,
+    archival: bool,
+    health: Mutex<Health>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Health {
+    healthy: bool,
+    consecutive_failures: u32,
+    latency: Option<Duration>,
+}
+
+impl Clone for Endpoint {
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            archival: self.archival,
+            health: Mutex::new(self.health()),
+        }
+    }
+}
+
+impl Endpoint {
+    fn new(client: JsonRpcClient, archival: bool) -> Self {
+        Self {
+            client,
+            archival,
+            health: Mutex::new(Health {
+                healthy: true,
+                consecutive_failures: 0,
+                latency: None,
+            }),
+        }
+    }
+
+    fn health(&self) -> Health {
+        *self.health.lock().unwrap()
+    }
+
+    fn mark_healthy(&self, latency: Duration) {
+        let mut health = self.health.lock().unwrap();
+        health.healthy = true;
+        health.consecutive_failures = 0;
+        health.latency = Some(latency);
+    }
+
+    fn mark_unhealthy(&self) {
+        let mut health = self.health.lock().unwrap();
+        if health.healthy {
+            log::debug!("marking [{}] as unhealthy", self.client.server_addr());
+        }
+        health.healthy = false;
+        health.consecutive_failures = health.consecutive_failures.saturating_add(1);
+    }
+}
+
+#[derive(Debug, Clone)]
+struct FailoverInner {
+    endpoints: Vec<Endpoint>,
+    archival_methods: HashSet<String>,
+    route_pinned_blocks_to_archival: bool,
+    probe: HealthProbe,
+    probe_timeout: Duration,
+}
+
+/// A client that routes calls across multiple RPC endpoints.
+///
+/// See the [`failover`](self) module documentation for more information.
+#[derive(Debug, Clone)]
+pub struct FailoverClient {
+    inner: Arc<FailoverInner>,
+}
+
+impl FailoverClient {
+    /// Create a new failover client from an ordered list of clients, the first being the most preferred.
+    pub fn new<I: IntoIterator<Item = JsonRpcClient>>(clients: I) -> Self {
+        Self {
+            inner: Arc::new(FailoverInner {
+                endpoints: clients
+                    .into_iter()
+                    .map(|client| Endpoint::new(client, false))
+                    .collect(),
+                archival_methods: HashSet::new(),
+                route_pinned_blocks_to_archival: true,
+                probe: HealthProbe::Health,
+                probe_timeout: Duration::from_secs(10),
+            }),
+        }
+    }
+
+    /// Create a failover client for mainnet, with the default NEAR RPC endpoints.
+    pub fn mainnet() -> Self {
+        Self::new([JsonRpcClient::connect(crate::NEAR_MAINNET_RPC_URL)])
+            .with_archival(JsonRpcClient::connect(crate::NEAR_MAINNET_ARCHIVAL_RPC_URL))
+    }
+
+    /// Create a failover client for testnet, with the default NEAR RPC endpoints.
+    pub fn testnet() -> Self {
+        Self::new([JsonRpcClient::connect(crate::NEAR_TESTNET_RPC_URL)])
+            .with_archival(JsonRpcClient::connect(crate::NEAR_TESTNET_ARCHIVAL_RPC_URL))
+    }
+
+    fn inner_mut(&mut self) -> &mut FailoverInner {
+        Arc::make_mut(&mut self.inner)
+    }
+
+    /// Add an archival endpoint.
+    ///
+    /// Like all the other `with_*` methods, reconfiguring a client that has already been cloned
+    /// leaves the clones as they were, starting from a copy of their endpoints' health.
+    pub fn with_archival(mut self, client: JsonRpcClient) -> Self {
+        self.inner_mut().endpoints.push(Endpoint::new(client, true));
+        self
+    }
+
+    /// Route calls to this method to archival endpoints first.
+    pub fn with_archival_method<N: Into<String>>(mut self, method_name: N) -> Self {
+        self.inner_mut().archival_methods.insert(method_name.into());
+        self
+    }
+
+    /// Whether or not to route requests that reference a specific block to archival endpoints first.
+    ///
+    /// Defaults to `true`.
+    pub fn with_pinned_blocks_to_archival(mut self, enabled: bool) -> Self {
+        self.inner_mut().route_pinned_blocks_to_archival = enabled;
+        self
+    }
+
+    /// Set the method used to probe endpoints, and how long to wait for them to respond.
+    ///
+    /// Defaults to [`HealthProbe::Health`] with a 10 second timeout.
+    pub fn with_health_probe(mut self, probe: HealthProbe, timeout: Duration) -> Self {
+        let inner = self.inner_mut();
+        inner.probe = probe;
+        inner.probe_timeout = timeout;
+        self
+    }
+
+    /// Get a snapshot of the health of every endpoint, in order.
+    pub fn endpoints(&self) -> Vec<EndpointStatus> {
+        self.inner
+            .endpoints
+            .iter()
+            .map(|endpoint| {
+                let health = endpoint.health();
+                EndpointStatus {
+                    server_addr: endpoint.client.server_addr().to_string(),
+                    archival: endpoint.archival,
+                    healthy: health.healthy,
+                    consecutive_failures: health.consecutive_failures,
+                    latency: health.latency,
+                }
+            })
+            .collect()
+    }
+
+    /// Call a method on the healthiest endpoint, failing over to the others if it's unable to serve the request.
+    ///
+    /// If every endpoint fails, the error from the last one is returned.
+    pub async fn call<M>(&self, method: M) -> MethodCallResult<M::Response, M::Error>
+    where
+        M: methods::RpcMethod,
+    {
+        let candidates = self.inner.route(&method);
+        let method_name = method.method_name();
+
+        let mut last_err = None;
+        for endpoint in candidates {
+            let started_at = Instant::now();
+            match endpoint.client.call(&method).await {
+                Err(err) if is_endpoint_failure(&err) => {
+                    endpoint.mark_unhealthy();
+                    // the endpoint may have accepted the transaction already
+                    let resendable = !NON_IDEMPOTENT_METHODS.contains(&method_name)
+                        || endpoint.client.max_attempts(method_name) > 1;
+                    if !resendable && !is_unsent(&err) {
+                        log::debug!(
+                            "[{}] failed to serve `{}`, which isn't safe to send again",
+                            endpoint.client.server_addr(),
+                            method_name
+                        );
+                        return Err(err);
+                    }
+                    log::debug!(
+                        "[{}] failed to serve `{}`, failing over",
+                        endpoint.client.server_addr(),
+                        method_name
+                    );
+                    last_err = Some(err);
+                }
+                result => {
+                    endpoint.mark_healthy(started_at.elapsed());
+                    return result;
+                }
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| {
+            JsonRpcError::ServerError(JsonRpcServerError::InternalError {
+                info: Some(String::from("no endpoints configured")),
+            })
+        }))
+    }
+
+    /// Probe the health of every endpoint once.
+    pub async fn probe(&self) {
+        self.inner.probe().await
+    }
+
+    /// Spawn a background task that probes every endpoint at the specified interval.
+    ///
+    /// The task stops once every handle to this client has been dropped.
+    ///
+    /// This must be called from within a Tokio runtime.
+    pub fn spawn_health_checks(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let inner: Weak<FailoverInner> = Arc::downgrade(&self.inner);
+        tokio::spawn(async move {
+            loop {
+                match inner.upgrade() {
+                    Some(inner) => inner.probe().await,
+                    None => break,
+                }
+                tokio::time::sleep(interval).await;
+            }
+        })
+    }
+}
+
+impl FailoverInner {
+    fn route<M: methods::RpcMethod>(&self, method: &M) -> Vec<&Endpoint> {
+        let prefer_archival = self.archival_methods.contains(method.method_name())
+            || (self.route_pinned_blocks_to_archival && pins_block(method));
+
+        let mut candidates = self.endpoints.iter().enumerate().collect::<Vec<_>>();
+        candidates.sort_by_key(|(index, endpoint)| {
+            let health = endpoint.health();
+            (
+                !health.healthy,
+                endpoint.archival != prefer_archival,
+                health.consecutive_failures,
+                *index,
+            )
+        });
+
+        candidates
+            .into_iter()
+            .map(|(_, endpoint)| endpoint)
+            .collect()
+    }
+
+    async fn probe(&self) {
+        for endpoint in &self.endpoints {
+            let started_at = Instant::now();
+            let healthy = match self.probe {
+                HealthProbe::Health => tokio::time::timeout(
+                    self.probe_timeout,
+                    endpoint.client.call(methods::health::RpcHealthRequest),
+                )
+                .await
+                .map_or(false, |res| res.is_ok()),
+                HealthProbe::Status => tokio::time::timeout(
+                    self.probe_timeout,
+                    endpoint.client.call(methods::status::RpcStatusRequest),
+                )
+                .await
+                .map_or(
+                    false,
+                    |res| matches!(res, Ok(ref status) if !status.sync_info.syncing),
+                ),
+            };
+            if healthy {
+                endpoint.mark_healthy(started_at.elapsed());
+            } else {
+                endpoint.mark_unhealthy();
+            }
+        }
+    }
+}
+
+/// Whether or not the request references a specific block, as opposed to the latest one.
+fn pins_block<M: methods::RpcMethod>(method: &M) -> bool {
+    matches!(method.params(), Ok(params) if !params["block_id"].is_null())
+}
+
+/// Whether or not the error suggests the endpoint itself is at fault, and another should be tried.
+fn is_endpoint_failure<E>(err: &JsonRpcError<E>) -> bool {
+    match err {
+        JsonRpcError::TransportError(RpcTransportError::SendError(
+            JsonRpcTransportSendError::PayloadSerializeError(_),
+        )) => false,
+        JsonRpcError::TransportError(RpcTransportError::TimeoutError(
+            JsonRpcTimeoutError::DeadlineExceeded,
+        )) => false,
+        JsonRpcError::TransportError(_) => true,
+        JsonRpcError::ServerError(JsonRpcServerError::InternalError { .. }) => true,
+        JsonRpcError::ServerError(JsonRpcServerError::ResponseStatusError(status)) => matches!(
+            status,
+            JsonRpcServerResponseStatusError::TooManyRequests { .. }
+                | JsonRpcServerResponseStatusError::ServiceUnavailable
+                | JsonRpcServerResponseStatusError::TimeoutError
+                | JsonRpcServerResponseStatusError::Unexpected { .. }
+        ),
+        JsonRpcError::ServerError(_) => false,
+    }
+}
+
+/// Whether or not the request provably never reached the server.
+fn is_unsent<E>(err: &JsonRpcError<E>) -> bool {
+    matches!(
+        err,
+        JsonRpcError::TransportError(RpcTransportError::SendError(_))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+
+    use super::*;
+    use crate::transport::{Transport, TransportFuture, TransportRequest, TransportResponse};
+
+    /// Fails every request sent to a server whose address contains "down".
+    #[derive(Debug)]
+    struct Flaky;
+
+    impl Transport for Flaky {
+        fn send(&self, request: TransportRequest) -> TransportFuture<'_> {
+            Box::pin(async move {
+                if request.server_addr.contains("down") {
+                    return Ok(TransportResponse::new(
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        vec![],
+                    ));
+                }
+                Ok(TransportResponse::new(
+                    StatusCode::OK,
+                    br#"{"jsonrpc":"2.0","id":"dontcare","result":null}"#.to_vec(),
+                ))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn fails_over_to_healthy_endpoints() {
+        let connector = JsonRpcClient::with_transport(Flaky);
+        let client = FailoverClient::new([
+            connector.connect("http://down.localhost"),
+            connector.connect("http://up.localhost"),
+        ])
+        .with_archival(connector.connect("http://archival.localhost"));
+
+        let response = client.call(methods::health::RpcHealthRequest).await;
+        assert!(response.is_ok(), "expected an Ok, found [{:?}]", response);
+
+        let endpoints = client.endpoints();
+        assert!(!endpoints[0].healthy);
+        assert_eq!(endpoints[0].consecutive_failures, 1);
+        assert!(endpoints[1].healthy);
+        assert!(endpoints[1].latency.is_some());
+        assert!(endpoints[2].healthy && endpoints[2].latency.is_none());
+
+        // the unhealthy endpoint is no longer the first choice
+        client
+            .call(methods::health::RpcHealthRequest)
+            .await
+            .unwrap();
+        assert_eq!(client.endpoints()[0].consecutive_failures, 1);
+
+        // probing doesn't revive endpoints that are still down
+        client.probe().await;
+        assert_eq!(client.endpoints()[0].consecutive_failures, 2);
+        assert!(client.endpoints()[2].latency.is_some());
+    }
+
+    #[test]
+    fn routes_pinned_blocks_to_archival() {
+        let connector = JsonRpcClient::with_transport(Flaky);
+        let client = FailoverClient::new([connector.connect("http://up.localhost")])
+            .with_archival(connector.connect("http://archival.localhost"));
+
+        let latest = methods::block::RpcBlockRequest {
+            block_reference: near_primitives::types::BlockReference::latest(),
+        };
+        let pinned = methods::block::RpcBlockRequest {
+            block_reference: near_primitives::types::BlockReference::BlockId(
+                near_primitives::types::BlockId::Height(1),
+            ),
+        };
+
+        let route = client.inner.route(&latest);
+        assert_eq!(route[0].client.server_addr(), "http://up.localhost");
+
+        let route = client.inner.route(&pinned);
+        assert_eq!(route[0].client.server_addr(), "http://archival.localhost");
+    }
+
+    #[test]
+    fn reconfigures_clones_copy_on_write() {
+        let connector = JsonRpcClient::with_transport(Flaky);
+        let client = FailoverClient::new([connector.connect("http://up.localhost")]);
+        let shared = client.clone();
+
+        let client = client.with_archival(connector.connect("http://archival.localhost"));
+        assert_eq!(client.endpoints().len(), 2);
+        assert_eq!(shared.endpoints().len(), 1);
+    }
+
+    /// Answers requests to servers whose address contains "slow" too late, recording every request.
+    #[derive(Debug, Default)]
+    struct Slow {
+        requests: Mutex<Vec<String>>,
+    }
+
+    impl Transport for Slow {
+        fn send(&self, request: TransportRequest) -> TransportFuture<'_> {
+            self.requests
+                .lock()
+                .unwrap()
+                .push(request.server_addr.clone());
+            Box::pin(async move {
+                if request.server_addr.contains("slow") {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+                Ok(TransportResponse::new(
+                    StatusCode::OK,
+                    br#"{"jsonrpc":"2.0","id":"dontcare","result":null}"#.to_vec(),
+                ))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn never_resends_non_idempotent_calls() {
+        use near_crypto::{KeyType, SecretKey, Signature};
+        use near_primitives::transaction::{SignedTransaction, Transaction, TransactionV0};
+
+        let transport = Arc::new(Slow::default());
+        let connector = JsonRpcClient::with_transport(transport.clone());
+        let failover = || {
+            FailoverClient::new([
+                connector
+                    .connect("http://slow.localhost")
+                    .with_timeout(Duration::from_millis(20)),
+                connector.connect("http://up.localhost"),
+            ])
+        };
+
+        let transaction = Transaction::V0(TransactionV0 {
+            signer_id: "alice.near".parse().unwrap(),
+            public_key: SecretKey::from_seed(KeyType::ED25519, "alice").public_key(),
+            nonce: 1,
+            receiver_id: "bob.near".parse().unwrap(),
+            block_hash: Default::default(),
+            actions: vec![],
+        });
+        let result = failover()
+            .call(methods::send_tx::RpcSendTransactionRequest {
+                signed_transaction: SignedTransaction::new(
+                    Signature::empty(KeyType::ED25519),
+                    transaction,
+                ),
+                wait_until: near_primitives::views::TxExecutionStatus::Final,
+            })
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(JsonRpcError::TransportError(
+                RpcTransportError::TimeoutError(JsonRpcTimeoutError::AttemptTimeout(_))
+            ))
+        ));
+        assert_eq!(
+            *transport.requests.lock().unwrap(),
+            ["http://slow.localhost"]
+        );
+        transport.requests.lock().unwrap().clear();
+
+        // idempotent calls fail over
+        failover()
+            .call(methods::health::RpcHealthRequest)
+            .await
+            .unwrap();
+        assert_eq!(
+            *transport.requests.lock().unwrap(),
+            ["http://slow.localhost", "http://up.localhost"]
+        );
+    }
+}
@@ -0,0 +1,625 @@
+//! Gas and fee estimation for transactions.
+//!
+//! A [`FeeEstimator`] holds the runtime fees of the protocol, from
+//! [`EXPERIMENTAL_protocol_config`](crate::methods::EXPERIMENTAL_protocol_config), and a gas price, from
+//! [`gas_price`](crate::methods::gas_price). [`JsonRpcClient::fee_estimator`] fetches both as of the latest
+//! final block, [`FeeEstimator::with_gas_price`] pins a different gas price.
+//!
+//! [`FeeEstimator::estimate`] breaks an unsigned [`Transaction`] down per action into a [`FeeEstimate`]:
+//!
+//! - the gas burnt sending the action, when the transaction is converted into a receipt
+//! - the gas burnt executing the action, before any contract code runs
+//! - the gas attached to function calls, the ceiling of what their code can burn
+//! - the tokens deposited, and the storage the action adds to the receiving account
+//!
+//! Fees are charged whether the actions succeed or not. The gas burnt by contract code can't be
+//! known in advance, only that it won't exceed the attached gas, unused attached gas is refunded.
+//!
+//! Receipts may run blocks after the transaction is converted, at a higher gas price. The signer
+//! prepays the execution fees and the attached gas at a [pessimistic](FeeEstimate::pessimistic_gas_price)
+//! gas price, inflated by the most the price can rise until then, the difference is refunded.
+//!
+//! Fees of meta transactions are an approximation, the actions of a [`Delegate`](Action::Delegate)
+//! action are accounted for as part of it. Actions added to the protocol after this crate was built are
+//! left out of estimates, with a warning logged.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use near_jsonrpc_client::JsonRpcClient;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let transaction = unimplemented!();
+//! let client = JsonRpcClient::connect("https://rpc.mainnet.near.org");
+//!
+//! let estimator = client.fee_estimator().await?;
+//! let estimate = estimator.estimate(&transaction);
+//!
+//! for action in &estimate.actions {
+//!     println!(
+//!         "action #{}: {} gas in fees, {} gas attached",
+//!         action.index,
+//!         action.fee_gas(),
+//!         action.attached_gas
+//!     );
+//! }
+//! println!(
+//!     "fees: {} yoctoNEAR, at most {} yoctoNEAR",
+//!     estimate.fee_cost(),
+//!     estimate.max_cost()
+//! );
+//! # Ok(())
+//! # }
+//! ```
+use near_parameters::{Fee, RuntimeConfigView};
+use near_primitives::account::id::AccountType;
+use near_primitives::account::AccessKeyPermission;
+use near_primitives::transaction::{Action, Transaction};
+use near_primitives::types::{AccountId, Balance, BlockReference, Finality, Gas, StorageUsage};
+use thiserror::Error;
+
+use crate::errors::*;
+use crate::methods::gas_price::RpcGasPriceError;
+use crate::methods::EXPERIMENTAL_protocol_config::RpcProtocolConfigError;
+use crate::{methods, JsonRpcClient};
+
+/// Potential errors returned while setting up a [`FeeEstimator`].
+#[derive(Debug, Error)]
+pub enum FeeEstimatorError {
+    /// The protocol config couldn't be fetched.
+    #[error("error while fetching protocol config: [{0}]")]
+    ProtocolConfigError(JsonRpcError<RpcProtocolConfigError>),
+    /// The gas price couldn't be fetched.
+    #[error("error while fetching gas price: [{0}]")]
+    GasPriceError(JsonRpcError<RpcGasPriceError>),
+}
+
+/// Estimates the gas and fees of transactions.
+///
+/// See the [`fees`](self) module documentation for more information.
+#[derive(Debug, Clone)]
+pub struct FeeEstimator {
+    runtime_config: RuntimeConfigView,
+    max_prepaid_gas: Gas,
+    gas_price: Balance,
+}
+
+/// The estimated gas and fees of a transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeeEstimate {
+    /// The gas price the costs are computed at, in yoctoNEAR.
+    pub gas_price: Balance,
+    /// The gas burnt creating the receipt of the transaction, when it's converted.
+    pub receipt_send_gas: Gas,
+    /// The gas burnt creating the receipt of the transaction, when it's executed.
+    pub receipt_exec_gas: Gas,
+    /// The estimates of the actions, in order.
+    pub actions: Vec<ActionEstimate>,
+    /// The cost of storing a byte, in yoctoNEAR.
+    pub storage_amount_per_byte: Balance,
+    /// The maximum gas a transaction can attach to its function calls.
+    pub max_prepaid_gas: Gas,
+    inflation: Inflation,
+}
+
+/// How much the gas price can rise before the receipts of a transaction run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Inflation {
+    /// The ratio the gas price can rise by per block.
+    ratio: (u128, u128),
+    /// The most blocks the receipts can run after the transaction.
+    blocks: u8,
+}
+
+/// The estimated gas and fees of an action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActionEstimate {
+    /// The position of the action in the transaction.
+    pub index: usize,
+    /// The gas burnt sending the action, when the transaction is converted into a receipt.
+    pub send_gas: Gas,
+    /// The gas burnt executing the action, not counting contract code.
+    pub exec_gas: Gas,
+    /// The gas attached to the function calls of the action.
+    pub attached_gas: Gas,
+    /// The tokens deposited by the action, in yoctoNEAR.
+    pub deposit: Balance,
+    /// The bytes of storage the action adds to the receiving account.
+    pub storage_bytes: StorageUsage,
+}
+
+impl ActionEstimate {
+    /// The gas burnt in fees for the action.
+    pub fn fee_gas(&self) -> Gas {
+        self.send_gas + self.exec_gas
+    }
+}
+
+impl JsonRpcClient {
+    /// Create a [`FeeEstimator`] with the runtime fees and gas price as of the latest final block.
+    ///
+    /// See the [`fees`](crate::fees) module documentation for more information.
+    pub async fn fee_estimator(&self) -> Result<FeeEstimator, FeeEstimatorError> {
+        let (config, gas_price) = futures::join!(
+            self.call(
+                methods::EXPERIMENTAL_protocol_config::RpcProtocolConfigRequest {
+                    block_reference: BlockReference::Finality(Finality::Final),
+                }
+            ),
+            self.call(methods::gas_price::RpcGasPriceRequest { block_id: None }),
+        );
+        let config = config.map_err(FeeEstimatorError::ProtocolConfigError)?;
+        let gas_price = gas_price.map_err(FeeEstimatorError::GasPriceError)?;
+
+        Ok(FeeEstimator::new(
+            config.runtime_config,
+            gas_price.gas_price,
+        ))
+    }
+}
+
+impl FeeEstimator {
+    /// Create an estimator from the runtime config of the protocol and a gas price.
+    pub fn new(runtime_config: RuntimeConfigView, gas_price: Balance) -> Self {
+        Self {
+            max_prepaid_gas: runtime_config
+                .wasm_config
+                .limit_config
+                .max_total_prepaid_gas,
+            runtime_config,
+            gas_price,
+        }
+    }
+
+    /// Compute costs at a different gas price.
+    pub fn with_gas_price(mut self, gas_price: Balance) -> Self {
+        self.gas_price = gas_price;
+        self
+    }
+
+    /// The gas price costs are computed at, in yoctoNEAR.
+    pub fn gas_price(&self) -> Balance {
+        self.gas_price
+    }
+
+    /// The runtime config fees are computed from.
+    pub fn runtime_config(&self) -> &RuntimeConfigView {
+        &self.runtime_config
+    }
+
+    /// Estimate the gas and fees of a transaction.
+    pub fn estimate(&self, transaction: &Transaction) -> FeeEstimate {
+        let sir = transaction.signer_id() == transaction.receiver_id();
+        let receipt = &self
+            .runtime_config
+            .transaction_costs
+            .action_receipt_creation_config;
+
+        let actions = transaction
+            .actions()
+            .iter()
+            .enumerate()
+            .map(|(index, action)| {
+                self.estimate_action(index, action, sir, transaction.receiver_id())
+            })
+            .collect::<Vec<_>>();
+
+        // receipts to another account run a block later, and every receipt the attached gas
+        // can pay for may run another block later still
+        let fees = &self.runtime_config.transaction_costs;
+        let min_receipt_gas = receipt.min_send_and_exec_fee()
+            + fees
+                .action_creation_config
+                .function_call_cost
+                .min_send_and_exec_fee();
+        let attached_gas = actions.iter().map(|a| a.attached_gas).sum::<Gas>();
+        let depth = attached_gas.checked_div(min_receipt_gas).unwrap_or(0) + u64::from(!sir);
+        let ratio = fees.pessimistic_gas_price_inflation_ratio;
+
+        FeeEstimate {
+            gas_price: self.gas_price,
+            receipt_send_gas: receipt.send_fee(sir),
+            receipt_exec_gas: receipt.exec_fee(),
+            actions,
+            storage_amount_per_byte: self.runtime_config.storage_amount_per_byte,
+            max_prepaid_gas: self.max_prepaid_gas,
+            inflation: Inflation {
+                ratio: (*ratio.numer() as u128, *ratio.denom() as u128),
+                blocks: u8::try_from(depth).unwrap_or(u8::MAX),
+            },
+        }
+    }
+
+    fn estimate_action(
+        &self,
+        index: usize,
+        action: &Action,
+        sir: bool,
+        receiver_id: &AccountId,
+    ) -> ActionEstimate {
+        let fees = &self.runtime_config.transaction_costs;
+        let costs = &fees.action_creation_config;
+        let mut estimate = ActionEstimate {
+            index,
+            send_gas: 0,
+            exec_gas: 0,
+            attached_gas: 0,
+            deposit: 0,
+            storage_bytes: 0,
+        };
+        let mut charge = |fee: &Fee, times: u64| {
+            estimate.send_gas += fee.send_fee(sir) * times;
+            estimate.exec_gas += fee.exec_fee() * times;
+        };
+
+        match action {
+            Action::CreateAccount(_) => {
+                charge(&costs.create_account_cost, 1);
+                estimate.storage_bytes = fees.storage_usage_config.num_bytes_account;
+            }
+            Action::DeployContract(action) => {
+                charge(&costs.deploy_contract_cost, 1);
+                charge(
+                    &costs.deploy_contract_cost_per_byte,
+                    action.code.len() as u64,
+                );
+                estimate.storage_bytes = action.code.len() as u64;
+            }
+            Action::FunctionCall(action) => {
+                charge(&costs.function_call_cost, 1);
+                charge(
+                    &costs.function_call_cost_per_byte,
+                    (action.method_name.len() + action.args.len()) as u64,
+                );
+                estimate.attached_gas = action.gas;
+                estimate.deposit = action.deposit;
+            }
+            Action::Transfer(action) => {
+                charge(&costs.transfer_cost, 1);
+                // transfers to implicit accounts pay for creating them
+                match receiver_id.get_account_type() {
+                    AccountType::NearImplicitAccount => {
+                        charge(&costs.create_account_cost, 1);
+                        charge(&costs.add_key_cost.full_access_cost, 1);
+                    }
+                    AccountType::EthImplicitAccount => charge(&costs.create_account_cost, 1),
+                    AccountType::NamedAccount => {}
+                }
+                estimate.deposit = action.deposit;
+            }
+            Action::Stake(_) => charge(&costs.stake_cost, 1),
+            Action::AddKey(action) => {
+                match &action.access_key.permission {
+                    AccessKeyPermission::FullAccess => {
+                        charge(&costs.add_key_cost.full_access_cost, 1)
+                    }
+                    AccessKeyPermission::FunctionCall(permission) => {
+                        charge(&costs.add_key_cost.function_call_cost, 1);
+                        charge(
+                            &costs.add_key_cost.function_call_cost_per_byte,
+                            permission
+                                .method_names
+                                .iter()
+                                .map(|name| name.len() as u64 + 1)
+                                .sum(),
+                        );
+                    }
+                }
+                estimate.storage_bytes = borsh::object_length(&action.public_key).unwrap_or(0)
+                    as u64
+                    + borsh::object_length(&action.access_key).unwrap_or(0) as u64
+                    + fees.storage_usage_config.num_extra_bytes_record;
+            }
+            Action::DeleteKey(_) => charge(&costs.delete_key_cost, 1),
+            Action::DeleteAccount(_) => charge(&costs.delete_account_cost, 1),
+            Action::Delegate(action) => {
+                charge(&costs.delegate_cost, 1);
+
+                // the delegated actions are sent in a receipt of their own
+                let delegate_action = &action.delegate_action;
+                let inner_sir = delegate_action.sender_id == delegate_action.receiver_id;
+                let receipt = &fees.action_receipt_creation_config;
+                estimate.send_gas += receipt.send_fee(inner_sir);
+                estimate.exec_gas += receipt.exec_fee();
+                for inner in delegate_action.get_actions() {
+                    let inner = self.estimate_action(
+                        index,
+                        &inner,
+                        inner_sir,
+                        &delegate_action.receiver_id,
+                    );
+                    estimate.send_gas += inner.send_gas;
+                    estimate.exec_gas += inner.exec_gas;
+                    estimate.attached_gas += inner.attached_gas;
+                    estimate.deposit += inner.deposit;
+                    estimate.storage_bytes += inner.storage_bytes;
+                }
+            }
+            #[allow(unreachable_patterns)]
+            _ => log::warn!(
+                "no fees known for action #{}, it's left out of the estimate",
+                index
+            ),
+        }
+
+        estimate
+    }
+}
+
+impl FeeEstimate {
+    /// The gas burnt when the transaction is converted into a receipt.
+    pub fn send_gas(&self) -> Gas {
+        self.receipt_send_gas + self.actions.iter().map(|a| a.send_gas).sum::<Gas>()
+    }
+
+    /// The gas burnt when the receipt is executed, not counting contract code.
+    pub fn exec_gas(&self) -> Gas {
+        self.receipt_exec_gas + self.actions.iter().map(|a| a.exec_gas).sum::<Gas>()
+    }
+
+    /// The gas burnt in fees, whether the actions succeed or not.
+    pub fn fee_gas(&self) -> Gas {
+        self.send_gas() + self.exec_gas()
+    }
+
+    /// The gas attached to function calls.
+    pub fn attached_gas(&self) -> Gas {
+        self.actions.iter().map(|a| a.attached_gas).sum()
+    }
+
+    /// The most gas the transaction can burn, its fees and all of its attached gas.
+    pub fn max_gas(&self) -> Gas {
+        self.fee_gas() + self.attached_gas()
+    }
+
+    /// The gas that can still be attached to function calls without exceeding the protocol limit.
+    pub fn remaining_attachable_gas(&self) -> Gas {
+        self.max_prepaid_gas.saturating_sub(self.attached_gas())
+    }
+
+    /// Whether or not function calls attach more gas than the protocol allows.
+    pub fn exceeds_max_prepaid_gas(&self) -> bool {
+        self.attached_gas() > self.max_prepaid_gas
+    }
+
+    /// The tokens deposited by the actions, in yoctoNEAR.
+    pub fn deposit(&self) -> Balance {
+        self.actions.iter().map(|a| a.deposit).sum()
+    }
+
+    /// The bytes of storage the actions add to the receiving account.
+    pub fn storage_bytes(&self) -> StorageUsage {
+        self.actions.iter().map(|a| a.storage_bytes).sum()
+    }
+
+    /// The balance the receiving account has to hold for the storage added, in yoctoNEAR.
+    ///
+    /// This balance is locked rather than spent.
+    pub fn storage_stake(&self) -> Balance {
+        self.storage_bytes() as Balance * self.storage_amount_per_byte
+    }
+
+    /// The tokens burnt in fees, in yoctoNEAR.
+    pub fn fee_cost(&self) -> Balance {
+        self.fee_gas() as Balance * self.gas_price
+    }
+
+    /// The gas price execution fees and attached gas are prepaid at, in yoctoNEAR.
+    ///
+    /// This is the gas price inflated by the most it can rise before the receipts of the transaction run.
+    pub fn pessimistic_gas_price(&self) -> Balance {
+        let Inflation {
+            ratio: (numer, denom),
+            blocks,
+        } = self.inflation;
+        // rounded up every block, so this never falls short of the protocol's own computation
+        (0..blocks).fold(self.gas_price, |gas_price, _| {
+            gas_price
+                .saturating_mul(numer)
+                .saturating_add(denom.saturating_sub(1))
+                .checked_div(denom)
+                .unwrap_or(gas_price)
+        })
+    }
+
+    /// The most the transaction can cost the signer, fees, attached gas and deposits, in yoctoNEAR.
+    ///
+    /// This is the balance the signer needs to submit the transaction. Send fees are charged at the
+    /// gas price, execution fees and attached gas at the [pessimistic](Self::pessimistic_gas_price) gas
+    /// price, whatever isn't spent is refunded.
+    pub fn max_cost(&self) -> Balance {
+        self.send_gas() as Balance * self.gas_price
+            + (self.exec_gas() + self.attached_gas()) as Balance * self.pessimistic_gas_price()
+            + self.deposit()
+    }
+
+    /// The same estimate, at a different gas price.
+    pub fn at_gas_price(mut self, gas_price: Balance) -> Self {
+        self.gas_price = gas_price;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_crypto::{KeyType, SecretKey};
+    use near_primitives::account::AccessKey;
+    use near_primitives::action::delegate::{
+        DelegateAction, NonDelegateAction, SignedDelegateAction,
+    };
+    use near_primitives::transaction::{
+        AddKeyAction, FunctionCallAction, TransactionV0, TransferAction,
+    };
+
+    use super::*;
+
+    fn transaction(receiver_id: &str, actions: Vec<Action>) -> Transaction {
+        Transaction::V0(TransactionV0 {
+            signer_id: "alice.near".parse().unwrap(),
+            public_key: SecretKey::from_seed(KeyType::ED25519, "alice").public_key(),
+            nonce: 1,
+            receiver_id: receiver_id.parse().unwrap(),
+            block_hash: Default::default(),
+            actions,
+        })
+    }
+
+    fn estimator() -> FeeEstimator {
+        FeeEstimator::new(near_parameters::RuntimeConfig::test().into(), 100_000_000)
+    }
+
+    #[test]
+    fn estimates_per_action() {
+        let estimator = estimator();
+        let costs = &estimator.runtime_config().transaction_costs;
+        let transaction = transaction(
+            "app.near",
+            vec![
+                Action::FunctionCall(Box::new(FunctionCallAction {
+                    method_name: "swap".to_string(),
+                    args: b"{}".to_vec(),
+                    gas: 30_000_000_000_000,
+                    deposit: 1,
+                })),
+                Action::Transfer(TransferAction { deposit: 10 }),
+            ],
+        );
+
+        let estimate = estimator.estimate(&transaction);
+
+        let call = &costs.action_creation_config.function_call_cost;
+        let per_byte = &costs.action_creation_config.function_call_cost_per_byte;
+        assert_eq!(
+            estimate.actions[0].send_gas,
+            call.send_not_sir + per_byte.send_not_sir * 6
+        );
+        assert_eq!(
+            estimate.actions[0].exec_gas,
+            call.execution + per_byte.execution * 6
+        );
+        assert_eq!(
+            estimate.receipt_send_gas,
+            costs.action_receipt_creation_config.send_not_sir
+        );
+
+        assert_eq!(estimate.attached_gas(), 30_000_000_000_000);
+        assert_eq!(estimate.deposit(), 11);
+        assert!(!estimate.exceeds_max_prepaid_gas());
+
+        // a receipt to another account, with gas for a few more
+        let min_receipt_gas = costs.action_receipt_creation_config.min_send_and_exec_fee()
+            + call.min_send_and_exec_fee();
+        let blocks = (1 + 30_000_000_000_000 / min_receipt_gas) as u32;
+        let (numer, denom) = (103u128.pow(blocks), 100u128.pow(blocks));
+        let inflated = (100_000_000 * numer).div_ceil(denom);
+        let pessimistic_gas_price = estimate.pessimistic_gas_price();
+        assert!(
+            (inflated..=inflated + blocks as Balance).contains(&pessimistic_gas_price),
+            "{} isn't {} rounded up",
+            pessimistic_gas_price,
+            inflated
+        );
+        assert_eq!(
+            estimate.max_cost(),
+            estimate.send_gas() as Balance * 100_000_000
+                + (estimate.exec_gas() + 30_000_000_000_000) as Balance * pessimistic_gas_price
+                + 11
+        );
+        assert_eq!(
+            estimate.clone().at_gas_price(200_000_000).fee_cost(),
+            estimate.fee_cost() * 2
+        );
+    }
+
+    #[test]
+    fn estimates_storage_and_implicit_transfers() {
+        let estimator = estimator();
+        let costs = &estimator.runtime_config().transaction_costs;
+        let public_key = SecretKey::from_seed(KeyType::ED25519, "bob").public_key();
+        let implicit = hex_account(&public_key);
+
+        let estimate = estimator.estimate(&transaction(
+            "alice.near",
+            vec![Action::AddKey(Box::new(AddKeyAction {
+                public_key,
+                access_key: AccessKey::full_access(),
+            }))],
+        ));
+        // borsh encoded key type and key, then nonce and permission
+        assert_eq!(
+            estimate.storage_bytes(),
+            33 + 9 + costs.storage_usage_config.num_extra_bytes_record
+        );
+        assert_eq!(
+            estimate.actions[0].send_gas,
+            costs
+                .action_creation_config
+                .add_key_cost
+                .full_access_cost
+                .send_sir
+        );
+
+        let named = estimator.estimate(&transaction(
+            "bob.near",
+            vec![Action::Transfer(TransferAction { deposit: 1 })],
+        ));
+        let implicit = estimator.estimate(&transaction(
+            &implicit,
+            vec![Action::Transfer(TransferAction { deposit: 1 })],
+        ));
+        let creation = &costs.action_creation_config;
+        assert_eq!(
+            implicit.actions[0].fee_gas() - named.actions[0].fee_gas(),
+            creation.create_account_cost.send_not_sir
+                + creation.create_account_cost.execution
+                + creation.add_key_cost.full_access_cost.send_not_sir
+                + creation.add_key_cost.full_access_cost.execution
+        );
+    }
+
+    #[test]
+    fn estimates_delegated_actions() {
+        let estimator = estimator();
+        let costs = &estimator.runtime_config().transaction_costs;
+        let public_key = SecretKey::from_seed(KeyType::ED25519, "bob").public_key();
+
+        // relayed for bob.near, to transfer to app.near
+        let transfer = Action::Transfer(TransferAction { deposit: 10 });
+        let estimate = estimator.estimate(&transaction(
+            "bob.near",
+            vec![Action::Delegate(Box::new(SignedDelegateAction {
+                delegate_action: DelegateAction {
+                    sender_id: "bob.near".parse().unwrap(),
+                    receiver_id: "app.near".parse().unwrap(),
+                    actions: vec![NonDelegateAction::try_from(transfer).unwrap()],
+                    nonce: 1,
+                    max_block_height: 100,
+                    public_key,
+                },
+                signature: near_crypto::Signature::empty(KeyType::ED25519),
+            }))],
+        ));
+
+        let delegate = &costs.action_creation_config.delegate_cost;
+        let receipt = &costs.action_receipt_creation_config;
+        let transfer = &costs.action_creation_config.transfer_cost;
+        assert_eq!(
+            estimate.actions[0].send_gas,
+            delegate.send_not_sir + receipt.send_not_sir + transfer.send_not_sir
+        );
+        assert_eq!(
+            estimate.actions[0].exec_gas,
+            delegate.execution + receipt.execution + transfer.execution
+        );
+        assert_eq!(estimate.deposit(), 10);
+    }
+
+    fn hex_account(public_key: &near_crypto::PublicKey) -> String {
+        public_key
+            .key_data()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
@@ -0,0 +1,626 @@
+//! Change history of an account.
+//!
+//! [`JsonRpcClient::account_history`] returns an [`AccountHistory`], a [`futures::Stream`] yielding every
+//! change to an account over a range of block heights, in height order:
+//!
+//! - [`AccountDelta::Balance`], when its balance, locked balance or storage usage changes,
+//!   or when it's created or deleted
+//! - [`AccountDelta::AccessKey`], when one of its access keys is added, updated or deleted
+//! - [`AccountDelta::Storage`], when its contract writes to or deletes from its storage
+//!
+//! Every change comes with its [cause](AccountChange::cause), the receipt or transaction hash in
+//! particular, and the block it happened in.
+//!
+//! Every height is first checked with `EXPERIMENTAL_changes_in_block`, and only blocks touching the
+//! account are queried further with `EXPERIMENTAL_changes`. Heights without a block are skipped, and
+//! as with [`stream`](crate::stream), non-archival nodes respond the same way for garbage collected
+//! heights, so walking old ranges requires an archival endpoint.
+//!
+//! The balance before the first change is read at the block preceding it. Within a block, balance
+//! changes come first, then access key changes, then storage changes.
+//!
+//! Errors don't end the stream, the block that failed is fetched again on the next poll.
+//! [`AccountHistory::cursor`] is the next height to be checked.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use near_jsonrpc_client::{history::AccountDelta, JsonRpcClient};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = JsonRpcClient::connect("https://archival-rpc.mainnet.near.org");
+//!
+//! let mut history = client
+//!     .account_history("aurora.near".parse()?, 120_000_000..=120_001_000)
+//!     .with_storage(false);
+//!
+//! while let Some(change) = history.next().await {
+//!     let change = change?;
+//!     if let AccountDelta::Balance(delta) = &change.delta {
+//!         println!(
+//!             "#{} {:+} yoctoNEAR, caused by {:?}",
+//!             change.block_height,
+//!             delta.amount_change(),
+//!             change.cause
+//!         );
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::future::BoxFuture;
+use futures::{FutureExt, Stream};
+use near_crypto::PublicKey;
+use near_jsonrpc_primitives::types::query::QueryResponseKind;
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{
+    AccountId, Balance, BlockHeight, BlockId, BlockReference, StorageUsage, StoreKey,
+};
+use near_primitives::views::{
+    AccessKeyView, AccountView, QueryRequest, StateChangeCauseView, StateChangeKindView,
+    StateChangeValueView, StateChangesRequestView,
+};
+use thiserror::Error;
+
+use crate::errors::*;
+use crate::methods::block::RpcBlockError;
+use crate::methods::query::RpcQueryError;
+use crate::methods::EXPERIMENTAL_changes::RpcStateChangesError;
+use crate::{methods, JsonRpcClient};
+
+/// Potential errors returned while walking the history of an account.
+#[derive(Debug, Error)]
+pub enum AccountHistoryError {
+    /// The changes in a block couldn't be fetched.
+    #[error("error while fetching changes: [{0}]")]
+    ChangesError(JsonRpcError<RpcStateChangesError>),
+    /// The block preceding the first change couldn't be fetched.
+    #[error("error while fetching block: [{0}]")]
+    BlockError(JsonRpcError<RpcBlockError>),
+    /// The account couldn't be viewed before the first change.
+    #[error("error while viewing account: [{0}]")]
+    QueryError(JsonRpcError<RpcQueryError>),
+}
+
+/// A change to an account, yielded by an [`AccountHistory`].
+#[derive(Debug, Clone)]
+pub struct AccountChange {
+    /// The height of the block the change happened in.
+    pub block_height: BlockHeight,
+    /// The hash of the block the change happened in.
+    pub block_hash: CryptoHash,
+    /// What caused the change.
+    pub cause: StateChangeCauseView,
+    /// The change itself.
+    pub delta: AccountDelta,
+}
+
+impl AccountChange {
+    /// The hash of the transaction that caused the change, if it was a transaction.
+    pub fn transaction_hash(&self) -> Option<CryptoHash> {
+        match self.cause {
+            StateChangeCauseView::TransactionProcessing { tx_hash } => Some(tx_hash),
+            _ => None,
+        }
+    }
+
+    /// The hash of the receipt that caused the change, if it was a receipt.
+    pub fn receipt_hash(&self) -> Option<CryptoHash> {
+        match self.cause {
+            StateChangeCauseView::ActionReceiptProcessingStarted { receipt_hash }
+            | StateChangeCauseView::ActionReceiptGasReward { receipt_hash }
+            | StateChangeCauseView::ReceiptProcessing { receipt_hash }
+            | StateChangeCauseView::PostponedReceipt { receipt_hash } => Some(receipt_hash),
+            _ => None,
+        }
+    }
+}
+
+/// A change to an account.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AccountDelta {
+    /// The balance, locked balance or storage usage of the account changed.
+    Balance(BalanceDelta),
+    /// An access key was added or updated, or deleted if `access_key` is `None`.
+    AccessKey {
+        public_key: PublicKey,
+        access_key: Option<AccessKeyView>,
+    },
+    /// A storage entry of the contract was written, or deleted if `value` is `None`.
+    Storage {
+        key: Vec<u8>,
+        value: Option<Vec<u8>>,
+    },
+}
+
+/// The balances of an account at some point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccountBalance {
+    /// The liquid balance, in yoctoNEAR.
+    pub amount: Balance,
+    /// The balance locked for staking, in yoctoNEAR.
+    pub locked: Balance,
+    /// The storage used by the account, in bytes.
+    pub storage_usage: StorageUsage,
+}
+
+impl From<&AccountView> for AccountBalance {
+    fn from(account: &AccountView) -> Self {
+        Self {
+            amount: account.amount,
+            locked: account.locked,
+            storage_usage: account.storage_usage,
+        }
+    }
+}
+
+/// The balances of an account before and after a change.
+///
+/// `before` is `None` when the account was created, `after` is `None` when it was deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BalanceDelta {
+    pub before: Option<AccountBalance>,
+    pub after: Option<AccountBalance>,
+}
+
+impl BalanceDelta {
+    /// The change in liquid balance, in yoctoNEAR.
+    pub fn amount_change(&self) -> i128 {
+        let amount = |balance: Option<AccountBalance>| balance.map_or(0, |b| b.amount as i128);
+        amount(self.after) - amount(self.before)
+    }
+
+    /// The change in locked balance, in yoctoNEAR.
+    pub fn locked_change(&self) -> i128 {
+        let locked = |balance: Option<AccountBalance>| balance.map_or(0, |b| b.locked as i128);
+        locked(self.after) - locked(self.before)
+    }
+}
+
+/// The state of the account, once known.
+type Known = Option<Option<AccountBalance>>;
+
+type Step = (
+    BlockHeight,
+    Known,
+    Option<Result<Vec<AccountChange>, AccountHistoryError>>,
+);
+
+/// A stream of changes to an account, in height order.
+///
+/// See the [`history`](self) module documentation for more information.
+pub struct AccountHistory {
+    client: JsonRpcClient,
+    account_id: AccountId,
+    cursor: BlockHeight,
+    end: BlockHeight,
+    storage_prefix: Option<Vec<u8>>,
+    known: Known,
+    buffered: VecDeque<AccountChange>,
+    pending: Option<BoxFuture<'static, Step>>,
+}
+
+impl JsonRpcClient {
+    /// Walk the changes to an account over a range of block heights.
+    ///
+    /// See the [`history`](crate::history) module documentation for more information.
+    pub fn account_history(
+        &self,
+        account_id: AccountId,
+        heights: RangeInclusive<BlockHeight>,
+    ) -> AccountHistory {
+        AccountHistory {
+            client: self.clone(),
+            account_id,
+            cursor: *heights.start(),
+            end: *heights.end(),
+            storage_prefix: Some(vec![]),
+            known: None,
+            buffered: VecDeque::new(),
+            pending: None,
+        }
+    }
+}
+
+impl AccountHistory {
+    /// Set whether or not to include changes to the contract storage.
+    ///
+    /// Defaults to `true`.
+    pub fn with_storage(mut self, with_storage: bool) -> Self {
+        self.storage_prefix = with_storage.then(Vec::new);
+        self
+    }
+
+    /// Only include changes to storage keys starting with `prefix`.
+    pub fn with_storage_prefix(mut self, prefix: impl Into<Vec<u8>>) -> Self {
+        self.storage_prefix = Some(prefix.into());
+        self
+    }
+
+    /// The height of the next block to be checked.
+    pub fn cursor(&self) -> BlockHeight {
+        self.cursor
+    }
+}
+
+impl Stream for AccountHistory {
+    type Item = Result<AccountChange, AccountHistoryError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            if let Some(change) = this.buffered.pop_front() {
+                return Poll::Ready(Some(Ok(change)));
+            }
+
+            let pending = this.pending.get_or_insert_with(|| {
+                next_changes(
+                    this.client.clone(),
+                    this.account_id.clone(),
+                    this.cursor,
+                    this.end,
+                    this.storage_prefix.clone(),
+                    this.known,
+                )
+                .boxed()
+            });
+
+            let (cursor, known, result) = futures::ready!(pending.poll_unpin(cx));
+            this.pending = None;
+            this.cursor = cursor;
+            this.known = known;
+
+            match result {
+                None => return Poll::Ready(None),
+                Some(Ok(changes)) => this.buffered.extend(changes),
+                Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for AccountHistory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AccountHistory")
+            .field("client", &self.client)
+            .field("account_id", &self.account_id)
+            .field("cursor", &self.cursor)
+            .field("end", &self.end)
+            .field("storage_prefix", &self.storage_prefix)
+            .field("buffered", &self.buffered.len())
+            .finish()
+    }
+}
+
+/// Fetch the changes in the first block at or above `cursor` touching the account, returning the
+/// updated cursor and known state of the account, or `None` once past the end of the range.
+async fn next_changes(
+    client: JsonRpcClient,
+    account_id: AccountId,
+    mut cursor: BlockHeight,
+    end: BlockHeight,
+    storage_prefix: Option<Vec<u8>>,
+    known: Known,
+) -> Step {
+    while cursor <= end {
+        let touched = client
+            .call(
+                methods::EXPERIMENTAL_changes_in_block::RpcStateChangesInBlockRequest {
+                    block_reference: BlockReference::BlockId(BlockId::Height(cursor)),
+                },
+            )
+            .await;
+
+        let touched = match touched {
+            Ok(touched) => touched,
+            Err(err) if err.is_unknown_block() => {
+                log::debug!("no block at #{}, skipping", cursor);
+                cursor += 1;
+                continue;
+            }
+            Err(err) => {
+                return (
+                    cursor,
+                    known,
+                    Some(Err(AccountHistoryError::ChangesError(err))),
+                )
+            }
+        };
+
+        let (mut account, mut keys, mut data) = (false, false, false);
+        for kind in &touched.changes {
+            match kind {
+                StateChangeKindView::AccountTouched { account_id: id } => {
+                    account |= *id == account_id
+                }
+                StateChangeKindView::AccessKeyTouched { account_id: id } => {
+                    keys |= *id == account_id
+                }
+                StateChangeKindView::DataTouched { account_id: id } => {
+                    data |= *id == account_id && storage_prefix.is_some()
+                }
+                StateChangeKindView::ContractCodeTouched { .. } => {}
+            }
+        }
+        if !(account || keys || data) {
+            cursor += 1;
+            continue;
+        }
+
+        let block_hash = touched.block_hash;
+        let mut requests = vec![];
+        if account {
+            requests.push(StateChangesRequestView::AccountChanges {
+                account_ids: vec![account_id.clone()],
+            });
+        }
+        if keys {
+            requests.push(StateChangesRequestView::AllAccessKeyChanges {
+                account_ids: vec![account_id.clone()],
+            });
+        }
+        if let (true, Some(prefix)) = (data, &storage_prefix) {
+            requests.push(StateChangesRequestView::DataChanges {
+                account_ids: vec![account_id.clone()],
+                key_prefix: StoreKey::from(prefix.clone()),
+            });
+        }
+
+        let responses = futures::future::try_join_all(requests.into_iter().map(|request| {
+            client.call(
+                methods::EXPERIMENTAL_changes::RpcStateChangesInBlockByTypeRequest {
+                    block_reference: BlockReference::BlockId(BlockId::Hash(block_hash)),
+                    state_changes_request: request,
+                },
+            )
+        }))
+        .await;
+        let responses = match responses {
+            Ok(responses) => responses,
+            Err(err) => {
+                return (
+                    cursor,
+                    known,
+                    Some(Err(AccountHistoryError::ChangesError(err))),
+                )
+            }
+        };
+
+        let mut before = match known {
+            Some(before) => before,
+            None if account => match balance_before(&client, &account_id, block_hash).await {
+                Ok(before) => before,
+                Err(err) => return (cursor, known, Some(Err(err))),
+            },
+            None => None,
+        };
+
+        let mut changes = vec![];
+        for change in responses.into_iter().flat_map(|response| response.changes) {
+            let delta = match change.value {
+                StateChangeValueView::AccountUpdate { account, .. } => {
+                    let after = Some(AccountBalance::from(&account));
+                    let delta = BalanceDelta { before, after };
+                    before = after;
+                    AccountDelta::Balance(delta)
+                }
+                StateChangeValueView::AccountDeletion { .. } => {
+                    let delta = BalanceDelta {
+                        before,
+                        after: None,
+                    };
+                    before = None;
+                    AccountDelta::Balance(delta)
+                }
+                StateChangeValueView::AccessKeyUpdate {
+                    public_key,
+                    access_key,
+                    ..
+                } => AccountDelta::AccessKey {
+                    public_key,
+                    access_key: Some(access_key),
+                },
+                StateChangeValueView::AccessKeyDeletion { public_key, .. } => {
+                    AccountDelta::AccessKey {
+                        public_key,
+                        access_key: None,
+                    }
+                }
+                StateChangeValueView::DataUpdate { key, value, .. } => AccountDelta::Storage {
+                    key: key.into(),
+                    value: Some(value.into()),
+                },
+                StateChangeValueView::DataDeletion { key, .. } => AccountDelta::Storage {
+                    key: key.into(),
+                    value: None,
+                },
+                StateChangeValueView::ContractCodeUpdate { .. }
+                | StateChangeValueView::ContractCodeDeletion { .. } => continue,
+            };
+            changes.push(AccountChange {
+                block_height: cursor,
+                block_hash,
+                cause: change.cause,
+                delta,
+            });
+        }
+        let known = if account { Some(before) } else { known };
+
+        return (cursor + 1, known, Some(Ok(changes)));
+    }
+
+    (cursor, known, None)
+}
+
+/// The balance of the account at the block preceding `block_hash`, `None` if it didn't exist.
+async fn balance_before(
+    client: &JsonRpcClient,
+    account_id: &AccountId,
+    block_hash: CryptoHash,
+) -> Result<Option<AccountBalance>, AccountHistoryError> {
+    let block = client
+        .call(methods::block::RpcBlockRequest {
+            block_reference: BlockReference::BlockId(BlockId::Hash(block_hash)),
+        })
+        .await
+        .map_err(AccountHistoryError::BlockError)?;
+
+    let response = client
+        .call(methods::query::RpcQueryRequest {
+            block_reference: BlockReference::BlockId(BlockId::Hash(block.header.prev_hash)),
+            request: QueryRequest::ViewAccount {
+                account_id: account_id.clone(),
+            },
+        })
+        .await;
+
+    match response {
+        Ok(response) => match response.kind {
+            QueryResponseKind::ViewAccount(account) => Ok(Some(AccountBalance::from(&account))),
+            _ => Ok(None),
+        },
+        Err(err) if err.is_not_found() => Ok(None),
+        Err(err) => Err(AccountHistoryError::QueryError(err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::testing::{fixtures, MockTransport};
+
+    fn at(height: BlockHeight) -> impl Fn(&Value) -> bool + Send + Sync + 'static {
+        move |params| params["block_id"] == height
+    }
+
+    /// A block with no chunks, following the block with the default hash.
+    #[tokio::test]
+    async fn walks_changes_to_the_account() {
+        let (hash_10, hash_13) = (CryptoHash::hash_bytes(b"10"), CryptoHash::hash_bytes(b"13"));
+        let tx_hash = CryptoHash::hash_bytes(b"tx");
+        let receipt_hash = CryptoHash::hash_bytes(b"receipt");
+
+        let mock = MockTransport::new();
+        mock.on("EXPERIMENTAL_changes_in_block")
+            .matching(at(10))
+            .respond(json!({
+                "block_hash": hash_10,
+                "changes": [
+                    { "type": "account_touched", "account_id": "alice.near" },
+                    { "type": "access_key_touched", "account_id": "alice.near" },
+                ],
+            }));
+        mock.on("EXPERIMENTAL_changes_in_block")
+            .matching(at(11))
+            .respond_error(json!({
+                "code": -32000,
+                "message": "Server error",
+                "name": "HANDLER_ERROR",
+                "cause": { "name": "UNKNOWN_BLOCK", "info": {} },
+            }));
+        mock.on("EXPERIMENTAL_changes_in_block")
+            .matching(at(12))
+            .respond(json!({
+                "block_hash": CryptoHash::hash_bytes(b"12"),
+                "changes": [{ "type": "account_touched", "account_id": "bob.near" }],
+            }));
+        mock.on("EXPERIMENTAL_changes_in_block")
+            .matching(at(13))
+            .respond(json!({
+                "block_hash": hash_13,
+                "changes": [{ "type": "account_touched", "account_id": "alice.near" }],
+            }));
+
+        let hash = |hash: CryptoHash| move |params: &Value| params["block_id"] == json!(hash);
+        mock.on("EXPERIMENTAL_changes")
+            .matching(move |params| hash(hash_10)(params) && params["changes_type"] == "account_changes")
+            .respond(json!({
+                "block_hash": hash_10,
+                "changes": [{
+                    "cause": { "type": "transaction_processing", "tx_hash": tx_hash },
+                    "type": "account_update",
+                    "change": { "account_id": "alice.near", "amount": "90", "locked": "0", "code_hash": CryptoHash::default(), "storage_usage": 100, "storage_paid_at": 0 },
+                }],
+            }));
+        mock.on("EXPERIMENTAL_changes")
+            .matching(move |params| hash(hash_10)(params) && params["changes_type"] == "all_access_key_changes")
+            .respond(json!({
+                "block_hash": hash_10,
+                "changes": [{
+                    "cause": { "type": "transaction_processing", "tx_hash": tx_hash },
+                    "type": "access_key_deletion",
+                    "change": { "account_id": "alice.near", "public_key": "ed25519:6DSjZ8mvsRZDvFqFxo8tCKePG96omXW7eVYVSySmDk8e" },
+                }],
+            }));
+        mock.on("EXPERIMENTAL_changes")
+            .matching(move |params| hash(hash_13)(params))
+            .respond(json!({
+                "block_hash": hash_13,
+                "changes": [{
+                    "cause": { "type": "receipt_processing", "receipt_hash": receipt_hash },
+                    "type": "account_update",
+                    "change": { "account_id": "alice.near", "amount": "140", "locked": "0", "code_hash": CryptoHash::default(), "storage_usage": 100, "storage_paid_at": 0 },
+                }],
+            }));
+        mock.on("block").respond(fixtures::block(10, vec![]));
+        mock.on("query").respond(json!({
+            "block_height": 9,
+            "block_hash": CryptoHash::default(),
+            "amount": "100",
+            "locked": "0",
+            "code_hash": CryptoHash::default(),
+            "storage_usage": 100,
+            "storage_paid_at": 0,
+        }));
+
+        let changes = mock
+            .client()
+            .account_history("alice.near".parse().unwrap(), 10..=13)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(changes.len(), 3);
+        assert_eq!(changes[0].block_height, 10);
+        assert_eq!(changes[0].transaction_hash(), Some(tx_hash));
+        assert!(matches!(
+            &changes[0].delta,
+            AccountDelta::Balance(delta) if delta.amount_change() == -10
+        ));
+        assert!(matches!(
+            &changes[1].delta,
+            AccountDelta::AccessKey {
+                access_key: None,
+                ..
+            }
+        ));
+        assert_eq!(changes[2].block_height, 13);
+        assert_eq!(changes[2].receipt_hash(), Some(receipt_hash));
+        assert!(matches!(
+            &changes[2].delta,
+            AccountDelta::Balance(delta) if delta.amount_change() == 50
+        ));
+
+        // the balance before the first change is only read once
+        let queries = mock.requests();
+        assert_eq!(
+            queries
+                .iter()
+                .filter(|request| request.method == "query")
+                .count(),
+            1
+        );
+    }
+}
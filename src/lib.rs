@@ -58,16 +58,43 @@
 //!    # Ok(())
 //!    # }
 //!    ```
-use std::{fmt, sync::Arc};
+
+use std::{fmt, sync::Arc, time::Instant};
 
 use lazy_static::lazy_static;
 
 pub mod auth;
+pub mod batch;
+pub mod cache;
+pub mod capabilities;
 pub mod errors;
+pub mod failover;
+pub mod fees;
 pub mod header;
+pub mod history;
+pub mod light_client;
 pub mod methods;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod middleware;
+pub mod options;
+pub mod rate_limit;
+pub mod retry;
+pub mod sender;
+pub mod state;
+pub mod state_proof;
+pub mod stream;
+#[cfg(feature = "tracing")]
+mod telemetry;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod trace;
+pub mod transport;
+pub mod view;
+pub mod wait;
 
 use errors::*;
+use near_jsonrpc_primitives::errors::RpcError;
 
 pub const NEAR_MAINNET_RPC_URL: &str = "https://rpc.mainnet.near.org";
 pub const NEAR_TESTNET_RPC_URL: &str = "https://rpc.testnet.near.org";
@@ -81,10 +108,59 @@ lazy_static! {
 /// NEAR JSON RPC client connector.
 #[derive(Clone)]
 pub struct JsonRpcClientConnector {
-    client: reqwest::Client,
+    transport: Arc<dyn transport::Transport>,
+    retry_policy: Option<Arc<retry::RetryPolicy>>,
+    middleware: Vec<Arc<dyn middleware::Middleware>>,
+    rate_limiter: Option<Arc<rate_limit::RateLimiter>>,
+    timeouts: Arc<options::Timeouts>,
 }
 
 impl JsonRpcClientConnector {
+    /// Retry requests that fail with transient errors on every client returned by this connector.
+    ///
+    /// See the [`retry`] module documentation for more information.
+    pub fn with_retry_policy(mut self, policy: retry::RetryPolicy) -> Self {
+        self.retry_policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Limit the rate of requests made by every client returned by this connector, per server.
+    ///
+    /// See the [`rate_limit`] module documentation for more information.
+    pub fn with_rate_limit(mut self, limit: rate_limit::RateLimit) -> Self {
+        self.rate_limiter = Some(Arc::new(rate_limit::RateLimiter::new(limit)));
+        self
+    }
+
+    /// Time out every attempt of every method call after `timeout`, on every client returned by this connector.
+    ///
+    /// See the [`options`] module documentation for more information.
+    pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+        Arc::make_mut(&mut self.timeouts).default = Some(timeout);
+        self
+    }
+
+    /// Time out every attempt of calls to the specified method after `timeout`, on every client
+    /// returned by this connector.
+    pub fn with_method_timeout<N: Into<String>>(
+        mut self,
+        method_name: N,
+        timeout: std::time::Duration,
+    ) -> Self {
+        Arc::make_mut(&mut self.timeouts)
+            .methods
+            .insert(method_name.into(), timeout);
+        self
+    }
+
+    /// Add middleware to every client returned by this connector.
+    ///
+    /// See the [`middleware`] module documentation for more information.
+    pub fn with_middleware<M: middleware::Middleware>(mut self, middleware: M) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
     /// Return a JsonRpcClient that connects to the specified server.
     pub fn connect<U: AsUrl>(&self, server_addr: U) -> JsonRpcClient {
         log::debug!("returned a new JSONRPC client handle");
@@ -92,16 +168,23 @@ impl JsonRpcClientConnector {
         JsonRpcClient {
             inner: Arc::new(JsonRpcInnerClient {
                 server_addr: server_addr.to_string(),
-                client: self.client.clone(),
+                transport: self.transport.clone(),
+                rate_limiter: self.rate_limiter.clone(),
+                capabilities: tokio::sync::OnceCell::new(),
             }),
             headers: reqwest::header::HeaderMap::new(),
+            retry_policy: self.retry_policy.clone(),
+            middleware: self.middleware.clone(),
+            timeouts: self.timeouts.clone(),
         }
     }
 }
 
 struct JsonRpcInnerClient {
     server_addr: String,
-    client: reqwest::Client,
+    transport: Arc<dyn transport::Transport>,
+    rate_limiter: Option<Arc<rate_limit::RateLimiter>>,
+    capabilities: tokio::sync::OnceCell<capabilities::Capabilities>,
 }
 
 #[derive(Clone)]
@@ -132,6 +215,9 @@ struct JsonRpcInnerClient {
 pub struct JsonRpcClient {
     inner: Arc<JsonRpcInnerClient>,
     headers: reqwest::header::HeaderMap,
+    retry_policy: Option<Arc<retry::RetryPolicy>>,
+    middleware: Vec<Arc<dyn middleware::Middleware>>,
+    timeouts: Arc<options::Timeouts>,
 }
 
 pub type MethodCallResult<T, E> = Result<T, JsonRpcError<E>>;
@@ -194,104 +280,261 @@ impl JsonRpcClient {
     where
         M: methods::RpcMethod,
     {
-        let request_payload = methods::to_json(&method).map_err(|err| {
-            JsonRpcError::TransportError(RpcTransportError::SendError(
-                JsonRpcTransportSendError::PayloadSerializeError(err),
-            ))
-        })?;
+        self.call_with(method, options::CallOptions::default())
+            .await
+    }
 
+    /// RPC method executor for the client, with options for this call only.
+    ///
+    /// See the [`options`] module documentation for more information.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use near_jsonrpc_client::{methods, options::CallOptions, JsonRpcClient};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let client = JsonRpcClient::connect("https://rpc.testnet.near.org");
+    ///
+    /// let options = CallOptions {
+    ///     timeout: Some(Duration::from_secs(30)),
+    ///     ..Default::default()
+    /// };
+    /// let response = client
+    ///     .call_with(methods::status::RpcStatusRequest, options)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn call_with<M>(
+        &self,
+        method: M,
+        options: options::CallOptions,
+    ) -> MethodCallResult<M::Response, M::Error>
+    where
+        M: methods::RpcMethod,
+    {
+        let call = middleware::CallInfo::new(method.method_name(), None);
+        let options = self.resolve_options(method.method_name(), options);
+
+        self.call_traced(call, |mut call| async move {
+            let result = match methods::to_json(&method) {
+                Ok(request_payload) => {
+                    call.set_params(request_payload["params"].clone());
+                    self.call_raw(
+                        &mut call,
+                        self.max_attempts(method.method_name()),
+                        request_payload,
+                        &options,
+                    )
+                    .await
+                    .and_then(parse_result::<M>)
+                }
+                Err(err) => Err(JsonRpcError::TransportError(RpcTransportError::SendError(
+                    JsonRpcTransportSendError::PayloadSerializeError(err),
+                ))),
+            };
+            (call, result)
+        })
+        .await
+    }
+
+    /// Make a call, running the middleware hooks around it, within its tracing span.
+    pub(crate) async fn call_traced<T, E, F>(
+        &self,
+        call: middleware::CallInfo,
+        send: impl FnOnce(middleware::CallInfo) -> F,
+    ) -> Result<T, JsonRpcError<E>>
+    where
+        E: 'static,
+        F: std::future::Future<Output = (middleware::CallInfo, Result<T, JsonRpcError<E>>)>,
+    {
+        #[cfg(feature = "tracing")]
+        let span = telemetry::span(&call, &self.inner.server_addr);
+        #[cfg(feature = "tracing")]
+        let recorded = span.clone();
+
+        let traced = async move {
+            self.middleware
+                .iter()
+                .for_each(|middleware| middleware.before_serialize(&call));
+
+            let (call, result) = send(call).await;
+
+            let outcome = match &result {
+                Ok(_) => middleware::CallOutcome::Ok,
+                Err(err) => middleware::CallOutcome::Err(middleware::CallError::new(err)),
+            };
+            self.middleware
+                .iter()
+                .rev()
+                .for_each(|middleware| middleware.after_parse(&call, outcome));
+
+            #[cfg(feature = "tracing")]
+            telemetry::record(&recorded, &call, &result);
+
+            result
+        };
+        #[cfg(feature = "tracing")]
+        let traced = tracing::Instrument::instrument(traced, span);
+
+        traced.await
+    }
+
+    /// Send a serialized JSON RPC payload, returning the raw result of a single method call.
+    pub(crate) async fn call_raw<E>(
+        &self,
+        call: &mut middleware::CallInfo,
+        max_attempts: u32,
+        request_payload: serde_json::Value,
+        options: &options::CallOptions,
+    ) -> Result<Result<serde_json::Value, RpcError>, JsonRpcError<E>> {
+        let response = self
+            .send_payload(call, max_attempts, request_payload, options)
+            .await?;
+
+        if let Some(err) = status_error(&response) {
+            return Err(JsonRpcError::ServerError(err));
+        }
+
+        let response_message = parse_message(&response.body)?;
+
+        if let near_jsonrpc_primitives::message::Message::Response(response) = response_message {
+            return Ok(response.result);
+        }
+        Err(JsonRpcError::TransportError(RpcTransportError::RecvError(
+            JsonRpcTransportRecvError::UnexpectedServerResponse(response_message),
+        )))
+    }
+
+    /// Send a serialized JSON RPC payload, retrying transient failures according to the retry policy.
+    #[allow(clippy::result_large_err)]
+    pub(crate) async fn send_payload<E>(
+        &self,
+        call: &mut middleware::CallInfo,
+        max_attempts: u32,
+        request_payload: serde_json::Value,
+        options: &options::CallOptions,
+    ) -> Result<transport::TransportResponse, JsonRpcError<E>> {
+        let mut headers = self.headers.clone();
+        headers.extend(options.headers.clone());
         log::debug!("request payload: {:#}", request_payload);
-        log::debug!("request headers: {:#?}", self.headers());
+        log::debug!("request headers: {:#?}", headers);
 
         let request_payload = serde_json::to_vec(&request_payload).map_err(|err| {
             JsonRpcError::TransportError(RpcTransportError::SendError(
                 JsonRpcTransportSendError::PayloadSerializeError(err.into()),
             ))
         })?;
+        call.set_request_size(request_payload.len());
 
-        let request = self
-            .inner
-            .client
-            .post(&self.inner.server_addr)
-            .headers(self.headers.clone())
-            .body(request_payload);
+        let request =
+            transport::TransportRequest::new(&self.inner.server_addr, headers, request_payload);
 
-        let response = request.send().await.map_err(|err| {
-            JsonRpcError::TransportError(RpcTransportError::SendError(
-                JsonRpcTransportSendError::PayloadSendError(err),
-            ))
-        })?;
-        log::debug!("response headers: {:#?}", response.headers());
-        match response.status() {
-            reqwest::StatusCode::OK => {}
-            non_ok_status => {
-                return Err(JsonRpcError::ServerError(match non_ok_status {
-                    reqwest::StatusCode::UNAUTHORIZED => JsonRpcServerError::ResponseStatusError(
-                        JsonRpcServerResponseStatusError::Unauthorized,
-                    ),
-                    reqwest::StatusCode::TOO_MANY_REQUESTS => {
-                        JsonRpcServerError::ResponseStatusError(
-                            JsonRpcServerResponseStatusError::TooManyRequests,
-                        )
-                    }
-                    reqwest::StatusCode::BAD_REQUEST => JsonRpcServerError::ResponseStatusError(
-                        JsonRpcServerResponseStatusError::BadRequest,
-                    ),
-                    reqwest::StatusCode::INTERNAL_SERVER_ERROR => {
-                        JsonRpcServerError::InternalError {
-                            info: Some(String::from("Internal server error")),
-                        }
-                    }
-                    reqwest::StatusCode::SERVICE_UNAVAILABLE => {
-                        JsonRpcServerError::ResponseStatusError(
-                            JsonRpcServerResponseStatusError::ServiceUnavailable,
-                        )
-                    }
-                    reqwest::StatusCode::REQUEST_TIMEOUT => {
-                        JsonRpcServerError::ResponseStatusError(
-                            JsonRpcServerResponseStatusError::TimeoutError,
-                        )
+        let mut attempt = 1;
+        let response = loop {
+            call.next_attempt();
+            let mut request = request.clone();
+            self.middleware
+                .iter()
+                .for_each(|middleware| middleware.before_send(call, &mut request));
+
+            let timed_out = || {
+                Err(RpcTransportError::TimeoutError(
+                    options.timeout_error(Instant::now()),
+                ))
+            };
+            // the timeout only starts once the rate limiter lets the request through,
+            // waiting on it is bounded by the deadline alone
+            let send = async {
+                let permit = match &self.inner.rate_limiter {
+                    Some(limiter) => limiter.acquire(&self.inner.server_addr, call).await,
+                    None => None,
+                };
+                let send = self.inner.transport.send(request);
+                let outcome = match options.time_left(Instant::now()) {
+                    Some(time_left) => tokio::time::timeout(time_left, send)
+                        .await
+                        .unwrap_or_else(|_| timed_out()),
+                    None => send.await,
+                };
+                drop(permit);
+                outcome
+            };
+            let outcome = match options.deadline {
+                Some(deadline) => tokio::time::timeout_at(deadline.into(), send)
+                    .await
+                    .unwrap_or_else(|_| timed_out()),
+                None => send.await,
+            };
+            if let Ok(response) = &outcome {
+                call.set_response(response);
+            }
+            if let Some(limiter) = &self.inner.rate_limiter {
+                limiter.observe(&self.inner.server_addr, &outcome);
+            }
+            self.middleware
+                .iter()
+                .rev()
+                .for_each(|middleware| middleware.after_receive(call, &outcome));
+
+            match (&self.retry_policy, &outcome) {
+                (Some(policy), _) if attempt < max_attempts && retry::is_transient(&outcome) => {
+                    let retry_after = outcome
+                        .as_ref()
+                        .ok()
+                        .and_then(|response| retry::retry_after(&response.headers));
+                    let delay = policy.delay(attempt, retry_after);
+                    if !options.allows(delay) {
+                        log::debug!(
+                            "attempt {}/{} failed, no time left to retry",
+                            attempt,
+                            max_attempts
+                        );
+                        break outcome.map_err(JsonRpcError::TransportError)?;
                     }
-                    unexpected => JsonRpcServerError::ResponseStatusError(
-                        JsonRpcServerResponseStatusError::Unexpected { status: unexpected },
-                    ),
-                }));
+                    log::debug!(
+                        "attempt {}/{} failed, retrying in {:?}",
+                        attempt,
+                        max_attempts,
+                        delay
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                _ => break outcome.map_err(JsonRpcError::TransportError)?,
             }
-        }
-        let response_payload = response.bytes().await.map_err(|err| {
-            JsonRpcError::TransportError(RpcTransportError::RecvError(
-                JsonRpcTransportRecvError::PayloadRecvError(err),
-            ))
-        })?;
-        let response_payload = serde_json::from_slice::<serde_json::Value>(&response_payload);
+        };
+        log::debug!("response headers: {:#?}", response.headers);
+
+        Ok(response)
+    }
 
-        if let Ok(ref response_payload) = response_payload {
-            log::debug!("response payload: {:#}", response_payload);
+    /// Fill in the timeout configured on the client for a method, if the call doesn't set one.
+    pub(crate) fn resolve_options(
+        &self,
+        method_name: &str,
+        mut options: options::CallOptions,
+    ) -> options::CallOptions {
+        if options.timeout.is_none() {
+            options.timeout = self.timeout_for(method_name);
         }
+        options
+    }
 
-        let response_message = near_jsonrpc_primitives::message::decoded_to_parsed(
-            response_payload.and_then(serde_json::from_value),
-        )
-        .map_err(|err| {
-            JsonRpcError::TransportError(RpcTransportError::RecvError(
-                JsonRpcTransportRecvError::PayloadParseError(err),
-            ))
-        })?;
+    /// The timeout configured on the client for a method, if any.
+    pub(crate) fn timeout_for(&self, method_name: &str) -> Option<std::time::Duration> {
+        self.timeouts.timeout_for(method_name)
+    }
 
-        if let near_jsonrpc_primitives::message::Message::Response(response) = response_message {
-            return M::parse_handler_response(response.result?)
-                .map_err(|err| {
-                    JsonRpcError::TransportError(RpcTransportError::RecvError(
-                        JsonRpcTransportRecvError::ResponseParseError(
-                            JsonRpcTransportHandlerResponseError::ResultParseError(err),
-                        ),
-                    ))
-                })?
-                .map_err(|err| JsonRpcError::ServerError(JsonRpcServerError::HandlerError(err)));
-        }
-        Err(JsonRpcError::TransportError(RpcTransportError::RecvError(
-            JsonRpcTransportRecvError::UnexpectedServerResponse(response_message),
-        )))
+    /// The maximum number of attempts for a method, according to the retry policy.
+    pub(crate) fn max_attempts(&self, method_name: &str) -> u32 {
+        self.retry_policy
+            .as_ref()
+            .map_or(1, |policy| policy.max_attempts_for(method_name))
     }
 
     /// Add a header to this request.
@@ -325,6 +568,71 @@ impl JsonRpcClient {
         D::apply(self, entry)
     }
 
+    /// Retry requests that fail with transient errors on this client.
+    ///
+    /// This overrides any policy inherited from the connector, see the [`retry`] module documentation
+    /// for more information.
+    ///
+    /// ### Example
+    ///
+    /// ```
+    /// use near_jsonrpc_client::{retry::RetryPolicy, JsonRpcClient};
+    ///
+    /// let client = JsonRpcClient::connect("https://rpc.testnet.near.org")
+    ///     .with_retry_policy(RetryPolicy::new().max_attempts(5));
+    /// ```
+    pub fn with_retry_policy(mut self, policy: retry::RetryPolicy) -> Self {
+        self.retry_policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Time out every attempt of every method call after `timeout`.
+    ///
+    /// This overrides any timeout inherited from the connector, see the [`options`] module documentation
+    /// for more information.
+    pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+        Arc::make_mut(&mut self.timeouts).default = Some(timeout);
+        self
+    }
+
+    /// Time out every attempt of calls to the specified method after `timeout`.
+    ///
+    /// ### Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use near_jsonrpc_client::JsonRpcClient;
+    ///
+    /// let client = JsonRpcClient::connect("https://rpc.testnet.near.org")
+    ///     .with_timeout(Duration::from_secs(10))
+    ///     .with_method_timeout("broadcast_tx_commit", Duration::from_secs(60))
+    ///     .with_method_timeout("send_tx", Duration::from_secs(60));
+    /// ```
+    pub fn with_method_timeout<N: Into<String>>(
+        mut self,
+        method_name: N,
+        timeout: std::time::Duration,
+    ) -> Self {
+        Arc::make_mut(&mut self.timeouts)
+            .methods
+            .insert(method_name.into(), timeout);
+        self
+    }
+
+    /// Add middleware to this client, after any inherited from the connector.
+    ///
+    /// See the [`middleware`] module documentation for more information.
+    pub fn with_middleware<M: middleware::Middleware>(mut self, middleware: M) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Get the retry policy configured on this client, if any.
+    pub fn retry_policy(&self) -> Option<&retry::RetryPolicy> {
+        self.retry_policy.as_deref()
+    }
+
     /// Get a shared reference to the headers.
     pub fn headers(&self) -> &reqwest::header::HeaderMap {
         &self.headers
@@ -360,12 +668,12 @@ impl JsonRpcClient {
         );
 
         log::debug!("initialized a new JSONRPC client connector");
-        JsonRpcClientConnector {
-            client: reqwest::Client::builder()
+        Self::with_transport(
+            reqwest::Client::builder()
                 .default_headers(headers)
                 .build()
                 .unwrap(),
-        }
+        )
     }
 
     /// Create a new client constructor using a custom web client.
@@ -388,8 +696,97 @@ impl JsonRpcClient {
     /// # }
     /// ```
     pub fn with(client: reqwest::Client) -> JsonRpcClientConnector {
-        JsonRpcClientConnector { client }
+        Self::with_transport(client)
+    }
+
+    /// Create a new client connector using a custom transport.
+    ///
+    /// This is useful if you want to send requests over something other than HTTP,
+    /// see the [`transport`] module documentation for more information.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use near_jsonrpc_client::JsonRpcClient;
+    ///
+    /// let web_client = reqwest::Client::new(); // <- any `transport::Transport` will do
+    ///
+    /// let testnet_client = JsonRpcClient::with_transport(web_client).connect("https://rpc.testnet.near.org");
+    /// ```
+    pub fn with_transport<T: transport::Transport>(transport: T) -> JsonRpcClientConnector {
+        JsonRpcClientConnector {
+            transport: Arc::new(transport),
+            retry_policy: None,
+            middleware: vec![],
+            rate_limiter: None,
+            timeouts: Arc::default(),
+        }
+    }
+}
+
+// `JsonRpcError` is large by design, boxing it would break every downstream match.
+#[allow(clippy::result_large_err)]
+fn parse_message<E>(
+    response_payload: &[u8],
+) -> Result<near_jsonrpc_primitives::message::Message, JsonRpcError<E>> {
+    let response_payload = serde_json::from_slice::<serde_json::Value>(response_payload);
+
+    if let Ok(ref response_payload) = response_payload {
+        log::debug!("response payload: {:#}", response_payload);
     }
+
+    near_jsonrpc_primitives::message::decoded_to_parsed(
+        response_payload.and_then(serde_json::from_value),
+    )
+    .map_err(|err| {
+        JsonRpcError::TransportError(RpcTransportError::RecvError(
+            JsonRpcTransportRecvError::PayloadParseError(err),
+        ))
+    })
+}
+
+#[allow(clippy::result_large_err)]
+fn parse_result<M: methods::RpcMethod>(
+    result: Result<serde_json::Value, RpcError>,
+) -> MethodCallResult<M::Response, M::Error> {
+    M::parse_handler_response(result?)
+        .map_err(|err| {
+            JsonRpcError::TransportError(RpcTransportError::RecvError(
+                JsonRpcTransportRecvError::ResponseParseError(err),
+            ))
+        })?
+        .map_err(|err| JsonRpcError::ServerError(JsonRpcServerError::HandlerError(err)))
+}
+
+fn status_error<E>(response: &transport::TransportResponse) -> Option<JsonRpcServerError<E>> {
+    Some(match response.status {
+        reqwest::StatusCode::OK => return None,
+        reqwest::StatusCode::UNAUTHORIZED => {
+            JsonRpcServerError::ResponseStatusError(JsonRpcServerResponseStatusError::Unauthorized)
+        }
+        reqwest::StatusCode::TOO_MANY_REQUESTS => JsonRpcServerError::ResponseStatusError(
+            JsonRpcServerResponseStatusError::TooManyRequests {
+                retry_after: retry::retry_after(&response.headers),
+            },
+        ),
+        reqwest::StatusCode::BAD_REQUEST => {
+            JsonRpcServerError::ResponseStatusError(JsonRpcServerResponseStatusError::BadRequest)
+        }
+        reqwest::StatusCode::INTERNAL_SERVER_ERROR => JsonRpcServerError::InternalError {
+            info: Some(String::from("Internal server error")),
+        },
+        reqwest::StatusCode::SERVICE_UNAVAILABLE => JsonRpcServerError::ResponseStatusError(
+            JsonRpcServerResponseStatusError::ServiceUnavailable,
+        ),
+        reqwest::StatusCode::REQUEST_TIMEOUT => {
+            JsonRpcServerError::ResponseStatusError(JsonRpcServerResponseStatusError::TimeoutError)
+        }
+        unexpected => {
+            JsonRpcServerError::ResponseStatusError(JsonRpcServerResponseStatusError::Unexpected {
+                status: unexpected,
+            })
+        }
+    })
 }
 
 impl fmt::Debug for JsonRpcClient {
@@ -397,7 +794,10 @@ impl fmt::Debug for JsonRpcClient {
         let mut builder = f.debug_struct("JsonRpcClient");
         builder.field("server_addr", &self.inner.server_addr);
         builder.field("headers", &self.headers);
-        builder.field("client", &self.inner.client);
+        builder.field("retry_policy", &self.retry_policy);
+        builder.field("middleware", &self.middleware);
+        builder.field("timeouts", &self.timeouts);
+        builder.field("transport", &self.inner.transport);
         builder.finish()
     }
 }
@@ -426,13 +826,95 @@ impl AsUrl for reqwest::Url {}
 
 #[cfg(test)]
 mod tests {
-    use crate::{methods, JsonRpcClient};
+    use serde_json::json;
+    #[cfg(feature = "any")]
+    use serde_json::Value;
+
+    use crate::methods;
+    use crate::testing::MockTransport;
+
+    #[cfg(feature = "any")]
+    const TX_HASH: &str = "9FtHUFBQsZ2MG77K3x3MJ9wjX3UT8zE1TczCrhZEcG8U";
+    #[cfg(feature = "any")]
+    const UNKNOWN_TX_HASH: &str = "9FtHUFBQsZ2MG77K3x3MJ9wjX3UT8zE1TczCrhZEcG8D";
+
+    /// Answers `tx` for [`TX_HASH`] sent by `miraclx.near`, and with an `UNKNOWN_TRANSACTION` error otherwise.
+    #[cfg(feature = "any")]
+    fn tx_status() -> MockTransport {
+        let outcome = |id: &str, receipt_ids: Value, status: Value| {
+            json!({
+                "proof": [],
+                "block_hash": "6RV4ibLSuEXVnjJjZwkt1fQt1n2XgMtDy3vh9gsCeeEf",
+                "id": id,
+                "outcome": {
+                    "logs": [],
+                    "receipt_ids": receipt_ids,
+                    "gas_burnt": 2428135649664u64,
+                    "tokens_burnt": "242813564966400000000",
+                    "executor_id": "miraclx.near",
+                    "status": status,
+                },
+            })
+        };
+        let receipt_id = "3B5PPT9EKj5352Wks9GnCeSUBDsVvSF4ceMQv2nEULTf";
+
+        let mock = MockTransport::new();
+        mock.on("tx")
+            .with_params(json!([TX_HASH, "miraclx.near"]))
+            .respond(json!({
+                "final_execution_status": "FINAL",
+                "status": { "SuccessValue": "" },
+                "transaction": {
+                    "signer_id": "miraclx.near",
+                    "public_key": "ed25519:6DSjZ8mvsRZDvFqFxo8tCKePG96omXW7eVYVSySmDk8e",
+                    "nonce": 1,
+                    "receiver_id": "miraclx.near",
+                    "actions": [{ "Transfer": { "deposit": "1" } }],
+                    "signature": near_crypto::Signature::empty(near_crypto::KeyType::ED25519),
+                    "hash": TX_HASH,
+                },
+                "transaction_outcome": outcome(TX_HASH, json!([receipt_id]), json!({ "SuccessReceiptId": receipt_id })),
+                "receipts_outcome": [outcome(receipt_id, json!([]), json!({ "SuccessValue": "" }))],
+            }));
+        mock.on("tx").respond_error(json!({
+            "code": -32000,
+            "message": "Server error",
+            "name": "HANDLER_ERROR",
+            "cause": {
+                "name": "UNKNOWN_TRANSACTION",
+                "info": { "requested_transaction_hash": UNKNOWN_TX_HASH },
+            },
+        }));
+        mock
+    }
 
     #[tokio::test]
-    async fn chk_status_testnet() {
-        let client = JsonRpcClient::connect("https://rpc.testnet.near.org");
+    async fn chk_status() {
+        let hash = near_primitives::hash::CryptoHash::default();
+        let mock = MockTransport::new();
+        mock.on("status").respond(json!({
+            "version": { "version": "2.4.0", "build": "2.4.0" },
+            "chain_id": "testnet",
+            "protocol_version": 73,
+            "latest_protocol_version": 73,
+            "validators": [],
+            "sync_info": {
+                "latest_block_hash": hash,
+                "latest_block_height": 1000,
+                "latest_state_root": hash,
+                "latest_block_time": "2024-01-01T00:00:00Z",
+                "syncing": false,
+                "earliest_block_hash": null,
+                "earliest_block_height": null,
+                "earliest_block_time": null,
+            },
+            "validator_account_id": null,
+            "node_public_key": "ed25519:6DSjZ8mvsRZDvFqFxo8tCKePG96omXW7eVYVSySmDk8e",
+            "uptime_sec": 1,
+            "genesis_hash": hash,
+        }));
 
-        let status = client.call(methods::status::RpcStatusRequest).await;
+        let status = mock.client().call(methods::status::RpcStatusRequest).await;
 
         assert!(
             matches!(status, Ok(methods::status::RpcStatusResponse { .. })),
@@ -444,24 +926,21 @@ mod tests {
     #[tokio::test]
     #[cfg(feature = "any")]
     async fn any_typed_ok() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let client = JsonRpcClient::connect("https://archival-rpc.mainnet.near.org");
+        let client = tx_status().client();
 
         let tx_status = client
             .call(methods::any::<methods::tx::RpcTransactionStatusRequest>(
                 "tx",
-                serde_json::json!([
-                    "9FtHUFBQsZ2MG77K3x3MJ9wjX3UT8zE1TczCrhZEcG8U",
-                    "miraclx.near",
-                ]),
+                json!([TX_HASH, "miraclx.near"]),
             ))
             .await;
 
         assert!(
             matches!(
                 tx_status,
-                Ok(methods::tx::RpcTransactionResponse { ref final_execution_outcome, .. })
-                if final_execution_outcome.unwrap().into_outcome().transaction.signer_id == "miraclx.near"
-                && final_execution_outcome.unwrap().into_outcome().transaction.hash == "9FtHUFBQsZ2MG77K3x3MJ9wjX3UT8zE1TczCrhZEcG8U".parse()?
+                Ok(methods::tx::RpcTransactionResponse { final_execution_outcome: Some(ref outcome), .. })
+                if outcome.clone().into_outcome().transaction.signer_id == "miraclx.near"
+                && outcome.clone().into_outcome().transaction.hash == TX_HASH.parse()?
             ),
             "expected an Ok(RpcTransactionStatusResponse) with matching signer_id + hash, found [{:?}]",
             tx_status
@@ -473,15 +952,12 @@ mod tests {
     #[tokio::test]
     #[cfg(feature = "any")]
     async fn any_typed_err() -> Result<(), Box<dyn std::error::Error>> {
-        let client = JsonRpcClient::connect("https://archival-rpc.mainnet.near.org");
+        let client = tx_status().client();
 
         let tx_error = client
             .call(methods::any::<methods::tx::RpcTransactionStatusRequest>(
                 "tx",
-                serde_json::json!([
-                    "9FtHUFBQsZ2MG77K3x3MJ9wjX3UT8zE1TczCrhZEcG8D",
-                    "youser.near",
-                ]),
+                json!([UNKNOWN_TX_HASH, "youser.near"]),
             ))
             .await
             .expect_err("request must not succeed");
@@ -492,7 +968,7 @@ mod tests {
                 Some(methods::tx::RpcTransactionError::UnknownTransaction {
                     requested_transaction_hash
                 })
-                if requested_transaction_hash.to_string() == "9FtHUFBQsZ2MG77K3x3MJ9wjX3UT8zE1TczCrhZEcG8D"
+                if requested_transaction_hash.to_string() == UNKNOWN_TX_HASH
             ),
             "expected an Ok(RpcTransactionError::UnknownTransaction) with matching hash, found [{:?}]",
             tx_error
@@ -504,18 +980,13 @@ mod tests {
     #[tokio::test]
     #[cfg(feature = "any")]
     async fn any_untyped_ok() {
-        let client = JsonRpcClient::connect("https://archival-rpc.mainnet.near.org");
+        let client = tx_status().client();
 
         let status = client
-            .call(
-                methods::any::<Result<serde_json::Value, serde_json::Value>>(
-                    "tx",
-                    serde_json::json!([
-                        "9FtHUFBQsZ2MG77K3x3MJ9wjX3UT8zE1TczCrhZEcG8U",
-                        "miraclx.near",
-                    ]),
-                ),
-            )
+            .call(methods::any::<Result<Value, Value>>(
+                "tx",
+                json!([TX_HASH, "miraclx.near"]),
+            ))
             .await
             .expect("request must not fail");
 
@@ -525,7 +996,7 @@ mod tests {
             status
         );
         assert_eq!(
-            status["transaction"]["hash"], "9FtHUFBQsZ2MG77K3x3MJ9wjX3UT8zE1TczCrhZEcG8U",
+            status["transaction"]["hash"], TX_HASH,
             "expected a tx_status with matching hash, [{:#}]",
             status
         );
@@ -534,18 +1005,13 @@ mod tests {
     #[tokio::test]
     #[cfg(feature = "any")]
     async fn any_untyped_err() {
-        let client = JsonRpcClient::connect("https://archival-rpc.mainnet.near.org");
+        let client = tx_status().client();
 
         let tx_error = client
-            .call(
-                methods::any::<Result<serde_json::Value, serde_json::Value>>(
-                    "tx",
-                    serde_json::json!([
-                        "9FtHUFBQsZ2MG77K3x3MJ9wjX3UT8zE1TczCrhZEcG8D",
-                        "youser.near",
-                    ]),
-                ),
-            )
+            .call(methods::any::<Result<Value, Value>>(
+                "tx",
+                json!([UNKNOWN_TX_HASH, "youser.near"]),
+            ))
             .await
             .expect_err("request must not succeed");
         let tx_error = tx_error
@@ -553,8 +1019,7 @@ mod tests {
             .expect("expected a handler error from query request");
 
         assert_eq!(
-            tx_error["info"]["requested_transaction_hash"],
-            "9FtHUFBQsZ2MG77K3x3MJ9wjX3UT8zE1TczCrhZEcG8D",
+            tx_error["info"]["requested_transaction_hash"], UNKNOWN_TX_HASH,
             "expected an error with matching hash, [{:#}]",
             tx_error
         );
@@ -0,0 +1,438 @@
+//! Light client verification.
+//!
+//! A [`LightClient`] keeps a trusted [`LightClientBlockView`] head, and only moves it forward to blocks
+//! that are endorsed by more than two thirds of the stake of their epoch's block producers.
+//! Once a head is trusted, execution proofs returned by [`light_client_proof`](crate::methods::light_client_proof)
+//! can be checked against its block merkle root, without trusting the RPC server they came from.
+//!
+//! This follows the [light client specification](https://nomicon.io/ChainSpec/LightClient).
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use near_jsonrpc_client::{light_client::LightClient, methods, JsonRpcClient};
+//! use near_primitives::types::TransactionOrReceiptId;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+//! # let (trusted_head, block_producers) = unimplemented!();
+//! let client = JsonRpcClient::connect("https://rpc.mainnet.near.org");
+//!
+//! // a head and the block producers of its epoch, obtained out of band
+//! let mut light_client = LightClient::new(trusted_head, block_producers);
+//!
+//! while light_client.sync(&client).await? {}
+//!
+//! let proof = client
+//!     .call(methods::light_client_proof::RpcLightClientExecutionProofRequest {
+//!         id: TransactionOrReceiptId::Transaction {
+//!             transaction_hash: "47sXP4jKXCMpkUS6kcxsfNU7tqysYr5eZZfbLPTwv4xf".parse()?,
+//!             sender_id: "aurora.near".parse()?,
+//!         },
+//!         light_client_head: light_client.head_hash(),
+//!     })
+//!     .await?;
+//!
+//! light_client.verify_execution_proof(&proof)?;
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::HashMap;
+
+use near_primitives::block_header::{Approval, ApprovalInner, BlockHeaderInnerLite};
+use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::merkle::{
+    combine_hash, compute_root_from_path, compute_root_from_path_and_item,
+};
+use near_primitives::types::{AccountId, Balance, BlockHeight};
+use near_primitives::views::validator_stake_view::ValidatorStakeView;
+use near_primitives::views::LightClientBlockView;
+use thiserror::Error;
+
+use crate::errors::JsonRpcError;
+use crate::methods::light_client_proof::RpcLightClientExecutionProofResponse;
+use crate::methods::next_light_client_block::RpcLightClientNextBlockError;
+use crate::{methods, JsonRpcClient};
+
+/// Potential errors returned while verifying light client blocks and proofs.
+#[derive(Debug, Error)]
+pub enum LightClientError {
+    /// The block isn't ahead of the current head.
+    #[error("block #{height} isn't ahead of the head at #{head_height}")]
+    NotAhead {
+        height: BlockHeight,
+        head_height: BlockHeight,
+    },
+    /// The block belongs to an epoch whose block producers aren't known.
+    #[error("block producers of epoch [{epoch_id}] are unknown")]
+    UnknownEpoch { epoch_id: CryptoHash },
+    /// The block is the first one of the next epoch, but doesn't say who produces the epoch after.
+    #[error("block starts a new epoch, but lacks the next block producers")]
+    MissingNextBlockProducers,
+    /// The next block producers don't hash to the `next_bp_hash` of the block.
+    #[error("next block producers don't match the block's next_bp_hash")]
+    NextBlockProducersMismatch,
+    /// An approval doesn't carry a valid signature of its block producer.
+    #[error("invalid approval signature from [{account_id}]")]
+    InvalidSignature { account_id: AccountId },
+    /// Not enough of the stake approved the block.
+    #[error("block approved by {approved} of {total} stake, more than 2/3 is required")]
+    InsufficientApprovals { approved: Balance, total: Balance },
+    /// The outcome doesn't hash up to the outcome root of its block.
+    #[error("execution outcome doesn't match the outcome root of its block")]
+    OutcomeRootMismatch,
+    /// The block of the outcome doesn't hash up to the trusted block merkle root.
+    #[error("block of the execution outcome doesn't match the trusted block merkle root")]
+    BlockMerkleRootMismatch,
+    /// The next light client block couldn't be fetched.
+    #[error(transparent)]
+    RpcError(JsonRpcError<RpcLightClientNextBlockError>),
+}
+
+/// A light client, tracking a trusted head.
+///
+/// See the [`light_client`](self) module documentation for more information.
+#[derive(Debug, Clone)]
+pub struct LightClient {
+    head: LightClientBlockView,
+    block_producers: HashMap<CryptoHash, Vec<ValidatorStakeView>>,
+}
+
+impl LightClient {
+    /// Create a light client from a trusted head, and the block producers of its epoch.
+    ///
+    /// If the head carries `next_bps`, the block producers of the next epoch are taken from there.
+    pub fn new(head: LightClientBlockView, block_producers: Vec<ValidatorStakeView>) -> Self {
+        let mut this = Self {
+            head,
+            block_producers: HashMap::new(),
+        };
+        this.block_producers
+            .insert(this.head.inner_lite.epoch_id, block_producers);
+        if let Some(next_bps) = &this.head.next_bps {
+            this.block_producers
+                .insert(this.head.inner_lite.next_epoch_id, next_bps.clone());
+        }
+        this
+    }
+
+    /// The current trusted head.
+    pub fn head(&self) -> &LightClientBlockView {
+        &self.head
+    }
+
+    /// The hash of the current trusted head.
+    pub fn head_hash(&self) -> CryptoHash {
+        block_hash(&self.head)
+    }
+
+    /// Check that a block can follow the current head, without moving the head.
+    #[allow(clippy::result_large_err)]
+    pub fn validate(&self, block: &LightClientBlockView) -> Result<(), LightClientError> {
+        let head = &self.head.inner_lite;
+        let inner_lite = &block.inner_lite;
+
+        if inner_lite.height <= head.height {
+            return Err(LightClientError::NotAhead {
+                height: inner_lite.height,
+                head_height: head.height,
+            });
+        }
+
+        if inner_lite.epoch_id != head.epoch_id && inner_lite.epoch_id != head.next_epoch_id {
+            return Err(LightClientError::UnknownEpoch {
+                epoch_id: inner_lite.epoch_id,
+            });
+        }
+
+        if inner_lite.epoch_id == head.next_epoch_id && block.next_bps.is_none() {
+            return Err(LightClientError::MissingNextBlockProducers);
+        }
+
+        let block_producers = self.block_producers.get(&inner_lite.epoch_id).ok_or(
+            LightClientError::UnknownEpoch {
+                epoch_id: inner_lite.epoch_id,
+            },
+        )?;
+
+        let next_block_hash = combine_hash(&block.next_block_inner_hash, &block_hash(block));
+        let message = Approval::get_data_for_sig(
+            &ApprovalInner::Endorsement(next_block_hash),
+            inner_lite.height + 2,
+        );
+
+        let mut total: Balance = 0;
+        let mut approved: Balance = 0;
+        for (index, block_producer) in block_producers.iter().enumerate() {
+            let block_producer = block_producer.clone().into_validator_stake();
+            total += block_producer.stake();
+
+            let Some(Some(signature)) = block.approvals_after_next.get(index) else {
+                continue;
+            };
+            if !signature.verify(&message, block_producer.public_key()) {
+                return Err(LightClientError::InvalidSignature {
+                    account_id: block_producer.account_id().clone(),
+                });
+            }
+            approved += block_producer.stake();
+        }
+
+        if approved * 3 <= total * 2 {
+            return Err(LightClientError::InsufficientApprovals { approved, total });
+        }
+
+        if let Some(next_bps) = &block.next_bps {
+            if CryptoHash::hash_borsh(next_bps) != inner_lite.next_bp_hash {
+                return Err(LightClientError::NextBlockProducersMismatch);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Validate a block, and make it the new head.
+    #[allow(clippy::result_large_err)]
+    pub fn update(&mut self, block: LightClientBlockView) -> Result<(), LightClientError> {
+        self.validate(&block)?;
+        if let Some(next_bps) = &block.next_bps {
+            self.block_producers
+                .insert(block.inner_lite.next_epoch_id, next_bps.clone());
+        }
+        // only the current and next epochs are ever needed
+        let (epoch_id, next_epoch_id) = (block.inner_lite.epoch_id, block.inner_lite.next_epoch_id);
+        self.block_producers
+            .retain(|id, _| *id == epoch_id || *id == next_epoch_id);
+        self.head = block;
+        Ok(())
+    }
+
+    /// Fetch the next light client block from the server, and make it the new head.
+    ///
+    /// Returns `false` if the head is already up to date.
+    pub async fn sync(&mut self, client: &JsonRpcClient) -> Result<bool, LightClientError> {
+        let block = client
+            .call(
+                methods::next_light_client_block::RpcLightClientNextBlockRequest {
+                    last_block_hash: self.head_hash(),
+                },
+            )
+            .await
+            .map_err(LightClientError::RpcError)?;
+
+        match block {
+            Some(block) if block.inner_lite.height > self.head.inner_lite.height => {
+                self.update(block)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Check an execution proof against the block merkle root of the current head.
+    ///
+    /// The proof must've been requested with the current head as `light_client_head`.
+    #[allow(clippy::result_large_err)]
+    pub fn verify_execution_proof(
+        &self,
+        proof: &RpcLightClientExecutionProofResponse,
+    ) -> Result<(), LightClientError> {
+        verify_execution_proof(proof, self.head.inner_lite.block_merkle_root)
+    }
+}
+
+/// Check an execution proof against a known block merkle root.
+#[allow(clippy::result_large_err)]
+pub fn verify_execution_proof(
+    proof: &RpcLightClientExecutionProofResponse,
+    block_merkle_root: CryptoHash,
+) -> Result<(), LightClientError> {
+    let outcome_hash = CryptoHash::hash_borsh(proof.outcome_proof.to_hashes());
+    let shard_outcome_root = compute_root_from_path(&proof.outcome_proof.proof, outcome_hash);
+    let outcome_root =
+        compute_root_from_path_and_item(&proof.outcome_root_proof, shard_outcome_root);
+    if outcome_root != proof.block_header_lite.inner_lite.outcome_root {
+        return Err(LightClientError::OutcomeRootMismatch);
+    }
+
+    let block_root = compute_root_from_path(&proof.block_proof, proof.block_header_lite.hash());
+    if block_root != block_merkle_root {
+        return Err(LightClientError::BlockMerkleRootMismatch);
+    }
+
+    Ok(())
+}
+
+/// Compute the hash of a light client block.
+pub fn block_hash(block: &LightClientBlockView) -> CryptoHash {
+    let inner_lite = BlockHeaderInnerLite::from(block.inner_lite.clone());
+    combine_hash(
+        &combine_hash(
+            &hash(&borsh::to_vec(&inner_lite).expect("inner lite header is serializable")),
+            &block.inner_rest_hash,
+        ),
+        &block.prev_block_hash,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use near_crypto::{InMemorySigner, KeyType};
+    use near_primitives::views::validator_stake_view::ValidatorStakeViewV1;
+    use near_primitives::views::{
+        BlockHeaderInnerLiteView, ExecutionOutcomeView, ExecutionOutcomeWithIdView,
+        ExecutionStatusView, LightClientBlockLiteView,
+    };
+
+    use super::*;
+
+    fn signers(n: usize) -> Vec<InMemorySigner> {
+        (0..n)
+            .map(|i| {
+                let account_id: AccountId = format!("bp{}.near", i).parse().unwrap();
+                InMemorySigner::from_seed(account_id.clone(), KeyType::ED25519, account_id.as_str())
+            })
+            .collect()
+    }
+
+    fn block_producers(signers: &[InMemorySigner]) -> Vec<ValidatorStakeView> {
+        signers
+            .iter()
+            .map(|signer| {
+                ValidatorStakeView::V1(ValidatorStakeViewV1 {
+                    account_id: signer.account_id.clone(),
+                    public_key: signer.public_key(),
+                    stake: 100,
+                })
+            })
+            .collect()
+    }
+
+    fn inner_lite(height: BlockHeight, epoch_id: CryptoHash) -> BlockHeaderInnerLiteView {
+        BlockHeaderInnerLiteView {
+            height,
+            epoch_id,
+            next_epoch_id: hash(epoch_id.as_ref()),
+            prev_state_root: CryptoHash::default(),
+            outcome_root: CryptoHash::default(),
+            timestamp: 0,
+            timestamp_nanosec: 0,
+            next_bp_hash: CryptoHash::default(),
+            block_merkle_root: CryptoHash::default(),
+        }
+    }
+
+    /// A block approved by the first `approvals` signers.
+    fn block(
+        height: BlockHeight,
+        epoch_id: CryptoHash,
+        signers: &[InMemorySigner],
+        approvals: usize,
+    ) -> LightClientBlockView {
+        let mut block = LightClientBlockView {
+            prev_block_hash: CryptoHash::default(),
+            next_block_inner_hash: hash(&height.to_le_bytes()),
+            inner_lite: inner_lite(height, epoch_id),
+            inner_rest_hash: CryptoHash::default(),
+            next_bps: None,
+            approvals_after_next: vec![],
+        };
+        let next_block_hash = combine_hash(&block.next_block_inner_hash, &block_hash(&block));
+        let message =
+            Approval::get_data_for_sig(&ApprovalInner::Endorsement(next_block_hash), height + 2);
+        block.approvals_after_next = signers
+            .iter()
+            .enumerate()
+            .map(|(i, signer)| (i < approvals).then(|| Box::new(signer.sign(&message))))
+            .collect();
+        block
+    }
+
+    #[test]
+    fn follows_approved_blocks() {
+        let signers = signers(4);
+        let epoch_id = hash(b"epoch");
+        let mut light_client =
+            LightClient::new(block(10, epoch_id, &signers, 4), block_producers(&signers));
+
+        // 2 of 4 isn't more than 2/3
+        assert!(matches!(
+            light_client.update(block(11, epoch_id, &signers, 2)),
+            Err(LightClientError::InsufficientApprovals {
+                approved: 200,
+                total: 400
+            })
+        ));
+
+        light_client
+            .update(block(11, epoch_id, &signers, 3))
+            .expect("block must be accepted");
+        assert_eq!(light_client.head().inner_lite.height, 11);
+
+        assert!(matches!(
+            light_client.update(block(11, epoch_id, &signers, 4)),
+            Err(LightClientError::NotAhead { .. })
+        ));
+
+        // a forged signature
+        let mut forged = block(12, epoch_id, &signers, 4);
+        forged.approvals_after_next[0] = Some(Box::new(signers[0].sign(b"something else")));
+        assert!(matches!(
+            light_client.update(forged),
+            Err(LightClientError::InvalidSignature { .. })
+        ));
+
+        // the next epoch's block producers aren't known
+        assert!(matches!(
+            light_client.update(block(12, hash(epoch_id.as_ref()), &signers, 4)),
+            Err(LightClientError::MissingNextBlockProducers)
+        ));
+    }
+
+    #[test]
+    fn verifies_execution_proofs() {
+        let outcome_proof = ExecutionOutcomeWithIdView {
+            proof: vec![],
+            block_hash: CryptoHash::default(),
+            id: hash(b"transaction"),
+            outcome: ExecutionOutcomeView {
+                logs: vec!["transferred".to_string()],
+                receipt_ids: vec![],
+                gas_burnt: 0,
+                tokens_burnt: 0,
+                executor_id: "alice.near".parse().unwrap(),
+                status: ExecutionStatusView::SuccessValue(vec![]),
+                metadata: Default::default(),
+            },
+        };
+        let shard_outcome_root = CryptoHash::hash_borsh(outcome_proof.to_hashes());
+
+        let mut block_header_lite = LightClientBlockLiteView {
+            prev_block_hash: CryptoHash::default(),
+            inner_rest_hash: CryptoHash::default(),
+            inner_lite: inner_lite(7, CryptoHash::default()),
+        };
+        block_header_lite.inner_lite.outcome_root = CryptoHash::hash_borsh(shard_outcome_root);
+        let block_merkle_root = block_header_lite.hash();
+
+        let mut proof = RpcLightClientExecutionProofResponse {
+            outcome_proof,
+            outcome_root_proof: vec![],
+            block_header_lite,
+            block_proof: vec![],
+        };
+
+        verify_execution_proof(&proof, block_merkle_root).expect("proof must be valid");
+
+        assert!(matches!(
+            verify_execution_proof(&proof, CryptoHash::default()),
+            Err(LightClientError::BlockMerkleRootMismatch)
+        ));
+
+        proof.outcome_proof.outcome.logs.clear();
+        assert!(matches!(
+            verify_execution_proof(&proof, block_merkle_root),
+            Err(LightClientError::OutcomeRootMismatch)
+        ));
+    }
+}
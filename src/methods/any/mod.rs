@@ -82,7 +82,7 @@ impl<T, E> private::Sealed for RpcAnyRequest<T, E> {}
 impl<T, E> RpcMethod for RpcAnyRequest<T, E>
 where
     T: RpcHandlerResponse,
-    E: RpcHandlerError,
+    E: RpcHandlerError + 'static,
 {
     type Response = T;
     type Error = E;
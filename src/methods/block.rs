@@ -75,6 +75,14 @@ impl RpcHandlerError for RpcBlockError {
     fn parse(value: serde_json::Value) -> Result<Self, serde_json::Error> {
         common::parse_unknown_block!(value => Self)
     }
+
+    fn is_retryable(&self) -> bool {
+        matches!(self, Self::NotSyncedYet)
+    }
+
+    fn is_unknown_block(&self) -> bool {
+        matches!(self, Self::UnknownBlock { .. })
+    }
 }
 
 impl RpcMethod for RpcBlockRequest {
@@ -102,6 +102,14 @@ impl RpcHandlerError for RpcChunkError {
     fn parse(value: serde_json::Value) -> Result<Self, serde_json::Error> {
         common::parse_unknown_block!(value => Self)
     }
+
+    fn is_unknown_block(&self) -> bool {
+        matches!(self, Self::UnknownBlock { .. })
+    }
+
+    fn is_not_found(&self) -> bool {
+        matches!(self, Self::UnknownChunk { .. })
+    }
 }
 
 impl RpcMethod for RpcChunkRequest {
@@ -0,0 +1,96 @@
+//! Returns the configuration of the client the RPC node runs.
+//!
+//! The node serializes the complete `near_chain_configs::ClientConfig`, which can't be deserialized,
+//! so only the most common fields are typed, every other one is kept as is.
+//!
+//! ## Example
+//!
+//! ```
+//! use near_jsonrpc_client::{methods, JsonRpcClient};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = JsonRpcClient::connect("https://rpc.testnet.near.org");
+//!
+//! let request = methods::client_config::RpcClientConfigRequest;
+//!
+//! let response = client.call(request).await?;
+//!
+//! assert_eq!(response.chain_id, "testnet");
+//! # Ok(())
+//! # }
+//! ```
+use super::*;
+
+pub use near_jsonrpc_primitives::types::client_config::RpcClientConfigError;
+
+#[derive(Debug)]
+pub struct RpcClientConfigRequest;
+
+/// The configuration of the client the RPC node runs.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RpcClientConfigResponse {
+    /// The version of the node.
+    pub version: near_primitives::version::Version,
+    /// The chain the node is on.
+    pub chain_id: String,
+    /// Whether or not the node is an archival node.
+    #[serde(default)]
+    pub archive: bool,
+    /// The number of blocks in an epoch.
+    pub epoch_length: near_primitives::types::BlockHeightDelta,
+    /// The accounts the node tracks.
+    #[serde(default)]
+    pub tracked_accounts: Vec<near_primitives::types::AccountId>,
+    /// The shards the node tracks.
+    #[serde(default)]
+    pub tracked_shards: Vec<near_primitives::types::ShardId>,
+    /// Every other field of the configuration.
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+impl RpcHandlerResponse for RpcClientConfigResponse {}
+
+impl RpcHandlerError for RpcClientConfigError {}
+
+impl RpcMethod for RpcClientConfigRequest {
+    type Response = RpcClientConfigResponse;
+    type Error = RpcClientConfigError;
+
+    fn method_name(&self) -> &str {
+        "client_config"
+    }
+
+    fn params(&self) -> Result<serde_json::Value, io::Error> {
+        Ok(json!(null))
+    }
+}
+
+impl private::Sealed for RpcClientConfigRequest {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockTransport;
+
+    #[tokio::test]
+    async fn keeps_untyped_fields() {
+        let mock = MockTransport::new();
+        mock.on("client_config").respond(json!({
+            "version": { "version": "2.4.0", "build": "2.4.0", "rustc_version": "1.82.0" },
+            "chain_id": "testnet",
+            "archive": true,
+            "epoch_length": 43200,
+            "tracked_shards": [0],
+            "gc": { "gc_blocks_limit": 2 },
+        }));
+
+        let response = mock.client().call(RpcClientConfigRequest).await.unwrap();
+        assert_eq!(response.chain_id, "testnet");
+        assert!(response.archive);
+        assert_eq!(response.tracked_shards.len(), 1);
+        assert!(response.tracked_accounts.is_empty());
+        assert_eq!(response.other["gc"]["gc_blocks_limit"], 2);
+    }
+}
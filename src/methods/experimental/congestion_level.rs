@@ -0,0 +1,51 @@
+//! Returns the congestion level of a shard, from `0.0` to `1.0`, as of a chunk.
+//!
+//! The chunk is referenced the same way as with the [`chunk`](crate::methods::chunk) method, and
+//! the method fails with the same errors.
+//!
+//! ## Example
+//!
+//! ```
+//! use near_jsonrpc_client::{methods, JsonRpcClient};
+//! use near_jsonrpc_primitives::types::chunks::ChunkReference;
+//! use near_primitives::types::{BlockId, ShardId};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+//! let client = JsonRpcClient::connect("https://rpc.mainnet.near.org");
+//!
+//! let request = methods::EXPERIMENTAL_congestion_level::RpcCongestionLevelRequest {
+//!     chunk_reference: ChunkReference::BlockShardId {
+//!         block_id: BlockId::Height(130_000_000),
+//!         shard_id: ShardId::new(0),
+//!     },
+//! };
+//!
+//! let response = client.call(request).await?;
+//!
+//! assert!((0.0..=1.0).contains(&response.congestion_level));
+//! # Ok(())
+//! # }
+//! ```
+use super::*;
+
+pub use near_jsonrpc_primitives::types::congestion::{
+    RpcCongestionLevelError, RpcCongestionLevelRequest, RpcCongestionLevelResponse,
+};
+
+impl RpcHandlerResponse for RpcCongestionLevelResponse {}
+
+impl RpcMethod for RpcCongestionLevelRequest {
+    type Response = RpcCongestionLevelResponse;
+    type Error = RpcCongestionLevelError;
+
+    fn method_name(&self) -> &str {
+        "EXPERIMENTAL_congestion_level"
+    }
+
+    fn params(&self) -> Result<serde_json::Value, io::Error> {
+        Ok(json!(self))
+    }
+}
+
+impl private::Sealed for RpcCongestionLevelRequest {}
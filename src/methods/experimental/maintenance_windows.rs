@@ -0,0 +1,50 @@
+//! Returns the windows in the current epoch where a validator isn't expected to produce anything.
+//!
+//! Each window is a range of block heights, as a `(start, end)` pair. Nodes
+//! can be safely restarted in any of these.
+//!
+//! ## Example
+//!
+//! ```
+//! use near_jsonrpc_client::{methods, JsonRpcClient};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+//! let client = JsonRpcClient::connect("https://rpc.mainnet.near.org");
+//!
+//! let request = methods::EXPERIMENTAL_maintenance_windows::RpcMaintenanceWindowsRequest {
+//!     account_id: "aurora.pool.near".parse()?,
+//! };
+//!
+//! let windows = client.call(request).await?;
+//!
+//! for (start, end) in windows {
+//!     println!("safe to restart between #{} and #{}", start, end);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use super::*;
+
+pub use near_jsonrpc_primitives::types::maintenance::{
+    RpcMaintenanceWindowsError, RpcMaintenanceWindowsRequest, RpcMaintenanceWindowsResponse,
+};
+
+impl RpcHandlerResponse for RpcMaintenanceWindowsResponse {}
+
+impl RpcHandlerError for RpcMaintenanceWindowsError {}
+
+impl RpcMethod for RpcMaintenanceWindowsRequest {
+    type Response = RpcMaintenanceWindowsResponse;
+    type Error = RpcMaintenanceWindowsError;
+
+    fn method_name(&self) -> &str {
+        "EXPERIMENTAL_maintenance_windows"
+    }
+
+    fn params(&self) -> Result<serde_json::Value, io::Error> {
+        Ok(json!(self))
+    }
+}
+
+impl private::Sealed for RpcMaintenanceWindowsRequest {}
@@ -6,15 +6,27 @@ pub use changes as EXPERIMENTAL_changes;
 pub mod changes_in_block;
 pub use changes_in_block as EXPERIMENTAL_changes_in_block;
 
+pub mod congestion_level;
+pub use congestion_level as EXPERIMENTAL_congestion_level;
+
 pub mod genesis_config;
 pub use genesis_config as EXPERIMENTAL_genesis_config;
 
+pub mod light_client_block_proof;
+pub use light_client_block_proof as EXPERIMENTAL_light_client_block_proof;
+
+pub mod maintenance_windows;
+pub use maintenance_windows as EXPERIMENTAL_maintenance_windows;
+
 pub mod protocol_config;
 pub use protocol_config as EXPERIMENTAL_protocol_config;
 
 pub mod receipt;
 pub use receipt as EXPERIMENTAL_receipt;
 
+pub mod split_storage_info;
+pub use split_storage_info as EXPERIMENTAL_split_storage_info;
+
 pub mod tx_status;
 pub use tx_status as EXPERIMENTAL_tx_status;
 
@@ -41,6 +41,10 @@ impl RpcHandlerError for RpcProtocolConfigError {
     fn parse(value: serde_json::Value) -> Result<Self, serde_json::Error> {
         common::parse_unknown_block!(value => Self)
     }
+
+    fn is_unknown_block(&self) -> bool {
+        matches!(self, Self::UnknownBlock { .. })
+    }
 }
 
 impl RpcMethod for RpcProtocolConfigRequest {
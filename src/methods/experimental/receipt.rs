@@ -37,7 +37,11 @@ pub type RpcReceiptResponse = near_primitives::views::ReceiptView;
 
 impl RpcHandlerResponse for RpcReceiptResponse {}
 
-impl RpcHandlerError for RpcReceiptError {}
+impl RpcHandlerError for RpcReceiptError {
+    fn is_not_found(&self) -> bool {
+        matches!(self, Self::UnknownReceipt { .. })
+    }
+}
 
 impl RpcMethod for RpcReceiptRequest {
     type Response = RpcReceiptResponse;
@@ -0,0 +1,48 @@
+//! Returns the split storage information of an archival node.
+//!
+//! That's the heights of the hot and cold storage heads, and the kind of the hot database.
+//!
+//! ## Example
+//!
+//! ```
+//! use near_jsonrpc_client::{methods, JsonRpcClient};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = JsonRpcClient::connect("https://archival-rpc.mainnet.near.org");
+//!
+//! let request = methods::EXPERIMENTAL_split_storage_info::RpcSplitStorageInfoRequest {};
+//!
+//! let response = client.call(request).await?;
+//!
+//! assert!(matches!(
+//!     response,
+//!     methods::EXPERIMENTAL_split_storage_info::RpcSplitStorageInfoResponse { .. }
+//! ));
+//! # Ok(())
+//! # }
+//! ```
+use super::*;
+
+pub use near_jsonrpc_primitives::types::split_storage::{
+    RpcSplitStorageInfoError, RpcSplitStorageInfoRequest, RpcSplitStorageInfoResponse,
+};
+
+impl RpcHandlerResponse for RpcSplitStorageInfoResponse {}
+
+impl RpcHandlerError for RpcSplitStorageInfoError {}
+
+impl RpcMethod for RpcSplitStorageInfoRequest {
+    type Response = RpcSplitStorageInfoResponse;
+    type Error = RpcSplitStorageInfoError;
+
+    fn method_name(&self) -> &str {
+        "EXPERIMENTAL_split_storage_info"
+    }
+
+    fn params(&self) -> Result<serde_json::Value, io::Error> {
+        Ok(json!(self))
+    }
+}
+
+impl private::Sealed for RpcSplitStorageInfoRequest {}
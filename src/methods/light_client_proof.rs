@@ -38,6 +38,18 @@ impl RpcHandlerError for RpcLightClientProofError {
     fn parse(value: serde_json::Value) -> Result<Self, serde_json::Error> {
         common::parse_unknown_block!(value => Self)
     }
+
+    fn is_retryable(&self) -> bool {
+        matches!(self, Self::NotConfirmed { .. })
+    }
+
+    fn is_unknown_block(&self) -> bool {
+        matches!(self, Self::UnknownBlock { .. })
+    }
+
+    fn is_not_found(&self) -> bool {
+        matches!(self, Self::UnknownTransactionOrReceipt { .. })
+    }
 }
 
 impl RpcMethod for RpcLightClientExecutionProofRequest {
@@ -5,6 +5,8 @@ use serde::Deserialize;
 use serde_json::json;
 use thiserror::Error;
 
+use crate::errors::JsonRpcTransportHandlerResponseError;
+
 mod private {
     pub trait Sealed {}
 }
@@ -16,7 +18,7 @@ where
     Self::Error: RpcHandlerError,
 {
     type Response;
-    type Error;
+    type Error: 'static;
 
     fn method_name(&self) -> &str;
 
@@ -24,8 +26,10 @@ where
 
     fn parse_handler_response(
         response: serde_json::Value,
-    ) -> Result<Result<Self::Response, Self::Error>, serde_json::Error> {
-        Self::Response::parse(response).map(Ok)
+    ) -> Result<Result<Self::Response, Self::Error>, JsonRpcTransportHandlerResponseError> {
+        Self::Response::parse(response)
+            .map(Ok)
+            .map_err(JsonRpcTransportHandlerResponseError::ResultParseError)
     }
 }
 
@@ -47,7 +51,7 @@ where
 
     fn parse_handler_response(
         response: serde_json::Value,
-    ) -> Result<Result<Self::Response, Self::Error>, serde_json::Error> {
+    ) -> Result<Result<Self::Response, Self::Error>, JsonRpcTransportHandlerResponseError> {
         T::parse_handler_response(response)
     }
 }
@@ -74,12 +78,34 @@ pub trait RpcHandlerError: serde::de::DeserializeOwned {
     fn parse_legacy_error(_error: serde_json::Value) -> Option<Result<Self, serde_json::Error>> {
         None
     }
+
+    /// Whether or not the same call may succeed if tried again later, like when the node is still syncing.
+    ///
+    /// Defaults to `false`.
+    fn is_retryable(&self) -> bool {
+        false
+    }
+
+    /// Whether or not the block referenced isn't known to the node, or was garbage collected.
+    ///
+    /// Defaults to `false`.
+    fn is_unknown_block(&self) -> bool {
+        false
+    }
+
+    /// Whether or not what was requested, other than a block, doesn't exist, like an account or transaction.
+    ///
+    /// Defaults to `false`.
+    fn is_not_found(&self) -> bool {
+        false
+    }
 }
 
 pub mod block;
 pub mod broadcast_tx_async;
 pub mod broadcast_tx_commit;
 pub mod chunk;
+pub mod client_config;
 pub mod gas_price;
 pub mod health;
 pub mod light_client_proof;
@@ -95,9 +121,13 @@ pub mod validators;
 mod experimental;
 pub use experimental::EXPERIMENTAL_changes;
 pub use experimental::EXPERIMENTAL_changes_in_block;
+pub use experimental::EXPERIMENTAL_congestion_level;
 pub use experimental::EXPERIMENTAL_genesis_config;
+pub use experimental::EXPERIMENTAL_light_client_block_proof;
+pub use experimental::EXPERIMENTAL_maintenance_windows;
 pub use experimental::EXPERIMENTAL_protocol_config;
 pub use experimental::EXPERIMENTAL_receipt;
+pub use experimental::EXPERIMENTAL_split_storage_info;
 pub use experimental::EXPERIMENTAL_tx_status;
 pub use experimental::EXPERIMENTAL_validators_ordered;
 // ======== experimental ========
@@ -225,21 +255,49 @@ mod common {
                 _ => None,
             }
         }
+
+        fn is_retryable(&self) -> bool {
+            matches!(self, Self::RequestRouted { .. } | Self::TimeoutError)
+        }
+
+        fn is_not_found(&self) -> bool {
+            matches!(self, Self::UnknownTransaction { .. })
+        }
     }
 
     // health, status
-    impl RpcHandlerError for near_jsonrpc_primitives::types::status::RpcStatusError {}
+    impl RpcHandlerError for near_jsonrpc_primitives::types::status::RpcStatusError {
+        fn is_retryable(&self) -> bool {
+            matches!(self, Self::NodeIsSyncing | Self::NoNewBlocks { .. })
+        }
+    }
 
     // EXPERIMENTAL_changes, EXPERIMENTAL_changes_in_block
     impl RpcHandlerError for near_jsonrpc_primitives::types::changes::RpcStateChangesError {
         fn parse(value: serde_json::Value) -> Result<Self, serde_json::Error> {
             parse_unknown_block!(value => Self)
         }
+
+        fn is_retryable(&self) -> bool {
+            matches!(self, Self::NotSyncedYet)
+        }
+
+        fn is_unknown_block(&self) -> bool {
+            matches!(self, Self::UnknownBlock { .. })
+        }
     }
 
     // send_tx
     impl RpcHandlerResponse for near_jsonrpc_primitives::types::transactions::RpcTransactionResponse {}
 
     // validators, EXPERIMENTAL_validators_ordered
-    impl RpcHandlerError for near_jsonrpc_primitives::types::validator::RpcValidatorError {}
+    impl RpcHandlerError for near_jsonrpc_primitives::types::validator::RpcValidatorError {
+        fn is_retryable(&self) -> bool {
+            matches!(self, Self::ValidatorInfoUnavailable)
+        }
+
+        fn is_not_found(&self) -> bool {
+            matches!(self, Self::UnknownEpoch)
+        }
+    }
 }
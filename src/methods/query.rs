@@ -12,6 +12,11 @@
 //! - View the `AccessKeyList` of an account
 //! - Call a function in a contract deployed on the network.
 //!
+//! Except for function calls, each of these also has a typed request ([`ViewAccountRequest`], [`ViewCodeRequest`],
+//! [`ViewStateRequest`], [`ViewAccessKeyRequest`] and [`ViewAccessKeyListRequest`]) whose response is narrowed
+//! to the matching view. If the server responds with a view of another kind, the call fails with an
+//! [`UnexpectedQueryKind`](crate::errors::JsonRpcTransportHandlerResponseError::UnexpectedQueryKind) error.
+//!
 //! ## Examples
 //!
 //! ### Returns basic account information.
@@ -194,9 +199,32 @@ use super::*;
 
 pub use near_jsonrpc_primitives::types::query::{RpcQueryError, RpcQueryRequest, RpcQueryResponse};
 
+mod typed;
+pub use typed::*;
+
 impl RpcHandlerResponse for RpcQueryResponse {}
 
-impl RpcHandlerError for RpcQueryError {}
+impl RpcHandlerError for RpcQueryError {
+    fn is_retryable(&self) -> bool {
+        matches!(self, Self::NoSyncedBlocks)
+    }
+
+    fn is_unknown_block(&self) -> bool {
+        matches!(
+            self,
+            Self::UnknownBlock { .. } | Self::GarbageCollectedBlock { .. }
+        )
+    }
+
+    fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            Self::UnknownAccount { .. }
+                | Self::NoContractCode { .. }
+                | Self::UnknownAccessKey { .. }
+        )
+    }
+}
 
 impl private::Sealed for RpcQueryRequest {}
 
@@ -214,8 +242,10 @@ impl RpcMethod for RpcQueryRequest {
 
     fn parse_handler_response(
         response: serde_json::Value,
-    ) -> Result<Result<Self::Response, Self::Error>, serde_json::Error> {
-        match serde_json::from_value::<QueryResponse>(response)? {
+    ) -> Result<Result<Self::Response, Self::Error>, JsonRpcTransportHandlerResponseError> {
+        match serde_json::from_value::<QueryResponse>(response)
+            .map_err(JsonRpcTransportHandlerResponseError::ResultParseError)?
+        {
             QueryResponse::HandlerResponse(r) => Ok(Ok(r)),
             QueryResponse::HandlerError(LegacyQueryError {
                 error,
@@ -244,9 +274,11 @@ impl RpcMethod for RpcQueryRequest {
                     err_parts.next(),
                     err_parts.next(),
                 ) {
-                    let public_key = pk
-                        .parse::<near_crypto::PublicKey>()
-                        .map_err(serde::de::Error::custom)?;
+                    let public_key = pk.parse::<near_crypto::PublicKey>().map_err(|err| {
+                        JsonRpcTransportHandlerResponseError::ErrorMessageParseError(
+                            serde::de::Error::custom(err),
+                        )
+                    })?;
                     RpcQueryError::UnknownAccessKey {
                         public_key,
                         block_height,
@@ -282,12 +314,27 @@ struct LegacyQueryError {
 
 #[cfg(test)]
 mod tests {
-    use {super::*, crate::*};
+    use serde_json::json;
+
+    use {super::*, crate::testing::MockTransport};
+
+    /// Answers every query with a legacy error, as servers do for contract and access key errors.
+    fn legacy_error(error: &str) -> MockTransport {
+        let mock = MockTransport::new();
+        mock.on("query").respond(json!({
+            "error": error,
+            "logs": [],
+            "block_height": 63503911,
+            "block_hash": "6RV4ibLSuEXVnjJjZwkt1fQt1n2XgMtDy3vh9gsCeeEf",
+        }));
+        mock
+    }
 
     /// This test is to make sure the method executor treats `&RpcMethod`s the same as `RpcMethod`s.
     #[tokio::test]
     async fn test_unknown_method() -> Result<(), Box<dyn std::error::Error>> {
-        let client = JsonRpcClient::connect("https://rpc.testnet.near.org");
+        let mock = legacy_error("wasm execution failed with error: FunctionCallError(MethodResolveError(MethodNotFound))");
+        let client = mock.client();
 
         let request = RpcQueryRequest {
             block_reference: near_primitives::types::BlockReference::latest(),
@@ -311,13 +358,21 @@ mod tests {
             "this is unexpected: {:#?}",
             response_err
         );
+        assert_eq!(
+            mock.requests()[0].params,
+            json!(request),
+            "borrowed requests must be sent like owned ones"
+        );
 
         Ok(())
     }
 
     #[tokio::test]
     async fn test_unknown_access_key() -> Result<(), Box<dyn std::error::Error>> {
-        let client = JsonRpcClient::connect("https://archival-rpc.testnet.near.org");
+        let client = legacy_error(
+            "access key ed25519:9KnjTjL6vVoM8heHvCcTgLZ67FwFkiLsNtknFAVsVvYY does not exist while viewing",
+        )
+        .client();
 
         let request = RpcQueryRequest {
             block_reference: near_primitives::types::BlockReference::BlockId(
@@ -349,7 +404,10 @@ mod tests {
 
     #[tokio::test]
     async fn test_contract_execution_error() -> Result<(), Box<dyn std::error::Error>> {
-        let client = JsonRpcClient::connect("https://archival-rpc.testnet.near.org");
+        let client = legacy_error(
+            "wasm execution failed with error: FunctionCallError(MethodResolveError(MethodEmptyName))",
+        )
+        .client();
 
         let request = RpcQueryRequest {
             block_reference: near_primitives::types::BlockReference::BlockId(
@@ -0,0 +1,330 @@
+//! Typed `query` requests.
+//!
+//! Each request here wraps a single [`QueryRequest`] variant, and its response is narrowed to the view
+//! that variant returns, alongside the block it was computed at. If the server responds with a view of
+//! another kind, the call fails with an
+//! [`UnexpectedQueryKind`](crate::errors::JsonRpcTransportHandlerResponseError::UnexpectedQueryKind)
+//! error instead of leaving it to the caller to check.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use near_jsonrpc_client::{methods, JsonRpcClient};
+//! use near_primitives::types::{BlockReference, Finality};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = JsonRpcClient::connect("https://rpc.testnet.near.org");
+//!
+//! let request = methods::query::ViewAccessKeyRequest {
+//!     block_reference: BlockReference::Finality(Finality::Final),
+//!     account_id: "miraclx.testnet".parse()?,
+//!     public_key: "ed25519:9KnjTjL6vVoM8heHvCcTgLZ67FwFkiLsNtknFAVsVvYY".parse()?,
+//! };
+//!
+//! let response = client.call(request).await?;
+//!
+//! println!("nonce {} as of #{}", response.view.nonce, response.block_height);
+//! # Ok(())
+//! # }
+//! ```
+use super::*;
+
+use near_jsonrpc_primitives::types::query::QueryResponseKind;
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{AccountId, BlockHeight, BlockReference, StoreKey};
+use near_primitives::views::{
+    AccessKeyList, AccessKeyView, AccountView, ContractCodeView, QueryRequest, ViewStateResult,
+};
+
+/// A view that's returned by exactly one kind of query.
+pub trait QueryKind: private::Sealed + Sized {
+    /// The name of the query kind, as it appears in `request_type`.
+    const NAME: &'static str;
+
+    /// Extract the view, returning the response kind as is if it's another one.
+    fn from_kind(kind: QueryResponseKind) -> Result<Self, QueryResponseKind>;
+}
+
+/// The response to a typed query.
+#[derive(Debug, Clone)]
+pub struct QueryView<T> {
+    /// The view returned by the query.
+    pub view: T,
+    /// The height of the block the query was executed at.
+    pub block_height: BlockHeight,
+    /// The hash of the block the query was executed at.
+    pub block_hash: CryptoHash,
+}
+
+pub type ViewAccountResponse = QueryView<AccountView>;
+pub type ViewAccessKeyResponse = QueryView<AccessKeyView>;
+pub type ViewAccessKeyListResponse = QueryView<AccessKeyList>;
+pub type ViewCodeResponse = QueryView<ContractCodeView>;
+pub type ViewStateResponse = QueryView<ViewStateResult>;
+
+impl<T: QueryKind> QueryView<T> {
+    fn narrow(response: RpcQueryResponse) -> Result<Self, JsonRpcTransportHandlerResponseError> {
+        match T::from_kind(response.kind) {
+            Ok(view) => Ok(Self {
+                view,
+                block_height: response.block_height,
+                block_hash: response.block_hash,
+            }),
+            Err(kind) => Err(JsonRpcTransportHandlerResponseError::UnexpectedQueryKind {
+                expected: T::NAME,
+                received: Box::new(kind),
+            }),
+        }
+    }
+}
+
+impl<'de, T: QueryKind> serde::Deserialize<'de> for QueryView<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Self::narrow(RpcQueryResponse::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}
+
+impl<T: QueryKind> RpcHandlerResponse for QueryView<T> {}
+
+/// Parse a `query` response, sharing the legacy error handling of [`RpcQueryRequest`].
+fn parse_query_response<T: QueryKind>(
+    response: serde_json::Value,
+) -> Result<Result<QueryView<T>, RpcQueryError>, JsonRpcTransportHandlerResponseError> {
+    Ok(match RpcQueryRequest::parse_handler_response(response)? {
+        Ok(response) => Ok(QueryView::narrow(response)?),
+        Err(err) => Err(err),
+    })
+}
+
+macro_rules! query_kind {
+    ($view:ty => $variant:ident, $name:literal) => {
+        impl private::Sealed for $view {}
+
+        impl QueryKind for $view {
+            const NAME: &'static str = $name;
+
+            fn from_kind(kind: QueryResponseKind) -> Result<Self, QueryResponseKind> {
+                match kind {
+                    QueryResponseKind::$variant(view) => Ok(view),
+                    kind => Err(kind),
+                }
+            }
+        }
+    };
+}
+
+query_kind!(AccountView => ViewAccount, "view_account");
+query_kind!(AccessKeyView => AccessKey, "view_access_key");
+query_kind!(AccessKeyList => AccessKeyList, "view_access_key_list");
+query_kind!(ContractCodeView => ViewCode, "view_code");
+query_kind!(ViewStateResult => ViewState, "view_state");
+
+/// Returns basic account information.
+#[derive(Debug, Clone)]
+pub struct ViewAccountRequest {
+    pub block_reference: BlockReference,
+    pub account_id: AccountId,
+}
+
+/// Returns information about a single access key of an account.
+#[derive(Debug, Clone)]
+pub struct ViewAccessKeyRequest {
+    pub block_reference: BlockReference,
+    pub account_id: AccountId,
+    pub public_key: near_crypto::PublicKey,
+}
+
+/// Returns all access keys of an account.
+#[derive(Debug, Clone)]
+pub struct ViewAccessKeyListRequest {
+    pub block_reference: BlockReference,
+    pub account_id: AccountId,
+}
+
+/// Returns the contract code deployed to an account.
+#[derive(Debug, Clone)]
+pub struct ViewCodeRequest {
+    pub block_reference: BlockReference,
+    pub account_id: AccountId,
+}
+
+/// Returns the contract state of an account, for keys starting with `prefix`.
+#[derive(Debug, Clone)]
+pub struct ViewStateRequest {
+    pub block_reference: BlockReference,
+    pub account_id: AccountId,
+    pub prefix: StoreKey,
+    pub include_proof: bool,
+}
+
+impl From<&ViewAccountRequest> for RpcQueryRequest {
+    fn from(this: &ViewAccountRequest) -> Self {
+        Self {
+            block_reference: this.block_reference.clone(),
+            request: QueryRequest::ViewAccount {
+                account_id: this.account_id.clone(),
+            },
+        }
+    }
+}
+
+impl From<&ViewAccessKeyRequest> for RpcQueryRequest {
+    fn from(this: &ViewAccessKeyRequest) -> Self {
+        Self {
+            block_reference: this.block_reference.clone(),
+            request: QueryRequest::ViewAccessKey {
+                account_id: this.account_id.clone(),
+                public_key: this.public_key.clone(),
+            },
+        }
+    }
+}
+
+impl From<&ViewAccessKeyListRequest> for RpcQueryRequest {
+    fn from(this: &ViewAccessKeyListRequest) -> Self {
+        Self {
+            block_reference: this.block_reference.clone(),
+            request: QueryRequest::ViewAccessKeyList {
+                account_id: this.account_id.clone(),
+            },
+        }
+    }
+}
+
+impl From<&ViewCodeRequest> for RpcQueryRequest {
+    fn from(this: &ViewCodeRequest) -> Self {
+        Self {
+            block_reference: this.block_reference.clone(),
+            request: QueryRequest::ViewCode {
+                account_id: this.account_id.clone(),
+            },
+        }
+    }
+}
+
+impl From<&ViewStateRequest> for RpcQueryRequest {
+    fn from(this: &ViewStateRequest) -> Self {
+        Self {
+            block_reference: this.block_reference.clone(),
+            request: QueryRequest::ViewState {
+                account_id: this.account_id.clone(),
+                prefix: this.prefix.clone(),
+                include_proof: this.include_proof,
+            },
+        }
+    }
+}
+
+macro_rules! typed_query {
+    ($request:ty => $view:ty) => {
+        impl private::Sealed for $request {}
+
+        impl RpcMethod for $request {
+            type Response = QueryView<$view>;
+            type Error = RpcQueryError;
+
+            fn method_name(&self) -> &str {
+                "query"
+            }
+
+            fn params(&self) -> Result<serde_json::Value, io::Error> {
+                Ok(json!(RpcQueryRequest::from(self)))
+            }
+
+            fn parse_handler_response(
+                response: serde_json::Value,
+            ) -> Result<Result<Self::Response, Self::Error>, JsonRpcTransportHandlerResponseError>
+            {
+                parse_query_response(response)
+            }
+        }
+    };
+}
+
+typed_query!(ViewAccountRequest => AccountView);
+typed_query!(ViewAccessKeyRequest => AccessKeyView);
+typed_query!(ViewAccessKeyListRequest => AccessKeyList);
+typed_query!(ViewCodeRequest => ContractCodeView);
+typed_query!(ViewStateRequest => ViewStateResult);
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::types::Finality;
+    use reqwest::StatusCode;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::errors::*;
+    use crate::transport::{Transport, TransportFuture, TransportRequest, TransportResponse};
+    use crate::JsonRpcClient;
+
+    /// Responds to every query with an access key.
+    #[derive(Debug)]
+    struct AccessKeys;
+
+    impl Transport for AccessKeys {
+        fn send(&self, request: TransportRequest) -> TransportFuture<'_> {
+            let request = serde_json::from_slice::<Value>(&request.body).unwrap();
+            assert_eq!(request["method"], "query");
+            assert_eq!(request["params"]["finality"], "final");
+
+            let message = json!({
+                "jsonrpc": "2.0",
+                "id": "dontcare",
+                "result": {
+                    "nonce": 41,
+                    "permission": "FullAccess",
+                    "block_height": 7,
+                    "block_hash": CryptoHash::default(),
+                },
+            });
+            Box::pin(async move {
+                Ok(TransportResponse::new(
+                    StatusCode::OK,
+                    message.to_string().into(),
+                ))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn narrows_response_kind() {
+        let client = JsonRpcClient::with_transport(AccessKeys).connect("http://localhost:3030");
+
+        let response = client
+            .call(ViewAccessKeyRequest {
+                block_reference: BlockReference::Finality(Finality::Final),
+                account_id: "alice.near".parse().unwrap(),
+                public_key: near_crypto::PublicKey::empty(near_crypto::KeyType::ED25519),
+            })
+            .await
+            .expect("access key must be returned");
+
+        assert_eq!(response.view.nonce, 41);
+        assert_eq!(response.block_height, 7);
+
+        let response = client
+            .call(ViewAccountRequest {
+                block_reference: BlockReference::Finality(Finality::Final),
+                account_id: "alice.near".parse().unwrap(),
+            })
+            .await;
+
+        assert!(
+            matches!(
+                response,
+                Err(JsonRpcError::TransportError(RpcTransportError::RecvError(
+                    JsonRpcTransportRecvError::ResponseParseError(
+                        JsonRpcTransportHandlerResponseError::UnexpectedQueryKind {
+                            expected: "view_account",
+                            ref received,
+                        }
+                    )
+                ))) if matches!(**received, QueryResponseKind::AccessKey(_))
+            ),
+            "expected an UnexpectedQueryKind error, found [{:?}]",
+            response
+        );
+    }
+}
@@ -0,0 +1,236 @@
+//! Per-method call metrics.
+//!
+//! [`Metrics`] is a [`Middleware`] counting, for every method called, the calls made, the errors by
+//! [`ErrorClass`], the bytes sent and received, and the latency of calls in a histogram. Register it
+//! on a client or connector, keeping a clone around to read a [`snapshot`](Metrics::snapshot) of the
+//! metrics collected so far, to export them however fits.
+//!
+//! Batches are counted as a single `batch` call, calls a batch falls back to sending individually are
+//! counted under their own method.
+//!
+//! ## Example
+//!
+//! ```
+//! use near_jsonrpc_client::{metrics::Metrics, JsonRpcClient};
+//!
+//! let metrics = Metrics::new();
+//! let client = JsonRpcClient::connect("https://rpc.testnet.near.org").with_middleware(metrics.clone());
+//!
+//! for (method, metrics) in metrics.snapshot() {
+//!     println!(
+//!         "{}: {} calls, {} errors, {:?} total latency",
+//!         method,
+//!         metrics.calls,
+//!         metrics.errors.values().sum::<u64>(),
+//!         metrics.latency.sum
+//!     );
+//! }
+//! ```
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::errors::ErrorClass;
+use crate::middleware::{CallInfo, CallOutcome, Middleware};
+
+/// The upper bounds of the latency histogram buckets, in milliseconds.
+pub const LATENCY_BUCKETS_MS: [u64; 11] = [5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+/// Middleware collecting metrics for every method called.
+///
+/// Clones share the same metrics.
+///
+/// See the [`metrics`](self) module documentation for more information.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    methods: Arc<Mutex<BTreeMap<String, MethodMetrics>>>,
+}
+
+/// The metrics collected for a method.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MethodMetrics {
+    /// The number of calls made, successful or not.
+    pub calls: u64,
+    /// The number of failed calls, by class of error.
+    pub errors: BTreeMap<ErrorClass, u64>,
+    /// The total size of the requests sent, retries included, in bytes.
+    pub request_bytes: u64,
+    /// The total size of the responses received, retries included, in bytes.
+    pub response_bytes: u64,
+    /// The latency of calls, retries included.
+    pub latency: Histogram,
+}
+
+/// A latency histogram, with the buckets in [`LATENCY_BUCKETS_MS`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    /// The number of observations in each bucket, not cumulative, the last one being unbounded.
+    pub buckets: [u64; LATENCY_BUCKETS_MS.len() + 1],
+    /// The number of observations.
+    pub count: u64,
+    /// The sum of every observation.
+    pub sum: Duration,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: [0; LATENCY_BUCKETS_MS.len() + 1],
+            count: 0,
+            sum: Duration::ZERO,
+        }
+    }
+}
+
+impl Histogram {
+    fn observe(&mut self, latency: Duration) {
+        let millis = latency.as_millis();
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|&bound| millis <= bound as u128)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum += latency;
+    }
+}
+
+impl Metrics {
+    /// Start collecting metrics, with none collected yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The metrics collected so far, by method name.
+    pub fn snapshot(&self) -> BTreeMap<String, MethodMetrics> {
+        self.methods.lock().unwrap().clone()
+    }
+
+    /// Reset every metric.
+    pub fn reset(&self) {
+        self.methods.lock().unwrap().clear();
+    }
+}
+
+impl Middleware for Metrics {
+    fn after_parse(&self, call: &CallInfo, outcome: CallOutcome<'_>) {
+        let mut methods = self.methods.lock().unwrap();
+        let metrics = methods.entry(call.method_name().to_string()).or_default();
+
+        metrics.calls += 1;
+        if let CallOutcome::Err(err) = outcome {
+            *metrics.errors.entry(err.class()).or_default() += 1;
+        }
+        metrics.request_bytes +=
+            call.request_size().unwrap_or_default() as u64 * call.attempt() as u64;
+        metrics.response_bytes += call.received() as u64;
+        metrics.latency.observe(call.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::MockTransport;
+    use crate::transport::{Transport, TransportFuture, TransportRequest, TransportResponse};
+    use crate::{methods, JsonRpcClient};
+
+    #[tokio::test]
+    async fn counts_calls_per_method() {
+        let mock = MockTransport::new();
+        mock.on("health").times(1).respond(());
+        mock.on("health")
+            .respond_status(reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        mock.on("block").respond_error(json!({
+            "code": -32000,
+            "message": "Server error",
+            "name": "HANDLER_ERROR",
+            "cause": { "name": "UNKNOWN_BLOCK", "info": {} },
+        }));
+
+        let metrics = Metrics::new();
+        let client = mock.client().with_middleware(metrics.clone());
+
+        client
+            .call(methods::health::RpcHealthRequest)
+            .await
+            .unwrap();
+        let _ = client.call(methods::health::RpcHealthRequest).await;
+        let _ = client
+            .call(methods::block::RpcBlockRequest {
+                block_reference: near_primitives::types::Finality::Final.into(),
+            })
+            .await;
+
+        let mut batch = client.batch();
+        batch.add(methods::block::RpcBlockRequest {
+            block_reference: near_primitives::types::Finality::Final.into(),
+        });
+        batch.send().await.expect("batch must be delivered");
+
+        let snapshot = metrics.snapshot();
+        let health = &snapshot["health"];
+        assert_eq!(health.calls, 2);
+        assert_eq!(health.errors, BTreeMap::from([(ErrorClass::Status, 1)]));
+        assert_eq!(health.latency.count, 2);
+        assert!(health.request_bytes > 0);
+        assert!(health.response_bytes > 0);
+
+        let block = &snapshot["block"];
+        assert_eq!(block.calls, 1);
+        assert_eq!(block.errors, BTreeMap::from([(ErrorClass::Handler, 1)]));
+
+        let batch = &snapshot["batch"];
+        assert_eq!(batch.calls, 1);
+        assert!(batch.errors.is_empty());
+        assert!(batch.request_bytes > 0);
+    }
+
+    /// Always overloaded, saying so in the response body.
+    #[derive(Debug)]
+    struct Overloaded;
+
+    impl Transport for Overloaded {
+        fn send(&self, _request: TransportRequest) -> TransportFuture<'_> {
+            Box::pin(async {
+                Ok(TransportResponse::new(
+                    reqwest::StatusCode::SERVICE_UNAVAILABLE,
+                    b"overloaded".to_vec(),
+                ))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn counts_bytes_of_every_attempt() {
+        let transport = Arc::new(Overloaded);
+        let client = JsonRpcClient::with_transport(transport).connect("http://localhost:3030");
+
+        let once = Metrics::new();
+        let _ = client
+            .clone()
+            .with_middleware(once.clone())
+            .call(methods::health::RpcHealthRequest)
+            .await;
+
+        let retried = Metrics::new();
+        let _ = client
+            .with_retry_policy(
+                crate::retry::RetryPolicy::new()
+                    .max_attempts(2)
+                    .initial_backoff(Duration::ZERO)
+                    .jitter(false),
+            )
+            .with_middleware(retried.clone())
+            .call(methods::health::RpcHealthRequest)
+            .await;
+
+        let once = &once.snapshot()["health"];
+        let retried = &retried.snapshot()["health"];
+        assert_eq!(once.response_bytes, 10);
+        assert_eq!(retried.request_bytes, 2 * once.request_bytes);
+        assert_eq!(retried.response_bytes, 2 * once.response_bytes);
+    }
+}
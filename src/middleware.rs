@@ -0,0 +1,437 @@
+//! Hooks around method calls.
+//!
+//! A [`Middleware`] registered with [`JsonRpcClient::with_middleware`] is called at every step of a method call:
+//!
+//! 1. [`before_serialize`](Middleware::before_serialize), once, before the method is serialized
+//! 2. [`before_send`](Middleware::before_send), before every attempt at sending the payload,
+//!    with a chance to modify the request, its headers in particular
+//! 3. [`after_receive`](Middleware::after_receive), after every attempt, with the raw response
+//! 4. [`after_parse`](Middleware::after_parse), once, with the outcome of the call
+//!
+//! Every hook gets a [`CallInfo`] describing the call, with its method name, params, attempt and timing.
+//! Middleware is called in the order it was registered before sending, and in the reverse order after
+//! receiving. Batches are seen as a single `batch` call, whose params are the payloads of the calls
+//! in the batch, followed by a call of its own for every call the batch falls back to sending individually.
+//!
+//! Errors are passed to [`after_parse`](Middleware::after_parse) type-erased, [`CallError::downcast_ref`]
+//! recovers the typed [`JsonRpcError`] of a specific method.
+//!
+//! With the `tower` feature, [`JsonRpcClient`] also implements [`tower_service::Service`] for every
+//! method, so existing tower layers can be stacked on top of it.
+//!
+//! ## Example
+//!
+//! ```
+//! use near_jsonrpc_client::middleware::{CallInfo, CallOutcome, Middleware};
+//! use near_jsonrpc_client::{methods, JsonRpcClient};
+//!
+//! #[derive(Debug)]
+//! struct Log;
+//!
+//! impl Middleware for Log {
+//!     fn after_parse(&self, call: &CallInfo, outcome: CallOutcome<'_>) {
+//!         let unknown_block = matches!(
+//!             outcome,
+//!             CallOutcome::Err(err) if matches!(
+//!                 err.downcast_ref::<methods::block::RpcBlockError>()
+//!                     .and_then(|err| err.handler_error()),
+//!                 Some(methods::block::RpcBlockError::UnknownBlock { .. })
+//!             )
+//!         );
+//!         println!(
+//!             "{} took {:?} over {} attempt(s), unknown block: {}",
+//!             call.method_name(),
+//!             call.elapsed(),
+//!             call.attempt(),
+//!             unknown_block
+//!         );
+//!     }
+//! }
+//!
+//! let client = JsonRpcClient::connect("https://rpc.testnet.near.org").with_middleware(Log);
+//! ```
+use std::any::Any;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use reqwest::StatusCode;
+use serde_json::Value;
+
+use crate::errors::{ErrorClass, JsonRpcError, RpcTransportError};
+use crate::transport::{TransportRequest, TransportResponse};
+#[cfg(doc)]
+use crate::JsonRpcClient;
+
+/// Hooks called around every method call of a client.
+///
+/// Every hook does nothing by default.
+///
+/// See the [`middleware`](self) module documentation for more information.
+pub trait Middleware: fmt::Debug + Send + Sync + 'static {
+    /// Called once, before the method is serialized.
+    ///
+    /// The params aren't known yet.
+    fn before_serialize(&self, _call: &CallInfo) {}
+
+    /// Called before every attempt at sending the request.
+    fn before_send(&self, _call: &CallInfo, _request: &mut TransportRequest) {}
+
+    /// Called after every attempt, with the raw response or the error that prevented it.
+    fn after_receive(
+        &self,
+        _call: &CallInfo,
+        _response: &Result<TransportResponse, RpcTransportError>,
+    ) {
+    }
+
+    /// Called once, with the outcome of the call.
+    fn after_parse(&self, _call: &CallInfo, _outcome: CallOutcome<'_>) {}
+}
+
+/// A method call going through the middleware.
+#[derive(Debug, Clone)]
+pub struct CallInfo {
+    method_name: String,
+    params: Option<Value>,
+    attempt: u32,
+    started_at: Instant,
+    request_size: Option<usize>,
+    response: Option<(StatusCode, usize)>,
+    received: usize,
+}
+
+impl CallInfo {
+    pub(crate) fn new(method_name: &str, params: Option<Value>) -> Self {
+        Self {
+            method_name: method_name.to_string(),
+            params,
+            attempt: 0,
+            started_at: Instant::now(),
+            request_size: None,
+            response: None,
+            received: 0,
+        }
+    }
+
+    pub(crate) fn set_params(&mut self, params: Value) {
+        self.params = Some(params);
+    }
+
+    pub(crate) fn next_attempt(&mut self) {
+        self.attempt += 1;
+    }
+
+    pub(crate) fn set_request_size(&mut self, size: usize) {
+        self.request_size = Some(size);
+    }
+
+    pub(crate) fn set_response(&mut self, response: &TransportResponse) {
+        self.response = Some((response.status, response.body.len()));
+        self.received += response.body.len();
+    }
+
+    /// The total size of the response bodies received over every attempt, in bytes.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn received(&self) -> usize {
+        self.received
+    }
+
+    /// The name of the method called.
+    pub fn method_name(&self) -> &str {
+        &self.method_name
+    }
+
+    /// The params of the call, `None` until it's serialized.
+    pub fn params(&self) -> Option<&Value> {
+        self.params.as_ref()
+    }
+
+    /// The current attempt, starting at 1 once the request is sent.
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// The size of the request body in bytes, `None` until it's serialized.
+    pub fn request_size(&self) -> Option<usize> {
+        self.request_size
+    }
+
+    /// The status code of the last response received, if any.
+    pub fn status(&self) -> Option<StatusCode> {
+        self.response.map(|(status, _)| status)
+    }
+
+    /// The size of the body of the last response received in bytes, if any.
+    pub fn response_size(&self) -> Option<usize> {
+        self.response.map(|(_, size)| size)
+    }
+
+    /// When the call started.
+    pub fn started_at(&self) -> Instant {
+        self.started_at
+    }
+
+    /// The time elapsed since the call started.
+    pub fn elapsed(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+}
+
+/// The outcome of a method call.
+#[derive(Debug, Clone, Copy)]
+pub enum CallOutcome<'a> {
+    /// The call succeeded.
+    Ok,
+    /// The call failed.
+    Err(CallError<'a>),
+}
+
+/// The error a method call failed with, type-erased.
+#[derive(Clone, Copy)]
+pub struct CallError<'a> {
+    error: &'a dyn Any,
+    class: ErrorClass,
+}
+
+impl<'a> CallError<'a> {
+    pub(crate) fn new<E: 'static>(error: &'a JsonRpcError<E>) -> Self {
+        Self {
+            error,
+            class: error.class(),
+        }
+    }
+
+    /// Which part of the error hierarchy the error belongs to.
+    pub fn class(&self) -> ErrorClass {
+        self.class
+    }
+
+    /// Get the typed error, if the handler error of the method called is `E`.
+    pub fn downcast_ref<E: 'static>(&self) -> Option<&'a JsonRpcError<E>> {
+        self.error.downcast_ref()
+    }
+}
+
+impl fmt::Debug for CallError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CallError")
+            .field("class", &self.class)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "tower")]
+mod service {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use crate::errors::JsonRpcError;
+    use crate::{methods, JsonRpcClient};
+
+    impl<M> tower_service::Service<M> for JsonRpcClient
+    where
+        M: methods::RpcMethod + Send + 'static,
+        M::Response: Send,
+        M::Error: Send,
+    {
+        type Response = M::Response;
+        type Error = JsonRpcError<M::Error>;
+        type Future = Pin<Box<dyn Future<Output = Result<M::Response, Self::Error>> + Send>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, method: M) -> Self::Future {
+            let client = self.clone();
+            Box::pin(async move { client.call(method).await })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use near_primitives::types::{BlockReference, Finality};
+    use reqwest::header::HeaderValue;
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::MockTransport;
+    use crate::{methods, retry};
+
+    #[derive(Debug, Default)]
+    struct Trace {
+        events: Mutex<Vec<String>>,
+    }
+
+    #[derive(Debug)]
+    struct Tracer(&'static str, Arc<Trace>);
+
+    impl Tracer {
+        fn push(&self, call: &CallInfo, event: &str) {
+            self.1.events.lock().unwrap().push(format!(
+                "{} {} {} #{}",
+                self.0,
+                event,
+                call.method_name(),
+                call.attempt()
+            ));
+        }
+    }
+
+    impl Middleware for Tracer {
+        fn before_serialize(&self, call: &CallInfo) {
+            assert!(call.params().is_none());
+            self.push(call, "serialize");
+        }
+
+        fn before_send(&self, call: &CallInfo, request: &mut TransportRequest) {
+            assert_eq!(call.params(), Some(&json!({ "finality": "final" })));
+            request
+                .headers
+                .insert(self.0, HeaderValue::from_static("seen"));
+            self.push(call, "send");
+        }
+
+        fn after_receive(
+            &self,
+            call: &CallInfo,
+            response: &Result<TransportResponse, RpcTransportError>,
+        ) {
+            let status = response.as_ref().unwrap().status;
+            self.push(call, &format!("receive {}", status.as_u16()));
+        }
+
+        fn after_parse(&self, call: &CallInfo, outcome: CallOutcome<'_>) {
+            let unknown_block = matches!(
+                outcome,
+                CallOutcome::Err(err) if matches!(
+                    err.downcast_ref::<methods::block::RpcBlockError>()
+                        .and_then(|err| err.handler_error()),
+                    Some(methods::block::RpcBlockError::UnknownBlock { .. })
+                )
+            );
+            self.push(call, &format!("parse unknown_block={}", unknown_block));
+        }
+    }
+
+    #[tokio::test]
+    async fn hooks_are_called_around_calls() {
+        let mock = MockTransport::new();
+        mock.on("block")
+            .times(1)
+            .respond_status(reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        mock.on("block").respond_error(json!({
+            "code": -32000,
+            "message": "Server error",
+            "name": "HANDLER_ERROR",
+            "cause": { "name": "UNKNOWN_BLOCK", "info": {} },
+        }));
+
+        let trace = Arc::new(Trace::default());
+        let client = mock
+            .client()
+            .with_retry_policy(
+                retry::RetryPolicy::new()
+                    .max_attempts(2)
+                    .initial_backoff(Duration::ZERO)
+                    .jitter(false),
+            )
+            .with_middleware(Tracer("a", trace.clone()))
+            .with_middleware(Tracer("b", trace.clone()));
+
+        let _ = client
+            .call(methods::block::RpcBlockRequest {
+                block_reference: BlockReference::Finality(Finality::Final),
+            })
+            .await;
+
+        assert_eq!(
+            *trace.events.lock().unwrap(),
+            [
+                "a serialize block #0",
+                "b serialize block #0",
+                "a send block #1",
+                "b send block #1",
+                "b receive 503 block #1",
+                "a receive 503 block #1",
+                "a send block #2",
+                "b send block #2",
+                "b receive 200 block #2",
+                "a receive 200 block #2",
+                "b parse unknown_block=true block #2",
+                "a parse unknown_block=true block #2",
+            ]
+        );
+
+        let request = &mock.requests()[1];
+        assert_eq!(request.headers["a"], "seen");
+        assert_eq!(request.headers["b"], "seen");
+    }
+
+    #[derive(Debug, Clone, Default)]
+    struct Outcomes(Arc<Mutex<Vec<String>>>);
+
+    impl Middleware for Outcomes {
+        fn before_serialize(&self, call: &CallInfo) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("serialize {}", call.method_name()));
+        }
+
+        fn after_parse(&self, call: &CallInfo, outcome: CallOutcome<'_>) {
+            let params = call.params().map_or(0, |params| match params {
+                Value::Array(payloads) => payloads.len(),
+                _ => 1,
+            });
+            self.0.lock().unwrap().push(format!(
+                "parse {} ({} params) ok={}",
+                call.method_name(),
+                params,
+                matches!(outcome, CallOutcome::Ok)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn hooks_are_called_around_batches() {
+        let mock = MockTransport::new();
+        mock.on("health")
+            .times(1)
+            .respond_status(reqwest::StatusCode::BAD_REQUEST);
+        mock.on("health").respond(());
+        mock.on("status").respond_error(json!({
+            "code": -32601,
+            "message": "Method not found",
+            "name": "REQUEST_VALIDATION_ERROR",
+            "cause": { "name": "METHOD_NOT_FOUND", "info": { "method_name": "status" } },
+        }));
+
+        let outcomes = Outcomes::default();
+        let client = mock.client().with_middleware(outcomes.clone());
+
+        let mut batch = client.batch();
+        batch.add(methods::health::RpcHealthRequest);
+        batch.add(methods::status::RpcStatusRequest);
+        batch.send().await.expect("batch must be delivered");
+
+        let mut events = outcomes.0.lock().unwrap().clone();
+        // the fallback calls run concurrently
+        events[2..].sort();
+        assert_eq!(
+            events,
+            [
+                "serialize batch",
+                "parse batch (2 params) ok=true",
+                "parse health (1 params) ok=true",
+                "parse status (1 params) ok=false",
+                "serialize health",
+                "serialize status",
+            ]
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! Per-call options.
+//!
+//! [`JsonRpcClient::call_with`] calls a method with [`CallOptions`], bounding how long it may take, and
+//! adding headers to its request.
+//!
+//! - The `timeout` applies to every attempt at sending the request, starting once the
+//!   [`rate_limit`](crate::rate_limit) lets it through, if one is configured. An attempt that times out fails with [`JsonRpcTimeoutError::AttemptTimeout`], and is
+//!   retried like any other transient failure if a [`retry`](crate::retry) policy is configured.
+//! - The `deadline` applies to the call as a whole, retries included. Once it passes, the call fails
+//!   with [`JsonRpcTimeoutError::DeadlineExceeded`], and no more retries are attempted. Waiting on the
+//!   rate limiter is only bounded by the deadline.
+//!
+//! Calls made without a timeout use the one configured on the client for the method, if any, see
+//! [`JsonRpcClient::with_timeout`] and [`JsonRpcClient::with_method_timeout`]. Some methods need far
+//! longer than others, `broadcast_tx_commit` or `send_tx` waiting for finality in particular.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use std::time::{Duration, Instant};
+//!
+//! use near_jsonrpc_client::{methods, options::CallOptions, JsonRpcClient};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = JsonRpcClient::connect("https://rpc.testnet.near.org")
+//!     .with_timeout(Duration::from_secs(10))
+//!     .with_method_timeout("broadcast_tx_commit", Duration::from_secs(60));
+//!
+//! let status = client
+//!     .call_with(
+//!         methods::status::RpcStatusRequest,
+//!         CallOptions {
+//!             timeout: Some(Duration::from_secs(2)),
+//!             deadline: Some(Instant::now() + Duration::from_secs(5)),
+//!             ..Default::default()
+//!         },
+//!     )
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use reqwest::header::HeaderMap;
+
+use crate::errors::JsonRpcTimeoutError;
+#[cfg(doc)]
+use crate::JsonRpcClient;
+
+/// Options for a single method call.
+///
+/// See the [`options`](self) module documentation for more information.
+#[derive(Debug, Clone, Default)]
+pub struct CallOptions {
+    /// How long to wait for a response to every attempt, overriding the client's timeout.
+    pub timeout: Option<Duration>,
+    /// When to give up on the call, retries included.
+    pub deadline: Option<Instant>,
+    /// Headers to send along with the ones configured on the client, replacing them if they overlap.
+    pub headers: HeaderMap,
+}
+
+impl CallOptions {
+    /// How long the next attempt may take, `None` if it's unbounded.
+    pub(crate) fn time_left(&self, now: Instant) -> Option<Duration> {
+        let until_deadline = self
+            .deadline
+            .map(|deadline| deadline.saturating_duration_since(now));
+        match (self.timeout, until_deadline) {
+            (Some(timeout), Some(until_deadline)) => Some(timeout.min(until_deadline)),
+            (timeout, until_deadline) => timeout.or(until_deadline),
+        }
+    }
+
+    /// The error an attempt that ran out of time fails with.
+    pub(crate) fn timeout_error(&self, now: Instant) -> JsonRpcTimeoutError {
+        match (self.timeout, self.deadline) {
+            (_, Some(deadline)) if deadline <= now => JsonRpcTimeoutError::DeadlineExceeded,
+            (Some(timeout), _) => JsonRpcTimeoutError::AttemptTimeout(timeout),
+            (None, _) => JsonRpcTimeoutError::DeadlineExceeded,
+        }
+    }
+
+    /// Whether or not there's time left for an attempt after waiting `delay`.
+    pub(crate) fn allows(&self, delay: Duration) -> bool {
+        self.deadline
+            .map_or(true, |deadline| Instant::now() + delay < deadline)
+    }
+}
+
+/// The default timeouts of a client, by method.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Timeouts {
+    pub(crate) default: Option<Duration>,
+    pub(crate) methods: HashMap<String, Duration>,
+}
+
+impl Timeouts {
+    pub(crate) fn timeout_for(&self, method_name: &str) -> Option<Duration> {
+        self.methods.get(method_name).copied().or(self.default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::{JsonRpcError, RpcTransportError};
+    use crate::testing::MockTransport;
+    use crate::{methods, retry, JsonRpcClient};
+
+    /// Responds to `health` after a delay.
+    fn slow(delay: Duration) -> MockTransport {
+        let mock = MockTransport::new();
+        mock.on("health").delay(delay).respond(());
+        mock
+    }
+
+    fn timeout_error<T: std::fmt::Debug, E: std::fmt::Debug>(
+        result: Result<T, JsonRpcError<E>>,
+    ) -> JsonRpcTimeoutError {
+        match result {
+            Err(JsonRpcError::TransportError(RpcTransportError::TimeoutError(err))) => err,
+            result => panic!("expected a timeout, found [{:?}]", result),
+        }
+    }
+
+    #[tokio::test]
+    async fn times_out() {
+        let client = JsonRpcClient::with_transport(slow(Duration::from_millis(200)))
+            .connect("http://localhost:3030")
+            .with_timeout(Duration::from_secs(5))
+            .with_method_timeout("health", Duration::from_millis(10));
+
+        let err = timeout_error(client.call(methods::health::RpcHealthRequest).await);
+        assert!(matches!(
+            err,
+            JsonRpcTimeoutError::AttemptTimeout(timeout) if timeout == Duration::from_millis(10)
+        ));
+
+        // the per-call timeout takes precedence
+        let options = CallOptions {
+            timeout: Some(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        client
+            .call_with(methods::health::RpcHealthRequest, options)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn times_attempts_from_the_rate_limiter() {
+        let client = JsonRpcClient::with_transport(slow(Duration::from_millis(50)))
+            .with_rate_limit(crate::rate_limit::RateLimit::new(1000.0).max_concurrent(1))
+            .connect("http://localhost:3030");
+
+        // every attempt takes 50ms, after waiting up to 100ms on the previous ones
+        let options = CallOptions {
+            timeout: Some(Duration::from_millis(80)),
+            ..Default::default()
+        };
+        let calls = futures::future::join_all(
+            (0..3).map(|_| client.call_with(methods::health::RpcHealthRequest, options.clone())),
+        )
+        .await;
+        assert!(calls.iter().all(Result::is_ok));
+
+        // the deadline still bounds the wait
+        let options = CallOptions {
+            deadline: Some(Instant::now() + Duration::from_millis(80)),
+            ..Default::default()
+        };
+        let calls = futures::future::join_all(
+            (0..3).map(|_| client.call_with(methods::health::RpcHealthRequest, options.clone())),
+        )
+        .await;
+        assert!(calls[0].is_ok());
+        assert!(matches!(
+            timeout_error(calls.into_iter().last().unwrap()),
+            JsonRpcTimeoutError::DeadlineExceeded
+        ));
+    }
+
+    #[tokio::test]
+    async fn stops_retrying_past_the_deadline() {
+        let client = JsonRpcClient::with_transport(slow(Duration::from_millis(200)))
+            .connect("http://localhost:3030")
+            .with_retry_policy(
+                retry::RetryPolicy::new()
+                    .max_attempts(10)
+                    .initial_backoff(Duration::ZERO)
+                    .jitter(false),
+            );
+
+        let started_at = Instant::now();
+        let options = CallOptions {
+            timeout: Some(Duration::from_millis(20)),
+            deadline: Some(started_at + Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let err = timeout_error(
+            client
+                .call_with(methods::health::RpcHealthRequest, options)
+                .await,
+        );
+        assert!(matches!(err, JsonRpcTimeoutError::DeadlineExceeded));
+        assert!(started_at.elapsed() < Duration::from_millis(200));
+    }
+}
@@ -0,0 +1,332 @@
+//! Client-side rate limiting.
+//!
+//! A [`RateLimit`] attached to a [`JsonRpcClientConnector`](crate::JsonRpcClientConnector) is shared by every
+//! client it connects, and applies separately to every server address. Requests beyond the limit wait
+//! their turn instead of failing, in the order they were made. Waiting doesn't count against the
+//! [`timeout`](crate::options::CallOptions::timeout) of a call, only its deadline.
+//!
+//! Every request costs one token by default, tokens are replenished at the configured rate, up to the
+//! burst size. Heavier calls can be made to cost more with [`RateLimit::weight`], by method name or,
+//! for `query`, by request type (like `view_state`). Independently, the number of requests in flight
+//! can be capped with [`RateLimit::max_concurrent`].
+//!
+//! When a server responds with `429 Too Many Requests`, the rate is halved, and if the server sent a
+//! `Retry-After` header, no request is sent before it's elapsed. Every successful response then
+//! raises the rate back towards the configured one, a twentieth of it at a time.
+//!
+//! Retries count against the limit like any other request.
+//!
+//! ## Example
+//!
+//! ```
+//! use near_jsonrpc_client::{rate_limit::RateLimit, JsonRpcClient};
+//!
+//! let limit = RateLimit::new(10.0)
+//!     .burst(20)
+//!     .max_concurrent(4)
+//!     .weight("EXPERIMENTAL_changes", 5)
+//!     .weight("view_state", 10);
+//!
+//! let client = JsonRpcClient::new_client()
+//!     .with_rate_limit(limit)
+//!     .connect("https://rpc.mainnet.near.org");
+//! ```
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use reqwest::StatusCode;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::errors::RpcTransportError;
+use crate::middleware::CallInfo;
+use crate::retry;
+use crate::transport::TransportResponse;
+
+/// Configuration for limiting the rate of requests to a server.
+///
+/// See the [`rate_limit`](self) module documentation for more information.
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    requests_per_second: f64,
+    burst: u32,
+    max_concurrent: Option<usize>,
+    adaptive: bool,
+    weights: HashMap<String, u32>,
+}
+
+impl RateLimit {
+    /// Allow `requests_per_second` requests per second to every server.
+    ///
+    /// The burst size defaults to one second worth of requests, with no limit on concurrency,
+    /// adapting to `429 Too Many Requests` responses.
+    pub fn new(requests_per_second: f64) -> Self {
+        let requests_per_second = requests_per_second.max(f64::MIN_POSITIVE);
+        Self {
+            requests_per_second,
+            burst: requests_per_second.ceil() as u32,
+            max_concurrent: None,
+            adaptive: true,
+            weights: HashMap::new(),
+        }
+    }
+
+    /// Set how many tokens can accumulate while idle.
+    pub fn burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+        self
+    }
+
+    /// Cap the number of requests in flight to every server.
+    pub fn max_concurrent(mut self, max_concurrent: usize) -> Self {
+        self.max_concurrent = Some(max_concurrent.max(1));
+        self
+    }
+
+    /// Set whether or not to slow down when the server responds with `429 Too Many Requests`.
+    ///
+    /// Defaults to `true`.
+    pub fn adaptive(mut self, adaptive: bool) -> Self {
+        self.adaptive = adaptive;
+        self
+    }
+
+    /// Set the number of tokens a method, or a `query` request type, costs.
+    pub fn weight<N: Into<String>>(mut self, name: N, weight: u32) -> Self {
+        self.weights.insert(name.into(), weight);
+        self
+    }
+
+    /// The number of tokens a call costs, never more than the burst size.
+    fn weight_of(&self, call: &CallInfo) -> f64 {
+        let request_type = call
+            .params()
+            .and_then(|params| params["request_type"].as_str())
+            .filter(|_| call.method_name() == "query");
+        let weight = request_type
+            .and_then(|request_type| self.weights.get(request_type))
+            .or_else(|| self.weights.get(call.method_name()))
+            .copied()
+            .unwrap_or(1);
+        weight.min(self.burst) as f64
+    }
+}
+
+/// The rate limiter shared by the clients of a connector.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    limit: RateLimit,
+    endpoints: Mutex<HashMap<String, Arc<Endpoint>>>,
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    permits: Option<Arc<Semaphore>>,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Negative when requests are waiting for tokens.
+    tokens: f64,
+    rate: f64,
+    updated_at: Instant,
+    paused_until: Option<Instant>,
+}
+
+impl Bucket {
+    /// Take `weight` tokens, returning how long to wait before they're available.
+    fn reserve(&mut self, weight: f64, burst: f64, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.updated_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(burst);
+        self.updated_at = now;
+
+        let paused = self
+            .paused_until
+            .map_or(Duration::ZERO, |until| until.saturating_duration_since(now));
+
+        self.tokens -= weight;
+        let refill = if self.tokens < 0.0 {
+            Duration::try_from_secs_f64(-self.tokens / self.rate).unwrap_or(Duration::MAX)
+        } else {
+            Duration::ZERO
+        };
+        paused.max(refill)
+    }
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            endpoints: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn endpoint(&self, server_addr: &str) -> Arc<Endpoint> {
+        self.endpoints
+            .lock()
+            .unwrap()
+            .entry(server_addr.to_string())
+            .or_insert_with(|| {
+                Arc::new(Endpoint {
+                    permits: self
+                        .limit
+                        .max_concurrent
+                        .map(|permits| Arc::new(Semaphore::new(permits))),
+                    bucket: Mutex::new(Bucket {
+                        tokens: self.limit.burst as f64,
+                        rate: self.limit.requests_per_second,
+                        updated_at: Instant::now(),
+                        paused_until: None,
+                    }),
+                })
+            })
+            .clone()
+    }
+
+    /// Wait for the call to be allowed through, holding on to the returned permit while it's in flight.
+    pub(crate) async fn acquire(
+        &self,
+        server_addr: &str,
+        call: &CallInfo,
+    ) -> Option<OwnedSemaphorePermit> {
+        let endpoint = self.endpoint(server_addr);
+
+        let wait = endpoint.bucket.lock().unwrap().reserve(
+            self.limit.weight_of(call),
+            self.limit.burst as f64,
+            Instant::now(),
+        );
+        if !wait.is_zero() {
+            log::debug!("rate limited, waiting {:?} before sending", wait);
+            tokio::time::sleep(wait).await;
+        }
+
+        match &endpoint.permits {
+            Some(permits) => permits.clone().acquire_owned().await.ok(),
+            None => None,
+        }
+    }
+
+    /// Adapt the rate to the response of the server.
+    pub(crate) fn observe(
+        &self,
+        server_addr: &str,
+        outcome: &Result<TransportResponse, RpcTransportError>,
+    ) {
+        let Ok(response) = outcome else { return };
+        if !self.limit.adaptive {
+            return;
+        }
+
+        let endpoint = self.endpoint(server_addr);
+        let mut bucket = endpoint.bucket.lock().unwrap();
+        let configured = self.limit.requests_per_second;
+        if response.status == StatusCode::TOO_MANY_REQUESTS {
+            bucket.rate = (bucket.rate / 2.0).max(configured / 20.0);
+            bucket.tokens = bucket.tokens.min(0.0);
+            if let Some(retry_after) = retry::retry_after(&response.headers) {
+                bucket.paused_until = Some(Instant::now() + retry_after);
+            }
+            log::debug!("server is rate limiting, slowing down to {}/s", bucket.rate);
+        } else if response.status.is_success() {
+            bucket.rate = (bucket.rate + configured / 20.0).min(configured);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::MockTransport;
+    use crate::{methods, JsonRpcClient};
+
+    fn bucket(rate: f64, now: Instant) -> Bucket {
+        Bucket {
+            tokens: 1.0,
+            rate,
+            updated_at: now,
+            paused_until: None,
+        }
+    }
+
+    #[test]
+    fn queues_beyond_the_rate() {
+        let now = Instant::now();
+        let mut bucket = bucket(10.0, now);
+
+        assert_eq!(bucket.reserve(1.0, 1.0, now), Duration::ZERO);
+        assert_eq!(bucket.reserve(1.0, 1.0, now), Duration::from_millis(100));
+        assert_eq!(bucket.reserve(1.0, 1.0, now), Duration::from_millis(200));
+        assert_eq!(
+            bucket.reserve(1.0, 1.0, now + Duration::from_millis(300)),
+            Duration::from_millis(0)
+        );
+
+        let limit = RateLimit::new(10.0).weight("view_state", 5);
+        let view_state = CallInfo::new(
+            "query",
+            Some(json!({ "request_type": "view_state", "finality": "final" })),
+        );
+        let view_account = CallInfo::new(
+            "query",
+            Some(json!({ "request_type": "view_account", "finality": "final" })),
+        );
+        assert_eq!(limit.weight_of(&view_state), 5.0);
+        assert_eq!(limit.weight_of(&view_account), 1.0);
+    }
+
+    #[test]
+    fn waits_forever_without_a_rate() {
+        let now = Instant::now();
+        let mut bucket = bucket(RateLimit::new(0.0).requests_per_second, now);
+
+        assert_eq!(bucket.reserve(1.0, 1.0, now), Duration::ZERO);
+        assert_eq!(bucket.reserve(1.0, 1.0, now), Duration::MAX);
+    }
+
+    #[test]
+    fn slows_down_when_rate_limited() {
+        let limiter = RateLimiter::new(RateLimit::new(10.0));
+        let server_addr = "http://localhost:3030";
+
+        let mut response = TransportResponse::new(StatusCode::TOO_MANY_REQUESTS, vec![]);
+        response
+            .headers
+            .insert(reqwest::header::RETRY_AFTER, "2".parse().unwrap());
+        limiter.observe(server_addr, &Ok(response));
+
+        let endpoint = limiter.endpoint(server_addr);
+        let mut bucket = endpoint.bucket.lock().unwrap();
+        assert_eq!(bucket.rate, 5.0);
+        assert!(bucket.reserve(1.0, 10.0, Instant::now()) > Duration::from_secs(1));
+        drop(bucket);
+
+        let ok = TransportResponse::new(StatusCode::OK, vec![]);
+        for _ in 0..20 {
+            limiter.observe(server_addr, &Ok(ok.clone()));
+        }
+        assert_eq!(endpoint.bucket.lock().unwrap().rate, 10.0);
+    }
+
+    #[tokio::test]
+    async fn caps_concurrency() {
+        let mock = MockTransport::new();
+        mock.on("health")
+            .delay(Duration::from_millis(10))
+            .respond(());
+        let client = JsonRpcClient::with_transport(mock.clone())
+            .with_rate_limit(RateLimit::new(1000.0).max_concurrent(2))
+            .connect("http://localhost:3030");
+
+        let calls = (0..6).map(|_| client.call(methods::health::RpcHealthRequest));
+        for result in futures::future::join_all(calls).await {
+            result.expect("every call must eventually go through");
+        }
+
+        assert_eq!(mock.max_in_flight(), 2);
+    }
+}
@@ -0,0 +1,307 @@
+//! Retry policies for transient failures.
+//!
+//! A [`RetryPolicy`] can be attached to either a [`JsonRpcClientConnector`](crate::JsonRpcClientConnector),
+//! where it applies to every client it connects, or a single [`JsonRpcClient`](crate::JsonRpcClient).
+//!
+//! Requests are retried when;
+//!   - the client is unable to send the request to the server
+//!   - an attempt times out, see [`CallOptions::timeout`](crate::options::CallOptions::timeout)
+//!   - the server responds with `429 Too Many Requests`, `503 Service Unavailable` or `408 Request Timeout`
+//!
+//! Between attempts, the client waits with an exponential backoff. If the server responds with a
+//! `Retry-After` header (in seconds), that delay is used instead, capped at the maximum backoff.
+//!
+//! Non-idempotent methods (see [`NON_IDEMPOTENT_METHODS`]) are never retried, unless explicitly
+//! opted into with [`RetryPolicy::method`].
+//!
+//! ## Example
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use near_jsonrpc_client::{retry::RetryPolicy, JsonRpcClient};
+//!
+//! let policy = RetryPolicy::new()
+//!     .max_attempts(5)
+//!     .initial_backoff(Duration::from_millis(200))
+//!     .method("send_tx", 3); // <- opt-in to retrying `send_tx`
+//!
+//! let client = JsonRpcClient::new_client()
+//!     .with_retry_policy(policy)
+//!     .connect("https://rpc.testnet.near.org");
+//! ```
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::time::Duration;
+
+use rand::Rng;
+use reqwest::header::HeaderMap;
+
+use crate::errors::{JsonRpcServerError, RpcTransportError};
+use crate::transport::TransportResponse;
+
+/// Methods that aren't safe to retry by default, since repeating them may have side effects.
+pub const NON_IDEMPOTENT_METHODS: &[&str] =
+    &["broadcast_tx_async", "broadcast_tx_commit", "send_tx"];
+
+/// Configuration for retrying requests that fail with transient errors.
+///
+/// See the [`retry`](self) module documentation for more information.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    jitter: bool,
+    respect_retry_after: bool,
+    methods: HashMap<String, u32>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter: true,
+            respect_retry_after: true,
+            methods: HashMap::new(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Create a new retry policy with the default configuration.
+    ///
+    /// That's 3 attempts in total, starting with a 500ms backoff that doubles on every retry
+    /// (up to 10s), with jitter, respecting `Retry-After` headers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum number of attempts, including the first one.
+    ///
+    /// A value of `1` disables retries.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Set the delay before the first retry.
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Set the upper bound for the delay between retries, `Retry-After` delays included.
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Set the factor by which the delay grows after every retry.
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// Randomize delays, to avoid retrying in lockstep with other clients.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Wait for as long as the server asks via the `Retry-After` header, when present.
+    pub fn respect_retry_after(mut self, respect: bool) -> Self {
+        self.respect_retry_after = respect;
+        self
+    }
+
+    /// Override the maximum number of attempts for a specific method.
+    ///
+    /// This is the only way to enable retries for [`NON_IDEMPOTENT_METHODS`].
+    pub fn method<N: Into<String>>(mut self, method_name: N, max_attempts: u32) -> Self {
+        self.methods.insert(method_name.into(), max_attempts.max(1));
+        self
+    }
+
+    /// Get the maximum number of attempts for the specified method.
+    pub fn max_attempts_for(&self, method_name: &str) -> u32 {
+        if let Some(max_attempts) = self.methods.get(method_name) {
+            return *max_attempts;
+        }
+        if NON_IDEMPOTENT_METHODS.contains(&method_name) {
+            return 1;
+        }
+        self.max_attempts
+    }
+
+    /// Compute the delay before the specified retry (starting at `1`), ignoring jitter.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = self
+            .multiplier
+            .powi(retry.saturating_sub(1).min(i32::MAX as u32) as i32);
+        Duration::try_from_secs_f64(self.initial_backoff.as_secs_f64() * factor)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+
+    pub(crate) fn delay(&self, retry: u32, retry_after: Option<Duration>) -> Duration {
+        if let (true, Some(retry_after)) = (self.respect_retry_after, retry_after) {
+            return retry_after.min(self.max_backoff);
+        }
+        let backoff = self.backoff(retry);
+        if !self.jitter || backoff.is_zero() {
+            return backoff;
+        }
+        // equal jitter: half of the delay is fixed, the other half is random
+        let half = backoff / 2;
+        half + rand::thread_rng().gen_range(Duration::ZERO..=backoff - half)
+    }
+}
+
+/// Whether or not the outcome of a transport request is worth retrying,
+/// the same as [`JsonRpcError::is_retryable`](crate::errors::JsonRpcError::is_retryable) short of handler errors.
+pub(crate) fn is_transient(outcome: &Result<TransportResponse, RpcTransportError>) -> bool {
+    match outcome {
+        Ok(response) => matches!(
+            crate::status_error::<Infallible>(response),
+            Some(JsonRpcServerError::ResponseStatusError(status)) if status.is_retryable()
+        ),
+        Err(err) => err.is_retryable(),
+    }
+}
+
+/// Parse the `Retry-After` header, if it's specified in seconds.
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use reqwest::StatusCode;
+
+    use super::*;
+    use crate::errors::*;
+    use crate::transport::{Transport, TransportFuture, TransportRequest};
+    use crate::{methods, JsonRpcClient};
+
+    #[test]
+    fn exponential_backoff() {
+        let policy = RetryPolicy::new()
+            .initial_backoff(Duration::from_millis(100))
+            .max_backoff(Duration::from_millis(500));
+
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff(4), Duration::from_millis(500));
+        assert_eq!(policy.backoff(100), Duration::from_millis(500));
+
+        for retry in 1..5 {
+            let delay = policy.delay(retry, None);
+            assert!(delay >= policy.backoff(retry) / 2 && delay <= policy.backoff(retry));
+        }
+
+        assert_eq!(
+            policy.delay(1, Some(Duration::from_millis(300))),
+            Duration::from_millis(300)
+        );
+        assert_eq!(
+            policy.delay(1, Some(Duration::from_secs(7))),
+            Duration::from_millis(500)
+        );
+    }
+
+    #[test]
+    fn non_idempotent_opt_in() {
+        let policy = RetryPolicy::new().max_attempts(4).method("send_tx", 2);
+
+        assert_eq!(policy.max_attempts_for("status"), 4);
+        assert_eq!(policy.max_attempts_for("send_tx"), 2);
+        assert_eq!(policy.max_attempts_for("broadcast_tx_commit"), 1);
+    }
+
+    #[derive(Debug, Default)]
+    struct FlakyTransport {
+        failures: usize,
+        calls: AtomicUsize,
+    }
+
+    impl Transport for FlakyTransport {
+        fn send(&self, _request: TransportRequest) -> TransportFuture<'_> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move {
+                if call < self.failures {
+                    let mut response =
+                        TransportResponse::new(StatusCode::TOO_MANY_REQUESTS, vec![]);
+                    response
+                        .headers
+                        .insert(reqwest::header::RETRY_AFTER, "0".parse().unwrap());
+                    return Ok(response);
+                }
+                Ok(TransportResponse::new(
+                    StatusCode::OK,
+                    br#"{"jsonrpc":"2.0","id":"dontcare","result":null}"#.to_vec(),
+                ))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_rate_limited_requests() {
+        let transport = Arc::new(FlakyTransport {
+            failures: 2,
+            ..Default::default()
+        });
+
+        let client = JsonRpcClient::with_transport(transport.clone())
+            .with_retry_policy(RetryPolicy::new().max_attempts(3))
+            .connect("http://localhost:3030");
+
+        let response = client.call(methods::health::RpcHealthRequest).await;
+        assert!(response.is_ok(), "expected an Ok, found [{:?}]", response);
+        assert_eq!(transport.calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let transport = Arc::new(FlakyTransport {
+            failures: 5,
+            ..Default::default()
+        });
+
+        let client = JsonRpcClient::with_transport(transport.clone())
+            .connect("http://localhost:3030")
+            .with_retry_policy(RetryPolicy::new().max_attempts(2));
+
+        let response = client.call(methods::health::RpcHealthRequest).await;
+        assert!(
+            matches!(
+                response,
+                Err(JsonRpcError::ServerError(
+                    JsonRpcServerError::ResponseStatusError(
+                        JsonRpcServerResponseStatusError::TooManyRequests { .. }
+                    )
+                ))
+            ),
+            "expected a TooManyRequests error, found [{:?}]",
+            response
+        );
+        assert_eq!(transport.calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,483 @@
+//! Transaction submission with nonce and block hash management.
+//!
+//! Submitting a transaction means fetching the access key for its nonce, picking a recent block hash,
+//! signing and finally sending it. A [`TransactionSender`] does all of that for you.
+//!
+//! Nonces are cached per (account, public key), so concurrent submissions from clones of the same sender
+//! are handed out increasing nonces without querying the network every time. The reference block hash
+//! is refreshed before it expires, and transactions rejected with [`InvalidTxError::InvalidNonce`] or
+//! [`InvalidTxError::Expired`] are re-signed and resubmitted.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use near_jsonrpc_client::{sender::TransactionSender, JsonRpcClient};
+//! use near_primitives::transaction::{Action, TransferAction};
+//! use near_primitives::views::TxExecutionStatus;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = JsonRpcClient::connect("https://rpc.testnet.near.org");
+//!
+//! let signer = near_crypto::InMemorySigner::from_secret_key(
+//!     "miraclx.testnet".parse()?,
+//!     "ed25519:2vVTQWpoZvYZBS4HYFZtzU2rxpoQSrhyFWdaHLqSdyaEfgjefbSKiFpuVatuRqax3HFvVq2tkkqWH2h7tso2nK8q".parse()?,
+//! );
+//!
+//! let sender = TransactionSender::new(client, signer.account_id.clone(), signer.into());
+//!
+//! let response = sender
+//!     .send(
+//!         "nosedive.testnet".parse()?,
+//!         vec![Action::Transfer(TransferAction { deposit: 1 })],
+//!         TxExecutionStatus::Final,
+//!     )
+//!     .await?;
+//!
+//! println!("{:#?}", response);
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use near_crypto::{PublicKey, Signer};
+use near_jsonrpc_primitives::types::query::QueryResponseKind;
+use near_primitives::errors::InvalidTxError;
+use near_primitives::hash::CryptoHash;
+use near_primitives::transaction::{Action, SignedTransaction, Transaction, TransactionV0};
+use near_primitives::types::{AccountId, BlockReference, Finality, Nonce};
+use near_primitives::views::{QueryRequest, TxExecutionStatus};
+use thiserror::Error;
+
+use crate::errors::JsonRpcError;
+use crate::{methods, JsonRpcClient};
+
+/// Potential errors returned while submitting a transaction.
+#[derive(Debug, Error)]
+pub enum TransactionSenderError {
+    /// The access key of the signer couldn't be fetched.
+    #[error("error while fetching the access key: [{0}]")]
+    AccessKeyError(JsonRpcError<methods::query::RpcQueryError>),
+    /// The server responded to the access key query with something other than an access key.
+    #[error("unexpected response to the access key query: [{0:?}]")]
+    UnexpectedQueryResponse(QueryResponseKind),
+    /// The transaction was rejected, or its status couldn't be determined.
+    #[error(transparent)]
+    TransactionError(JsonRpcError<methods::send_tx::RpcTransactionError>),
+}
+
+#[derive(Debug)]
+struct KeyState {
+    nonce: Nonce,
+    block_hash: CryptoHash,
+    fetched_at: Option<Instant>,
+}
+
+/// The state of every access key, each behind a lock of its own, so fetching one doesn't hold up the others.
+type NonceCache = HashMap<(AccountId, PublicKey), Arc<tokio::sync::Mutex<Option<KeyState>>>>;
+
+/// Signs and submits transactions on behalf of an account.
+///
+/// Cloning a sender is cheap, and clones share the same nonce cache.
+///
+/// See the [`sender`](self) module documentation for more information.
+#[derive(Debug, Clone)]
+pub struct TransactionSender {
+    client: JsonRpcClient,
+    signer_id: AccountId,
+    signer: Arc<Signer>,
+    cache: Arc<Mutex<NonceCache>>,
+    block_hash_ttl: Duration,
+    max_resubmits: u32,
+}
+
+impl TransactionSender {
+    /// Create a new sender that signs transactions for `signer_id` with `signer`.
+    pub fn new(client: JsonRpcClient, signer_id: AccountId, signer: Signer) -> Self {
+        Self {
+            client,
+            signer_id,
+            signer: Arc::new(signer),
+            cache: Default::default(),
+            block_hash_ttl: Duration::from_secs(10 * 60),
+            max_resubmits: 3,
+        }
+    }
+
+    /// Create a sender for another signer, sharing this sender's client and nonce cache.
+    pub fn for_signer(&self, signer_id: AccountId, signer: Signer) -> Self {
+        Self {
+            signer_id,
+            signer: Arc::new(signer),
+            ..self.clone()
+        }
+    }
+
+    /// Set how long a reference block hash is used before it's refreshed.
+    ///
+    /// Defaults to 10 minutes, well within the transaction validity period of every NEAR network.
+    pub fn with_block_hash_ttl(mut self, ttl: Duration) -> Self {
+        self.block_hash_ttl = ttl;
+        self
+    }
+
+    /// Set how many times a transaction with an invalid nonce or an expired block hash is resubmitted.
+    ///
+    /// Defaults to 3.
+    pub fn with_max_resubmits(mut self, max_resubmits: u32) -> Self {
+        self.max_resubmits = max_resubmits;
+        self
+    }
+
+    /// The account transactions are signed for.
+    pub fn signer_id(&self) -> &AccountId {
+        &self.signer_id
+    }
+
+    /// The public key transactions are signed with.
+    pub fn public_key(&self) -> PublicKey {
+        self.signer.public_key()
+    }
+
+    /// Sign a transaction with the next available nonce and a recent block hash, without submitting it.
+    pub async fn sign(
+        &self,
+        receiver_id: AccountId,
+        actions: Vec<Action>,
+    ) -> Result<SignedTransaction, TransactionSenderError> {
+        let (nonce, block_hash) = self.next_nonce().await?;
+
+        let transaction = Transaction::V0(TransactionV0 {
+            signer_id: self.signer_id.clone(),
+            public_key: self.signer.public_key(),
+            nonce,
+            receiver_id,
+            block_hash,
+            actions,
+        });
+
+        let signature = self.signer.sign(transaction.get_hash_and_size().0.as_ref());
+
+        Ok(SignedTransaction::new(signature, transaction))
+    }
+
+    /// Sign and submit a transaction, waiting until it reaches the specified execution status.
+    pub async fn send(
+        &self,
+        receiver_id: AccountId,
+        actions: Vec<Action>,
+        wait_until: TxExecutionStatus,
+    ) -> Result<methods::send_tx::RpcTransactionResponse, TransactionSenderError> {
+        let mut resubmits = 0;
+        loop {
+            let signed_transaction = self.sign(receiver_id.clone(), actions.clone()).await?;
+
+            let result = self
+                .client
+                .call(methods::send_tx::RpcSendTransactionRequest {
+                    signed_transaction,
+                    wait_until: wait_until.clone(),
+                })
+                .await;
+
+            let err = match result {
+                Ok(response) => return Ok(response),
+                Err(err) => err,
+            };
+
+            let context = match err.handler_error() {
+                Some(methods::send_tx::RpcTransactionError::InvalidTransaction { context }) => {
+                    context
+                }
+                _ => return Err(TransactionSenderError::TransactionError(err)),
+            };
+
+            if resubmits >= self.max_resubmits {
+                return Err(TransactionSenderError::TransactionError(err));
+            }
+
+            match context {
+                InvalidTxError::InvalidNonce { ak_nonce, .. } => {
+                    log::debug!(
+                        "nonce is behind the access key ({}), resubmitting",
+                        ak_nonce
+                    );
+                    self.observe_nonce(*ak_nonce).await;
+                }
+                InvalidTxError::Expired => {
+                    log::debug!("reference block hash expired, resubmitting");
+                    self.expire_block_hash().await;
+                }
+                _ => return Err(TransactionSenderError::TransactionError(err)),
+            }
+            resubmits += 1;
+        }
+    }
+
+    /// The state of the signer's access key.
+    fn key_state(&self) -> Arc<tokio::sync::Mutex<Option<KeyState>>> {
+        let key = (self.signer_id.clone(), self.signer.public_key());
+        self.cache.lock().unwrap().entry(key).or_default().clone()
+    }
+
+    /// Hand out the next nonce, refreshing the access key if it's unknown or stale.
+    async fn next_nonce(&self) -> Result<(Nonce, CryptoHash), TransactionSenderError> {
+        let key = (self.signer_id.clone(), self.signer.public_key());
+
+        let key_state = self.key_state();
+        let mut state = key_state.lock().await;
+
+        let stale = state.as_ref().map_or(true, |state| {
+            state.fetched_at.map_or(true, |fetched_at| {
+                fetched_at.elapsed() >= self.block_hash_ttl
+            })
+        });
+
+        if stale {
+            let response = self
+                .client
+                .call(methods::query::RpcQueryRequest {
+                    block_reference: BlockReference::Finality(Finality::Final),
+                    request: QueryRequest::ViewAccessKey {
+                        account_id: key.0.clone(),
+                        public_key: key.1.clone(),
+                    },
+                })
+                .await
+                .map_err(TransactionSenderError::AccessKeyError)?;
+
+            let access_key = match response.kind {
+                QueryResponseKind::AccessKey(access_key) => access_key,
+                kind => return Err(TransactionSenderError::UnexpectedQueryResponse(kind)),
+            };
+
+            let state = state.get_or_insert(KeyState {
+                nonce: 0,
+                block_hash: response.block_hash,
+                fetched_at: None,
+            });
+            // other transactions may still be in flight, so never go backwards
+            state.nonce = state.nonce.max(access_key.nonce);
+            state.block_hash = response.block_hash;
+            state.fetched_at = Some(Instant::now());
+        }
+
+        let state = state.as_mut().expect("access key was just cached");
+        state.nonce += 1;
+
+        Ok((state.nonce, state.block_hash))
+    }
+
+    async fn expire_block_hash(&self) {
+        if let Some(state) = self.key_state().lock().await.as_mut() {
+            state.fetched_at = None;
+        }
+    }
+
+    async fn observe_nonce(&self, nonce: Nonce) {
+        if let Some(state) = self.key_state().lock().await.as_mut() {
+            state.nonce = state.nonce.max(nonce);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use reqwest::StatusCode;
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::MockTransport;
+    use crate::transport::{Transport, TransportFuture, TransportRequest, TransportResponse};
+
+    #[derive(Debug, Default)]
+    struct AccessKeys {
+        queries: AtomicUsize,
+    }
+
+    impl Transport for AccessKeys {
+        fn send(&self, _request: TransportRequest) -> TransportFuture<'_> {
+            self.queries.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async {
+                Ok(TransportResponse::new(
+                    StatusCode::OK,
+                    json!({
+                        "jsonrpc": "2.0",
+                        "id": "dontcare",
+                        "result": {
+                            "nonce": 41,
+                            "permission": "FullAccess",
+                            "block_height": 1,
+                            "block_hash": CryptoHash::default(),
+                        },
+                    })
+                    .to_string()
+                    .into(),
+                ))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn hands_out_increasing_nonces() {
+        let transport = Arc::new(AccessKeys::default());
+        let client =
+            JsonRpcClient::with_transport(transport.clone()).connect("http://localhost:3030");
+
+        let signer = near_crypto::InMemorySigner::from_seed(
+            "alice.near".parse().unwrap(),
+            near_crypto::KeyType::ED25519,
+            "alice.near",
+        );
+        let sender = TransactionSender::new(client, signer.account_id.clone(), signer.into());
+
+        let transactions = futures::future::join_all((0..5).map(|_| {
+            let sender = sender.clone();
+            async move { sender.sign("bob.near".parse().unwrap(), vec![]).await }
+        }))
+        .await;
+
+        let mut nonces = transactions
+            .into_iter()
+            .map(|tx| tx.expect("signing must not fail").transaction.nonce())
+            .collect::<Vec<_>>();
+        nonces.sort();
+
+        assert_eq!(nonces, [42, 43, 44, 45, 46]);
+        assert_eq!(transport.queries.load(Ordering::SeqCst), 1);
+
+        // a stale block hash is refreshed, without reusing nonces
+        let sender = sender.with_block_hash_ttl(Duration::ZERO);
+        let tx = sender
+            .sign("bob.near".parse().unwrap(), vec![])
+            .await
+            .unwrap();
+        assert_eq!(tx.transaction.nonce(), 47);
+        assert_eq!(transport.queries.load(Ordering::SeqCst), 2);
+    }
+
+    fn sender(mock: &MockTransport) -> TransactionSender {
+        let signer = near_crypto::InMemorySigner::from_seed(
+            "alice.near".parse().unwrap(),
+            near_crypto::KeyType::ED25519,
+            "alice.near",
+        );
+        TransactionSender::new(mock.client(), signer.account_id.clone(), signer.into())
+    }
+
+    fn access_key(nonce: Nonce) -> serde_json::Value {
+        json!({
+            "nonce": nonce,
+            "permission": "FullAccess",
+            "block_height": 1,
+            "block_hash": CryptoHash::default(),
+        })
+    }
+
+    /// An `INVALID_TRANSACTION` error, as nodes report it.
+    fn invalid_transaction(context: InvalidTxError) -> serde_json::Value {
+        json!({
+            "code": -32000,
+            "message": "Server error",
+            "data": near_jsonrpc_primitives::errors::ServerError::TxExecutionError(
+                near_primitives::errors::TxExecutionError::InvalidTxError(context),
+            ),
+            "name": "HANDLER_ERROR",
+            "cause": { "name": "INVALID_TRANSACTION", "info": {} },
+        })
+    }
+
+    fn sent_nonces(mock: &MockTransport) -> Vec<Nonce> {
+        mock.requests()
+            .iter()
+            .filter(|request| request.method == "send_tx")
+            .map(|request| {
+                let signed_tx = request.payload["params"]["signed_tx_base64"]
+                    .as_str()
+                    .unwrap();
+                let bytes = near_primitives::serialize::from_base64(signed_tx).unwrap();
+                <SignedTransaction as borsh::BorshDeserialize>::try_from_slice(&bytes)
+                    .unwrap()
+                    .transaction
+                    .nonce()
+            })
+            .collect()
+    }
+
+    fn queries(mock: &MockTransport) -> usize {
+        mock.requests()
+            .iter()
+            .filter(|request| request.method == "query")
+            .count()
+    }
+
+    async fn send(sender: &TransactionSender) -> Result<(), TransactionSenderError> {
+        sender
+            .send("bob.near".parse().unwrap(), vec![], TxExecutionStatus::None)
+            .await
+            .map(drop)
+    }
+
+    #[tokio::test]
+    async fn resubmits_with_the_nonce_of_the_access_key() {
+        let mock = MockTransport::new();
+        mock.on("query").respond(access_key(41));
+        mock.on("send_tx")
+            .times(1)
+            .respond_error(invalid_transaction(InvalidTxError::InvalidNonce {
+                tx_nonce: 42,
+                ak_nonce: 100,
+            }));
+        mock.on("send_tx")
+            .respond(json!({ "final_execution_status": "NONE" }));
+
+        send(&sender(&mock)).await.unwrap();
+
+        assert_eq!(sent_nonces(&mock), [42, 101]);
+        assert_eq!(queries(&mock), 1);
+    }
+
+    #[tokio::test]
+    async fn refetches_the_access_key_when_expired() {
+        let mock = MockTransport::new();
+        mock.on("query").respond(access_key(41));
+        mock.on("send_tx")
+            .times(1)
+            .respond_error(invalid_transaction(InvalidTxError::Expired));
+        mock.on("send_tx")
+            .respond(json!({ "final_execution_status": "NONE" }));
+
+        send(&sender(&mock)).await.unwrap();
+
+        assert_eq!(sent_nonces(&mock), [42, 43]);
+        assert_eq!(queries(&mock), 2);
+    }
+
+    #[tokio::test]
+    async fn stops_resubmitting_at_the_cap() {
+        let mock = MockTransport::new();
+        mock.on("query").respond(access_key(41));
+        mock.on("send_tx")
+            .respond_error(invalid_transaction(InvalidTxError::Expired));
+
+        let err = send(&sender(&mock).with_max_resubmits(2))
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            err,
+            TransactionSenderError::TransactionError(ref err)
+                if matches!(
+                    err.handler_error(),
+                    Some(methods::send_tx::RpcTransactionError::InvalidTransaction {
+                        context: InvalidTxError::Expired,
+                    })
+                )
+        ));
+        assert_eq!(sent_nonces(&mock), [42, 43, 44]);
+    }
+}
@@ -0,0 +1,713 @@
+//! Downloading the full state of a contract.
+//!
+//! A single `view_state` query fails with
+//! [`TooLargeContractState`](crate::methods::query::RpcQueryError::TooLargeContractState)
+//! when the state under the requested prefix exceeds what the node is willing to return.
+//! A [`ContractStateReader`] works around that by splitting the key space: every prefix that's
+//! too large is replaced with its 256 one-byte-longer children, until each of them fits.
+//!
+//! All queries are pinned to the block the first one was executed at, so the result is a consistent
+//! snapshot, even when the reader was created with a [`Finality`](near_primitives::types::Finality).
+//! Key/value pairs are yielded in key order.
+//!
+//! Some nodes compare the limit against the total storage usage of the account, regardless of the prefix.
+//! Splitting doesn't help there, so the reader probes a random prefix before the first split, and fails
+//! with [`ContractStateError::StateTooLarge`] right away if that's also rejected.
+//!
+//! A key that's exactly equal to a prefix that had to be split can't be retrieved, since `view_state`
+//! has no way of querying a single key. The queries under a split prefix request proofs, whose nodes
+//! tell whether that key exists, and if it does, the reader fails with
+//! [`ContractStateError::UnreachableKey`] instead of leaving it out.
+//!
+//! With [`ContractStateReader::with_proofs`], every response is checked against the state root of the
+//! shard of the account with [`verify_state_proof`]. Like [`JsonRpcClient::view_state_verified`], the
+//! state root is taken from the chunk header of the referenced block, so the state is read at its
+//! parent, and the referenced block must include a new chunk for the shard.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use futures::TryStreamExt;
+//! use near_jsonrpc_client::{state::ContractStateReader, JsonRpcClient};
+//! use near_primitives::types::{BlockReference, Finality};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = JsonRpcClient::connect("https://archival-rpc.mainnet.near.org");
+//!
+//! let mut state = ContractStateReader::new(
+//!     client,
+//!     "wrap.near".parse()?,
+//!     BlockReference::Finality(Finality::Final),
+//! )
+//! .with_proofs(true)
+//! .stream();
+//!
+//! while let Some(item) = state.try_next().await? {
+//!     println!("{:?} = {:?}", item.key, item.value);
+//! }
+//!
+//! println!("snapshot taken at {:?}", state.block());
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures::future::BoxFuture;
+use futures::{FutureExt, Stream};
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{
+    AccountId, BlockHeight, BlockId, BlockReference, StateRoot, StoreKey,
+};
+use near_primitives::views::{StateItem, ViewStateResult};
+use thiserror::Error;
+
+use crate::errors::JsonRpcError;
+use crate::methods::query::{RpcQueryError, ViewStateRequest};
+use crate::state_proof::{
+    proof_root, proven_value_hash, verify_state_proof, StateProofError, VerifiedStateError,
+};
+use crate::JsonRpcClient;
+
+/// Potential errors returned while downloading contract state.
+#[derive(Debug, Error)]
+pub enum ContractStateError {
+    /// A `view_state` query failed.
+    #[error(transparent)]
+    QueryError(JsonRpcError<RpcQueryError>),
+    /// The state under a prefix is too large for the node, and can't be split any further.
+    #[error("state of [{account_id}] under prefix [{prefix:?}] is too large to be viewed")]
+    StateTooLarge {
+        account_id: AccountId,
+        prefix: StoreKey,
+    },
+    /// A key is exactly equal to a prefix whose state is too large to be viewed, so it can't be retrieved.
+    #[error("key [{key:?}] of [{account_id}] is also a prefix too large to be viewed")]
+    UnreachableKey {
+        account_id: AccountId,
+        key: StoreKey,
+    },
+    /// The state root to check the proofs against couldn't be found.
+    #[error(transparent)]
+    StateRootError(VerifiedStateError),
+    /// The values returned don't match the proof returned with them.
+    #[error(transparent)]
+    ProofError(StateProofError),
+}
+
+/// Downloads the full state of a contract, at a single block.
+///
+/// See the [`state`](self) module documentation for more information.
+#[derive(Debug, Clone)]
+pub struct ContractStateReader {
+    client: JsonRpcClient,
+    account_id: AccountId,
+    block_reference: BlockReference,
+    prefix: Vec<u8>,
+    include_proof: bool,
+    max_split_depth: usize,
+}
+
+impl ContractStateReader {
+    /// Create a new reader for the state of `account_id` at `block_reference`.
+    pub fn new(
+        client: JsonRpcClient,
+        account_id: AccountId,
+        block_reference: BlockReference,
+    ) -> Self {
+        Self {
+            client,
+            account_id,
+            block_reference,
+            prefix: vec![],
+            include_proof: false,
+            max_split_depth: 4,
+        }
+    }
+
+    /// Only download keys starting with `prefix`.
+    pub fn with_prefix(mut self, prefix: impl Into<Vec<u8>>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Request proofs, and check every response against the state root of the shard of the account.
+    ///
+    /// See the [`state`](self) module documentation for more information.
+    pub fn with_proofs(mut self, include_proof: bool) -> Self {
+        self.include_proof = include_proof;
+        self
+    }
+
+    /// Set how many bytes can be appended to the initial prefix while splitting.
+    ///
+    /// Defaults to 4.
+    pub fn with_max_split_depth(mut self, max_split_depth: usize) -> Self {
+        self.max_split_depth = max_split_depth;
+        self
+    }
+
+    /// Start downloading, yielding key/value pairs in key order.
+    pub fn stream(&self) -> ContractStateStream {
+        ContractStateStream {
+            walk: Some(Walk {
+                reader: self.clone(),
+                block: None,
+                prefixes: vec![self.prefix.clone()],
+                items: VecDeque::new(),
+                splittable: false,
+                state_root: None,
+                split: vec![],
+            }),
+            block: None,
+            pending: None,
+        }
+    }
+}
+
+/// A stream of the key/value pairs of a contract.
+///
+/// The stream ends after the first error.
+pub struct ContractStateStream {
+    walk: Option<Walk>,
+    block: Option<(BlockHeight, CryptoHash)>,
+    pending: Option<BoxFuture<'static, Step>>,
+}
+
+type Step = (Walk, Result<Option<StateItem>, ContractStateError>);
+
+impl ContractStateStream {
+    /// The height and hash of the block the snapshot is taken at, once the first query returns.
+    pub fn block(&self) -> Option<(BlockHeight, CryptoHash)> {
+        self.block
+    }
+}
+
+impl Stream for ContractStateStream {
+    type Item = Result<StateItem, ContractStateError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if this.pending.is_none() {
+            match this.walk.take() {
+                Some(walk) => this.pending = Some(walk.next().boxed()),
+                None => return Poll::Ready(None),
+            }
+        }
+
+        let pending = this.pending.as_mut().expect("a query is in flight");
+        let (walk, result) = futures::ready!(pending.poll_unpin(cx));
+        this.pending = None;
+        this.block = walk.block;
+
+        match result {
+            Ok(Some(item)) => {
+                this.walk = Some(walk);
+                Poll::Ready(Some(Ok(item)))
+            }
+            Ok(None) => Poll::Ready(None),
+            Err(err) => Poll::Ready(Some(Err(err))),
+        }
+    }
+}
+
+impl std::fmt::Debug for ContractStateStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ContractStateStream")
+            .field("block", &self.block)
+            .field("done", &(self.walk.is_none() && self.pending.is_none()))
+            .finish()
+    }
+}
+
+/// A depth-first walk over the prefixes of the key space.
+struct Walk {
+    reader: ContractStateReader,
+    block: Option<(BlockHeight, CryptoHash)>,
+    /// Prefixes left to query, the next one is at the end.
+    prefixes: Vec<Vec<u8>>,
+    items: VecDeque<StateItem>,
+    /// Whether or not the node limits the state per prefix, rather than per account.
+    splittable: bool,
+    /// The state root proofs are checked against, once it's found.
+    state_root: Option<StateRoot>,
+    /// Split prefixes yet to be checked for a key exactly equal to them.
+    split: Vec<Vec<u8>>,
+}
+
+impl Walk {
+    async fn next(mut self) -> Step {
+        let result = self.advance().await;
+        (self, result)
+    }
+
+    async fn advance(&mut self) -> Result<Option<StateItem>, ContractStateError> {
+        if self.reader.include_proof && self.state_root.is_none() {
+            let (block_hash, state_root) = self
+                .reader
+                .client
+                .state_root(&self.reader.account_id, self.reader.block_reference.clone())
+                .await
+                .map_err(ContractStateError::StateRootError)?;
+            self.reader.block_reference = BlockReference::BlockId(BlockId::Hash(block_hash));
+            self.state_root = Some(state_root);
+        }
+
+        loop {
+            if let Some(item) = self.items.pop_front() {
+                return Ok(Some(item));
+            }
+
+            let Some(prefix) = self.prefixes.pop() else {
+                return Ok(None);
+            };
+
+            match self.view_state(&prefix).await? {
+                Some(result) => {
+                    self.check_split(&result.proof)?;
+                    let values = match self.state_root {
+                        Some(state_root) => verify_state_proof(
+                            state_root,
+                            &self.reader.account_id,
+                            &prefix,
+                            &result,
+                        )
+                        .map_err(ContractStateError::ProofError)?,
+                        None => result.values,
+                    };
+                    self.items.extend(values);
+                }
+                None => {
+                    if prefix.len() - self.reader.prefix.len() >= self.reader.max_split_depth {
+                        return Err(self.too_large(prefix));
+                    }
+
+                    if !self.splittable {
+                        let probe = rand::random::<[u8; 32]>();
+                        if self.view_state(&probe).await?.is_none() {
+                            return Err(self.too_large(prefix));
+                        }
+                        self.splittable = true;
+                    }
+
+                    log::debug!(
+                        "state of [{}] under prefix [{:?}] is too large, splitting",
+                        self.reader.account_id,
+                        prefix
+                    );
+                    // reversed, so the children are popped in key order
+                    self.split.push(prefix.clone());
+                    self.prefixes.extend((0..=u8::MAX).rev().map(|byte| {
+                        let mut child = prefix.clone();
+                        child.push(byte);
+                        child
+                    }));
+                }
+            }
+        }
+    }
+
+    /// Fail if a key exactly equal to a split prefix exists, going by the proof of a query under it.
+    #[allow(clippy::result_large_err)]
+    fn check_split(&mut self, proof: &[Arc<[u8]>]) -> Result<(), ContractStateError> {
+        if self.split.is_empty() {
+            return Ok(());
+        }
+
+        // without a known state root, the server is trusted like it is for the values
+        let state_root = self
+            .state_root
+            .or_else(|| proof_root(proof))
+            .ok_or(ContractStateError::ProofError(StateProofError::MissingRoot))?;
+
+        for prefix in std::mem::take(&mut self.split) {
+            let value_hash = proven_value_hash(state_root, &self.reader.account_id, &prefix, proof)
+                .map_err(ContractStateError::ProofError)?;
+            if value_hash.is_some() {
+                return Err(ContractStateError::UnreachableKey {
+                    account_id: self.reader.account_id.clone(),
+                    key: prefix.into(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Query the state under `prefix`, returning `None` if it's too large.
+    async fn view_state(
+        &mut self,
+        prefix: &[u8],
+    ) -> Result<Option<ViewStateResult>, ContractStateError> {
+        let block_reference = match self.block {
+            Some((_, block_hash)) => BlockReference::BlockId(BlockId::Hash(block_hash)),
+            None => self.reader.block_reference.clone(),
+        };
+
+        let response = self
+            .reader
+            .client
+            .call(ViewStateRequest {
+                block_reference,
+                account_id: self.reader.account_id.clone(),
+                prefix: prefix.to_vec().into(),
+                include_proof: self.state_root.is_some() || !self.split.is_empty(),
+            })
+            .await;
+
+        let response = match response {
+            Ok(response) => response,
+            Err(err) => match err.handler_error() {
+                Some(RpcQueryError::TooLargeContractState {
+                    block_height,
+                    block_hash,
+                    ..
+                }) => {
+                    self.block.get_or_insert((*block_height, *block_hash));
+                    return Ok(None);
+                }
+                _ => return Err(ContractStateError::QueryError(err)),
+            },
+        };
+
+        self.block
+            .get_or_insert((response.block_height, response.block_hash));
+
+        Ok(Some(response.view))
+    }
+
+    fn too_large(&self, prefix: Vec<u8>) -> ContractStateError {
+        ContractStateError::StateTooLarge {
+            account_id: self.reader.account_id.clone(),
+            prefix: prefix.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use futures::TryStreamExt;
+    use near_chain_configs::{GenesisConfig, ProtocolConfig, ProtocolConfigView};
+    use near_primitives::hash::hash;
+    use near_primitives::trie_key::TrieKey;
+    use near_primitives::types::Finality;
+    use reqwest::StatusCode;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::testing::fixtures;
+    use crate::transport::{Transport, TransportFuture, TransportRequest, TransportResponse};
+
+    /// A node that refuses to return more than `limit` values at once,
+    /// or anything at all if the account is over the limit and `account_wide` is set.
+    ///
+    /// Its proofs hold the whole trie.
+    #[derive(Debug)]
+    struct Node {
+        state: Vec<(&'static [u8], &'static [u8])>,
+        limit: usize,
+        account_wide: bool,
+        queries: AtomicUsize,
+    }
+
+    impl Node {
+        fn new(state: Vec<(&'static [u8], &'static [u8])>, limit: usize) -> Self {
+            Self {
+                state,
+                limit,
+                account_wide: false,
+                queries: AtomicUsize::new(0),
+            }
+        }
+
+        fn result(&self, request: &Value) -> Value {
+            let params = &request["params"];
+            let (state_root, proof) = trie(&self.state);
+
+            match request["method"].as_str() {
+                Some("block") => return json!({ "result": block(state_root) }),
+                Some("EXPERIMENTAL_protocol_config") => {
+                    let config = ProtocolConfigView::from(ProtocolConfig {
+                        genesis_config: GenesisConfig::default(),
+                        runtime_config: near_parameters::RuntimeConfig::test(),
+                    });
+                    return json!({ "result": config });
+                }
+                _ => {}
+            }
+
+            assert_eq!(params["request_type"], "view_state");
+            if self.queries.fetch_add(1, Ordering::SeqCst) > 0 {
+                // every query after the first one is pinned to its block
+                assert_eq!(params["block_id"], json!(CryptoHash::default()));
+            }
+
+            let prefix = serde_json::from_value::<StoreKey>(params["prefix_base64"].clone())
+                .unwrap()
+                .to_vec();
+            let values = self
+                .state
+                .iter()
+                .filter(|(key, _)| key.starts_with(&prefix))
+                .map(|(key, value)| StateItem {
+                    key: key.to_vec().into(),
+                    value: value.to_vec().into(),
+                })
+                .collect::<Vec<_>>();
+
+            let too_large = if self.account_wide {
+                self.state.len() > self.limit
+            } else {
+                values.len() > self.limit
+            };
+            if too_large {
+                return json!({
+                    "error": {
+                        "code": -32000,
+                        "message": "Server error",
+                        "name": "HANDLER_ERROR",
+                        "cause": {
+                            "name": "TOO_LARGE_CONTRACT_STATE",
+                            "info": {
+                                "contract_account_id": "alice.near",
+                                "block_height": 7,
+                                "block_hash": CryptoHash::default(),
+                            },
+                        },
+                    },
+                });
+            }
+
+            let proof = match params["include_proof"].as_bool() {
+                Some(true) => proof,
+                _ => vec![],
+            };
+            let mut result = json!(ViewStateResult { values, proof });
+            result["block_height"] = json!(7);
+            result["block_hash"] = json!(CryptoHash::default());
+            json!({ "result": result })
+        }
+    }
+
+    impl Transport for Node {
+        fn send(&self, request: TransportRequest) -> TransportFuture<'_> {
+            let request = serde_json::from_slice::<Value>(&request.body).unwrap();
+            let mut message = self.result(&request);
+            message["jsonrpc"] = json!("2.0");
+            message["id"] = json!("dontcare");
+
+            Box::pin(async move {
+                Ok(TransportResponse::new(
+                    StatusCode::OK,
+                    message.to_string().into(),
+                ))
+            })
+        }
+    }
+
+    /// A block at #8, whose chunk commits to `state_root`, on top of the block the state is read at.
+    fn block(state_root: StateRoot) -> Value {
+        fixtures::block(8, vec![fixtures::chunk_header(8, state_root)])
+    }
+
+    /// Build the trie of the contract data of `alice.near`, returning its root, and all of its
+    /// nodes and values.
+    fn trie(state: &[(&[u8], &[u8])]) -> (StateRoot, Vec<Arc<[u8]>>) {
+        let entries = state
+            .iter()
+            .map(|(key, value)| {
+                let key = TrieKey::ContractData {
+                    account_id: "alice.near".parse().unwrap(),
+                    key: key.to_vec(),
+                };
+                (nibbles(&key.to_vec()), value.to_vec())
+            })
+            .collect::<Vec<_>>();
+
+        let mut nodes = vec![];
+        let state_root = trie_node(&entries, &mut nodes);
+        (state_root, nodes)
+    }
+
+    fn trie_node(entries: &[(Vec<u8>, Vec<u8>)], nodes: &mut Vec<Arc<[u8]>>) -> CryptoHash {
+        let strip = |len: usize, entries: &mut dyn Iterator<Item = &(Vec<u8>, Vec<u8>)>| {
+            entries
+                .map(|(key, value)| (key[len..].to_vec(), value.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        let node = if let [(key, value)] = entries {
+            nodes.push(value.clone().into());
+            [&[0][..], &partial_key(key, true), &value_ref(value)].concat()
+        } else {
+            let common = (0..)
+                .take_while(|&i| {
+                    entries
+                        .iter()
+                        .all(|(key, _)| key.get(i).is_some() && key[i] == entries[0].0[i])
+                })
+                .count();
+            if common > 0 {
+                let child = trie_node(&strip(common, &mut entries.iter()), nodes);
+                [
+                    &[3][..],
+                    &partial_key(&entries[0].0[..common], false),
+                    child.as_ref(),
+                ]
+                .concat()
+            } else {
+                let mut bitmap = 0u16;
+                let mut children = vec![];
+                for nibble in 0..16 {
+                    let mut group = entries
+                        .iter()
+                        .filter(|(key, _)| key.first() == Some(&nibble));
+                    let group = strip(1, &mut group);
+                    if !group.is_empty() {
+                        bitmap |= 1 << nibble;
+                        children.extend(trie_node(&group, nodes).0);
+                    }
+                }
+                match entries.iter().find(|(key, _)| key.is_empty()) {
+                    Some((_, value)) => {
+                        nodes.push(value.clone().into());
+                        [
+                            &[2][..],
+                            &value_ref(value),
+                            &bitmap.to_le_bytes(),
+                            &children,
+                        ]
+                        .concat()
+                    }
+                    None => [&[1][..], &bitmap.to_le_bytes(), &children].concat(),
+                }
+            }
+        };
+        // memory usage
+        let node = [node, 0u64.to_le_bytes().to_vec()].concat();
+
+        let node_hash = hash(&node);
+        nodes.push(node.into());
+        node_hash
+    }
+
+    fn nibbles(bytes: &[u8]) -> Vec<u8> {
+        bytes
+            .iter()
+            .flat_map(|byte| [byte >> 4, byte & 0x0f])
+            .collect()
+    }
+
+    /// A hex-prefix encoded partial key, prefixed with its length.
+    fn partial_key(nibbles: &[u8], is_leaf: bool) -> Vec<u8> {
+        let odd = nibbles.len() % 2;
+        let mut encoded = vec![if is_leaf { 0x20 } else { 0 }];
+        if odd == 1 {
+            encoded[0] |= 0x10 | nibbles[0];
+        }
+        encoded.extend(nibbles[odd..].chunks(2).map(|pair| pair[0] << 4 | pair[1]));
+        [(encoded.len() as u32).to_le_bytes().to_vec(), encoded].concat()
+    }
+
+    fn value_ref(value: &[u8]) -> Vec<u8> {
+        [
+            &(value.len() as u32).to_le_bytes()[..],
+            hash(value).as_ref(),
+        ]
+        .concat()
+    }
+
+    fn reader(node: Arc<Node>) -> ContractStateReader {
+        ContractStateReader::new(
+            JsonRpcClient::with_transport(node).connect("http://localhost:3030"),
+            "alice.near".parse().unwrap(),
+            BlockReference::Finality(Finality::Final),
+        )
+    }
+
+    #[tokio::test]
+    async fn splits_large_prefixes() {
+        for include_proof in [false, true] {
+            let node = Node::new(
+                vec![(b"a1", b"1"), (b"a2", b"2"), (b"a3", b"3"), (b"b1", b"4")],
+                2,
+            );
+            let mut state = reader(Arc::new(node)).with_proofs(include_proof).stream();
+
+            let mut items = vec![];
+            while let Some(item) = state.try_next().await.expect("state must be downloaded") {
+                items.push((item.key.to_vec(), item.value.to_vec()));
+            }
+
+            assert_eq!(
+                items,
+                [
+                    (b"a1".to_vec(), b"1".to_vec()),
+                    (b"a2".to_vec(), b"2".to_vec()),
+                    (b"a3".to_vec(), b"3".to_vec()),
+                    (b"b1".to_vec(), b"4".to_vec()),
+                ]
+            );
+            assert_eq!(state.block(), Some((7, CryptoHash::default())));
+        }
+    }
+
+    #[tokio::test]
+    async fn fails_on_keys_equal_to_split_prefixes() {
+        for include_proof in [false, true] {
+            let node = Node::new(
+                vec![(b"a", b"0"), (b"a1", b"1"), (b"a2", b"2"), (b"b1", b"4")],
+                2,
+            );
+            let result = reader(Arc::new(node))
+                .with_proofs(include_proof)
+                .stream()
+                .try_collect::<Vec<_>>()
+                .await;
+
+            assert!(
+                matches!(result, Err(ContractStateError::UnreachableKey { ref key, .. }) if **key == *b"a"),
+                "expected an UnreachableKey error, found [{:?}]",
+                result
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_proofs_of_another_state() {
+        let node = Node::new(vec![(b"a1", b"1")], 2);
+        let mut state = reader(Arc::new(node)).stream();
+        // as if the node served proofs of another state than the one committed to
+        state.walk.as_mut().unwrap().state_root = Some(hash(b"another root"));
+
+        let result = state.try_collect::<Vec<_>>().await;
+        assert!(
+            matches!(result, Err(ContractStateError::ProofError(_))),
+            "expected a ProofError, found [{:?}]",
+            result
+        );
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_account_wide_limits() {
+        let node = Arc::new(Node {
+            account_wide: true,
+            ..Node::new(vec![(b"a1", b"1"), (b"b1", b"2")], 1)
+        });
+
+        let result = reader(node.clone()).stream().try_collect::<Vec<_>>().await;
+
+        assert!(
+            matches!(result, Err(ContractStateError::StateTooLarge { ref prefix, .. }) if prefix.is_empty()),
+            "expected a StateTooLarge error, found [{:?}]",
+            result
+        );
+        // the initial query, and the probe
+        assert_eq!(node.queries.load(Ordering::SeqCst), 2);
+    }
+}
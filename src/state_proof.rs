@@ -0,0 +1,644 @@
+//! Verified contract state reads.
+//!
+//! A `view_state` query with `include_proof` set returns, alongside the values, every trie node the node
+//! visited while reading them. [`verify_state_proof`] walks those nodes down from a trusted state root,
+//! and checks that the values returned are exactly the ones stored under the requested prefix, none
+//! modified, added or left out.
+//!
+//! [`JsonRpcClient::view_state_verified`] takes the state root from a block's chunk header. The chunk
+//! included in a block commits to the state right after its parent block, so the state is read at the
+//! parent of the referenced block, and the referenced block must include a new chunk for the shard of
+//! the account.
+//!
+//! Note that the chunk header is trusted as served by the RPC server. Check the block against a
+//! [`LightClient`](crate::light_client::LightClient) to remove that trust.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use near_jsonrpc_client::JsonRpcClient;
+//! use near_primitives::types::{BlockReference, Finality};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+//! let client = JsonRpcClient::connect("https://rpc.testnet.near.org");
+//!
+//! let state = client
+//!     .view_state_verified(
+//!         "guest-book.testnet".parse()?,
+//!         b"m".to_vec(),
+//!         BlockReference::Finality(Finality::Final),
+//!     )
+//!     .await?;
+//!
+//! for item in state.values {
+//!     println!("{:?} = {:?}", item.key, item.value);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+
+use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::shard_layout::account_id_to_shard_id;
+use near_primitives::trie_key::TrieKey;
+use near_primitives::types::{AccountId, BlockHeight, BlockId, BlockReference, ShardId, StateRoot};
+use near_primitives::views::{StateItem, ViewStateResult};
+use thiserror::Error;
+
+use crate::errors::JsonRpcError;
+use crate::methods::block::RpcBlockError;
+use crate::methods::query::{RpcQueryError, ViewStateRequest};
+use crate::methods::EXPERIMENTAL_protocol_config::RpcProtocolConfigError;
+use crate::{methods, JsonRpcClient};
+
+/// Potential errors returned while checking a state proof.
+#[derive(Debug, Error)]
+pub enum StateProofError {
+    /// A node or value needed to read the state isn't part of the proof.
+    #[error("trie node [{hash}] is missing from the proof")]
+    MissingNode { hash: CryptoHash },
+    /// A node in the proof couldn't be decoded.
+    #[error("trie node [{hash}] is malformed")]
+    InvalidNode { hash: CryptoHash },
+    /// A value was returned that isn't stored under its key.
+    #[error("value of key [{key:?}] doesn't match the proof")]
+    ValueMismatch { key: Vec<u8> },
+    /// A value stored under the prefix wasn't returned.
+    #[error("value of key [{key:?}] was left out")]
+    MissingValue { key: Vec<u8> },
+    /// The proof has no single root node, and no state root was given.
+    #[error("the proof has no single root node")]
+    MissingRoot,
+}
+
+/// Potential errors returned by a verified state read.
+#[derive(Debug, Error)]
+pub enum VerifiedStateError {
+    /// The block couldn't be fetched.
+    #[error(transparent)]
+    BlockError(JsonRpcError<RpcBlockError>),
+    /// The shard layout couldn't be fetched.
+    #[error(transparent)]
+    ProtocolConfigError(JsonRpcError<RpcProtocolConfigError>),
+    /// The `view_state` query failed.
+    #[error(transparent)]
+    QueryError(JsonRpcError<RpcQueryError>),
+    /// The block has no new chunk for the shard of the account, so it doesn't commit to its state.
+    #[error("block [{block_hash}] has no new chunk for shard {shard_id}")]
+    MissingChunk {
+        block_hash: CryptoHash,
+        shard_id: ShardId,
+    },
+    /// The values returned don't match the state root.
+    #[error(transparent)]
+    ProofError(#[from] StateProofError),
+}
+
+/// Contract state checked against a state root.
+#[derive(Debug, Clone)]
+pub struct VerifiedState {
+    /// The key/value pairs stored under the prefix, in key order.
+    pub values: Vec<StateItem>,
+    /// The height of the block the state was read at.
+    pub block_height: BlockHeight,
+    /// The hash of the block the state was read at.
+    pub block_hash: CryptoHash,
+    /// The state root the values were checked against.
+    pub state_root: StateRoot,
+}
+
+impl JsonRpcClient {
+    /// Read the state of a contract under `prefix`, and check it against the state root of its shard.
+    ///
+    /// See the [`state_proof`](crate::state_proof) module documentation for more information.
+    pub async fn view_state_verified(
+        &self,
+        account_id: AccountId,
+        prefix: Vec<u8>,
+        block_reference: BlockReference,
+    ) -> Result<VerifiedState, VerifiedStateError> {
+        let (block_hash, state_root) = self.state_root(&account_id, block_reference).await?;
+
+        let response = self
+            .call(ViewStateRequest {
+                block_reference: BlockReference::BlockId(BlockId::Hash(block_hash)),
+                account_id: account_id.clone(),
+                prefix: prefix.clone().into(),
+                include_proof: true,
+            })
+            .await
+            .map_err(VerifiedStateError::QueryError)?;
+
+        let values = verify_state_proof(state_root, &account_id, &prefix, &response.view)?;
+
+        Ok(VerifiedState {
+            values,
+            block_height: response.block_height,
+            block_hash: response.block_hash,
+            state_root,
+        })
+    }
+
+    /// Find the state root of the shard of `account_id` committed to by the referenced block,
+    /// along with the hash of the block whose state that is, its parent.
+    pub(crate) async fn state_root(
+        &self,
+        account_id: &AccountId,
+        block_reference: BlockReference,
+    ) -> Result<(CryptoHash, StateRoot), VerifiedStateError> {
+        let block = self
+            .call(methods::block::RpcBlockRequest { block_reference })
+            .await
+            .map_err(VerifiedStateError::BlockError)?;
+
+        let config = self
+            .call(
+                methods::EXPERIMENTAL_protocol_config::RpcProtocolConfigRequest {
+                    block_reference: BlockReference::BlockId(BlockId::Hash(block.header.hash)),
+                },
+            )
+            .await
+            .map_err(VerifiedStateError::ProtocolConfigError)?;
+
+        let shard_id = account_id_to_shard_id(account_id, &config.shard_layout);
+        let state_root = block
+            .chunks
+            .iter()
+            .find(|chunk| {
+                chunk.shard_id == shard_id && chunk.height_included == block.header.height
+            })
+            .map(|chunk| chunk.prev_state_root)
+            .ok_or(VerifiedStateError::MissingChunk {
+                block_hash: block.header.hash,
+                shard_id,
+            })?;
+
+        Ok((block.header.prev_hash, state_root))
+    }
+}
+
+/// Check the result of a `view_state` query for `account_id` under `prefix` against a state root.
+///
+/// Returns the values, once every one of them is proven to be stored under its key, and no other
+/// value is stored under the prefix.
+pub fn verify_state_proof(
+    state_root: StateRoot,
+    account_id: &AccountId,
+    prefix: &[u8],
+    result: &ViewStateResult,
+) -> Result<Vec<StateItem>, StateProofError> {
+    let proof = Proof {
+        nodes: result
+            .proof
+            .iter()
+            .map(|node| (hash(node), &node[..]))
+            .collect(),
+    };
+
+    let account_prefix = TrieKey::ContractData {
+        account_id: account_id.clone(),
+        key: vec![],
+    }
+    .to_vec();
+    let key_prefix = [&account_prefix[..], prefix].concat();
+
+    let mut stored = vec![];
+    if state_root != CryptoHash::default() {
+        proof.collect(state_root, &mut vec![], &nibbles(&key_prefix), &mut stored)?;
+    }
+
+    let mut stored = stored
+        .into_iter()
+        .map(|(key, value)| (key[account_prefix.len()..].to_vec(), value))
+        .collect::<BTreeMap<_, _>>();
+
+    for item in &result.values {
+        match stored.remove(&item.key[..]) {
+            Some(value_hash) if value_hash == hash(&item.value) => {}
+            _ => {
+                return Err(StateProofError::ValueMismatch {
+                    key: item.key.to_vec(),
+                })
+            }
+        }
+    }
+
+    if let Some((key, _)) = stored.into_iter().next() {
+        return Err(StateProofError::MissingValue { key });
+    }
+
+    let mut values = result.values.clone();
+    values.sort_by(|a, b| a.key.cmp(&b.key));
+    Ok(values)
+}
+
+/// Look up the hash of the value stored under `key` of `account_id`, with the nodes of a proof
+/// that covers the path to it.
+pub(crate) fn proven_value_hash(
+    state_root: StateRoot,
+    account_id: &AccountId,
+    key: &[u8],
+    proof: &[Arc<[u8]>],
+) -> Result<Option<CryptoHash>, StateProofError> {
+    if state_root == CryptoHash::default() {
+        return Ok(None);
+    }
+
+    let proof = Proof {
+        nodes: proof.iter().map(|node| (hash(node), &node[..])).collect(),
+    };
+
+    let path = nibbles(
+        &TrieKey::ContractData {
+            account_id: account_id.clone(),
+            key: key.to_vec(),
+        }
+        .to_vec(),
+    );
+
+    let (mut hash, mut rest) = (state_root, &path[..]);
+    loop {
+        match proof.node(hash)? {
+            Node::Leaf(key, value_hash) => return Ok((key[..] == *rest).then_some(value_hash)),
+            Node::Extension(key, child) => match rest.strip_prefix(&key[..]) {
+                Some(tail) => (hash, rest) = (child, tail),
+                None => return Ok(None),
+            },
+            Node::Branch(value_hash, children) => match rest.split_first() {
+                None => return Ok(value_hash),
+                Some((&nibble, tail)) => match children[nibble as usize] {
+                    Some(child) => (hash, rest) = (child, tail),
+                    None => return Ok(None),
+                },
+            },
+        }
+    }
+}
+
+/// Find the root of the nodes of a proof, the only one no other node refers to.
+pub(crate) fn proof_root(proof: &[Arc<[u8]>]) -> Option<StateRoot> {
+    let nodes = proof
+        .iter()
+        .filter_map(|node| Some((hash(node), decode_node(node)?)))
+        .collect::<HashMap<_, _>>();
+
+    let children = nodes
+        .values()
+        .flat_map(|node| match node {
+            Node::Leaf(..) => vec![],
+            Node::Extension(_, child) => vec![*child],
+            Node::Branch(_, children) => children.iter().flatten().copied().collect(),
+        })
+        .collect::<HashSet<_>>();
+
+    let mut roots = nodes.keys().filter(|hash| !children.contains(hash));
+    match (roots.next(), roots.next()) {
+        (Some(root), None) => Some(*root),
+        _ => None,
+    }
+}
+
+/// A decoded trie node, with its partial keys as nibbles.
+enum Node {
+    Leaf(Vec<u8>, CryptoHash),
+    Branch(Option<CryptoHash>, Box<[Option<CryptoHash>; 16]>),
+    Extension(Vec<u8>, CryptoHash),
+}
+
+struct Proof<'a> {
+    nodes: HashMap<CryptoHash, &'a [u8]>,
+}
+
+impl Proof<'_> {
+    fn get(&self, hash: CryptoHash) -> Result<&[u8], StateProofError> {
+        self.nodes
+            .get(&hash)
+            .copied()
+            .ok_or(StateProofError::MissingNode { hash })
+    }
+
+    fn node(&self, hash: CryptoHash) -> Result<Node, StateProofError> {
+        decode_node(self.get(hash)?).ok_or(StateProofError::InvalidNode { hash })
+    }
+
+    /// Collect the keys under `prefix` of the subtree at `hash`, with the hashes of their values.
+    ///
+    /// `path` holds the nibbles leading to the subtree, and is compatible with the prefix.
+    fn collect(
+        &self,
+        hash: CryptoHash,
+        path: &mut Vec<u8>,
+        prefix: &[u8],
+        out: &mut Vec<(Vec<u8>, CryptoHash)>,
+    ) -> Result<(), StateProofError> {
+        let compatible = |path: &[u8]| path.starts_with(prefix) || prefix.starts_with(path);
+        let value = |path: &[u8], value: CryptoHash| {
+            let key = bytes(path).ok_or(StateProofError::InvalidNode { hash })?;
+            // the value itself has to be part of the proof too
+            self.get(value)?;
+            Ok((key, value))
+        };
+
+        match self.node(hash)? {
+            Node::Leaf(key, value_hash) => {
+                let len = path.len();
+                path.extend(key);
+                if path.starts_with(prefix) {
+                    out.push(value(path, value_hash)?);
+                }
+                path.truncate(len);
+            }
+            Node::Extension(key, child) => {
+                let len = path.len();
+                path.extend(key);
+                if compatible(path) {
+                    self.collect(child, path, prefix, out)?;
+                }
+                path.truncate(len);
+            }
+            Node::Branch(value_hash, children) => {
+                if let Some(value_hash) = value_hash.filter(|_| path.starts_with(prefix)) {
+                    out.push(value(path, value_hash)?);
+                }
+                for (nibble, child) in (0..).zip(*children) {
+                    let Some(child) = child else { continue };
+                    path.push(nibble);
+                    if compatible(path) {
+                        self.collect(child, path, prefix, out)?;
+                    }
+                    path.pop();
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Decode a borsh-serialized `RawTrieNodeWithSize`.
+fn decode_node(bytes: &[u8]) -> Option<Node> {
+    let mut reader = Reader(bytes);
+    let node = match reader.u8()? {
+        0 => {
+            let key = decode_nibbles(reader.vec()?)?;
+            Node::Leaf(key, reader.value_ref()?)
+        }
+        1 => Node::Branch(None, reader.children()?),
+        2 => {
+            let value = reader.value_ref()?;
+            Node::Branch(Some(value), reader.children()?)
+        }
+        3 => {
+            let key = decode_nibbles(reader.vec()?)?;
+            Node::Extension(key, reader.hash()?)
+        }
+        _ => return None,
+    };
+    // memory usage
+    reader.take(8)?;
+    reader.0.is_empty().then_some(node)
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Some(head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes(self.take(2)?.try_into().ok()?))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn vec(&mut self) -> Option<&'a [u8]> {
+        let len = self.u32()?;
+        self.take(len as usize)
+    }
+
+    fn hash(&mut self) -> Option<CryptoHash> {
+        Some(CryptoHash(self.take(32)?.try_into().ok()?))
+    }
+
+    /// A `ValueRef`, of which only the hash matters.
+    fn value_ref(&mut self) -> Option<CryptoHash> {
+        self.u32()?;
+        self.hash()
+    }
+
+    /// A bitmap of the children present, followed by their hashes.
+    fn children(&mut self) -> Option<Box<[Option<CryptoHash>; 16]>> {
+        let bitmap = self.u16()?;
+        let mut children = Box::new([None; 16]);
+        for (i, child) in children.iter_mut().enumerate() {
+            if bitmap & (1 << i) != 0 {
+                *child = Some(self.hash()?);
+            }
+        }
+        Some(children)
+    }
+}
+
+/// Decode a hex-prefix encoded partial key.
+///
+/// The high nibble of the first byte holds flags, its low nibble is the first nibble of the key
+/// when the key has an odd length.
+fn decode_nibbles(encoded: &[u8]) -> Option<Vec<u8>> {
+    let (&first, rest) = encoded.split_first()?;
+    let mut key = Vec::with_capacity(rest.len() * 2 + 1);
+    if first & 0x10 != 0 {
+        key.push(first & 0x0f);
+    }
+    key.extend(nibbles(rest));
+    Some(key)
+}
+
+fn nibbles(bytes: &[u8]) -> Vec<u8> {
+    bytes
+        .iter()
+        .flat_map(|byte| [byte >> 4, byte & 0x0f])
+        .collect()
+}
+
+fn bytes(nibbles: &[u8]) -> Option<Vec<u8>> {
+    let chunks = nibbles.chunks_exact(2);
+    chunks
+        .remainder()
+        .is_empty()
+        .then(|| chunks.map(|pair| pair[0] << 4 | pair[1]).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_nibbles(nibbles: &[u8], is_leaf: bool) -> Vec<u8> {
+        let odd = nibbles.len() % 2;
+        let mut encoded = vec![if is_leaf { 0x20 } else { 0 }];
+        if odd == 1 {
+            encoded[0] |= 0x10 | nibbles[0];
+        }
+        encoded.extend(bytes(&nibbles[odd..]).unwrap());
+        encoded
+    }
+
+    fn node(tag: u8, body: &[&[u8]]) -> Vec<u8> {
+        let mut node = vec![tag];
+        body.iter().for_each(|part| node.extend(*part));
+        node.extend(0u64.to_le_bytes());
+        node
+    }
+
+    fn leaf(key: &[u8], value: &[u8]) -> Vec<u8> {
+        let key = encode_nibbles(key, true);
+        node(
+            0,
+            &[
+                &(key.len() as u32).to_le_bytes(),
+                &key,
+                &(value.len() as u32).to_le_bytes(),
+                hash(value).as_ref(),
+            ],
+        )
+    }
+
+    /// The state of `alice.near`, with `a = 1` and `b = 2`.
+    fn state() -> (StateRoot, Vec<Arc<[u8]>>) {
+        let account_prefix = TrieKey::ContractData {
+            account_id: "alice.near".parse().unwrap(),
+            key: vec![],
+        }
+        .to_vec();
+
+        let leaf_a = leaf(&[], b"1");
+        let leaf_b = leaf(&[], b"2");
+        // 'a' and 'b' are 0x61 and 0x62
+        let branch = node(
+            1,
+            &[
+                &0b110u16.to_le_bytes(),
+                hash(&leaf_a).as_ref(),
+                hash(&leaf_b).as_ref(),
+            ],
+        );
+        let mut key = nibbles(&account_prefix);
+        key.push(6);
+        let key = encode_nibbles(&key, false);
+        let root = node(
+            3,
+            &[
+                &(key.len() as u32).to_le_bytes(),
+                &key,
+                hash(&branch).as_ref(),
+            ],
+        );
+
+        let proof = [
+            root.clone(),
+            branch,
+            leaf_a,
+            leaf_b,
+            b"1".to_vec(),
+            b"2".to_vec(),
+        ];
+        (hash(&root), proof.into_iter().map(Into::into).collect())
+    }
+
+    fn item(key: &[u8], value: &[u8]) -> StateItem {
+        StateItem {
+            key: key.to_vec().into(),
+            value: value.to_vec().into(),
+        }
+    }
+
+    #[test]
+    fn verifies_values() {
+        let (state_root, proof) = state();
+        let account_id = "alice.near".parse().unwrap();
+
+        let result = ViewStateResult {
+            values: vec![item(b"a", b"1"), item(b"b", b"2")],
+            proof: proof.clone(),
+        };
+        let values = verify_state_proof(state_root, &account_id, b"", &result).unwrap();
+        assert_eq!(values, result.values);
+
+        let result = ViewStateResult {
+            values: vec![item(b"b", b"2")],
+            proof,
+        };
+        let values = verify_state_proof(state_root, &account_id, b"b", &result).unwrap();
+        assert_eq!(values, result.values);
+    }
+
+    #[test]
+    fn rejects_tampered_values() {
+        let (state_root, proof) = state();
+        let account_id = "alice.near".parse().unwrap();
+
+        let result = ViewStateResult {
+            values: vec![item(b"a", b"1"), item(b"b", b"3")],
+            proof: proof.clone(),
+        };
+        assert!(matches!(
+            verify_state_proof(state_root, &account_id, b"", &result),
+            Err(StateProofError::ValueMismatch { key }) if key == b"b"
+        ));
+
+        let result = ViewStateResult {
+            values: vec![item(b"a", b"1")],
+            proof: proof.clone(),
+        };
+        assert!(matches!(
+            verify_state_proof(state_root, &account_id, b"", &result),
+            Err(StateProofError::MissingValue { key }) if key == b"b"
+        ));
+
+        let result = ViewStateResult {
+            values: vec![item(b"a", b"1"), item(b"b", b"2")],
+            proof: proof[..3].to_vec(),
+        };
+        assert!(matches!(
+            verify_state_proof(state_root, &account_id, b"", &result),
+            Err(StateProofError::MissingNode { .. })
+        ));
+
+        let result = ViewStateResult {
+            values: vec![item(b"a", b"1"), item(b"b", b"2")],
+            proof,
+        };
+        assert!(matches!(
+            verify_state_proof(hash(b"another root"), &account_id, b"", &result),
+            Err(StateProofError::MissingNode { .. })
+        ));
+    }
+
+    #[test]
+    fn looks_up_single_values() {
+        let (state_root, proof) = state();
+        let account_id = "alice.near".parse().unwrap();
+
+        assert_eq!(proof_root(&proof), Some(state_root));
+
+        let lookup = |key: &[u8]| proven_value_hash(state_root, &account_id, key, &proof).unwrap();
+        assert_eq!(lookup(b"a"), Some(hash(b"1")));
+        assert_eq!(lookup(b"b"), Some(hash(b"2")));
+        assert_eq!(lookup(b""), None);
+        assert_eq!(lookup(b"ab"), None);
+        assert_eq!(lookup(b"c"), None);
+    }
+}
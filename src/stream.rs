@@ -0,0 +1,362 @@
+//! Streaming blocks and their chunks.
+//!
+//! [`JsonRpcClient::blocks_from`] returns a [`BlockStream`], a [`futures::Stream`] yielding every block
+//! from the specified height onwards, in height order. Once the stream catches up with the chain,
+//! it polls for new blocks.
+//!
+//! Heights without a block (the server responds with `UnknownBlock`) are skipped. Keep in mind that
+//! non-archival nodes also respond with `UnknownBlock` for garbage collected heights, so streaming
+//! old blocks requires an archival endpoint.
+//!
+//! Optionally, all chunks of each block are fetched concurrently with [`BlockStream::with_chunks`].
+//!
+//! Errors don't end the stream, the block that failed is fetched again on the next poll.
+//! [`BlockStream::cursor`] is the next height to be fetched, so a stream can be resumed from there
+//! after reconnecting.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use near_jsonrpc_client::JsonRpcClient;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = JsonRpcClient::connect("https://archival-rpc.mainnet.near.org");
+//!
+//! let mut blocks = client.blocks_from(100_000_000).with_chunks(true);
+//!
+//! while let Some(block) = blocks.next().await {
+//!     let block = block?;
+//!     println!(
+//!         "#{} has {} transactions",
+//!         block.block.header.height,
+//!         block.chunks.iter().map(|chunk| chunk.transactions.len()).sum::<usize>()
+//!     );
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::{FutureExt, Stream};
+use near_jsonrpc_primitives::types::chunks::ChunkReference;
+use near_primitives::types::{BlockHeight, BlockId, BlockReference, Finality};
+use near_primitives::views::{BlockView, ChunkView};
+use thiserror::Error;
+
+use crate::errors::*;
+use crate::methods::block::RpcBlockError;
+use crate::methods::chunk::RpcChunkError;
+use crate::{methods, JsonRpcClient};
+
+/// Potential errors returned while streaming blocks.
+#[derive(Debug, Error)]
+pub enum BlockStreamError {
+    /// A block couldn't be fetched.
+    #[error("error while fetching block: [{0}]")]
+    BlockError(JsonRpcError<RpcBlockError>),
+    /// A chunk couldn't be fetched.
+    #[error("error while fetching chunk: [{0}]")]
+    ChunkError(JsonRpcError<RpcChunkError>),
+}
+
+/// A block yielded by a [`BlockStream`].
+#[derive(Debug)]
+pub struct StreamedBlock {
+    /// The block.
+    pub block: BlockView,
+    /// The chunks of the block, in shard order. Empty unless [`BlockStream::with_chunks`] is enabled.
+    ///
+    /// Chunks that weren't produced at this height are carried over from earlier blocks,
+    /// their `height_included` is lower than the block height.
+    pub chunks: Vec<ChunkView>,
+}
+
+type Step = (
+    BlockHeight,
+    Option<BlockHeight>,
+    Result<StreamedBlock, BlockStreamError>,
+);
+
+/// A stream of blocks, in height order.
+///
+/// See the [`stream`](self) module documentation for more information.
+pub struct BlockStream {
+    client: JsonRpcClient,
+    cursor: BlockHeight,
+    head: Option<BlockHeight>,
+    finality: Finality,
+    with_chunks: bool,
+    poll_interval: Duration,
+    pending: Option<BoxFuture<'static, Step>>,
+}
+
+impl JsonRpcClient {
+    /// Stream every block from the specified height onwards.
+    ///
+    /// See the [`stream`](crate::stream) module documentation for more information.
+    pub fn blocks_from(&self, height: BlockHeight) -> BlockStream {
+        BlockStream {
+            client: self.clone(),
+            cursor: height,
+            head: None,
+            finality: Finality::Final,
+            with_chunks: false,
+            poll_interval: Duration::from_secs(1),
+            pending: None,
+        }
+    }
+}
+
+impl BlockStream {
+    /// Fetch all chunks of each block, concurrently.
+    pub fn with_chunks(mut self, with_chunks: bool) -> Self {
+        self.with_chunks = with_chunks;
+        self
+    }
+
+    /// Set the finality of the blocks the stream follows.
+    ///
+    /// Defaults to [`Finality::Final`]. With any other finality, heights skipped at the time
+    /// they're fetched may be missed.
+    pub fn with_finality(mut self, finality: Finality) -> Self {
+        self.finality = finality;
+        self
+    }
+
+    /// Set how often the chain is polled for new blocks, once the stream catches up.
+    ///
+    /// Defaults to 1s.
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// The height of the next block to be fetched.
+    pub fn cursor(&self) -> BlockHeight {
+        self.cursor
+    }
+}
+
+impl Stream for BlockStream {
+    type Item = Result<StreamedBlock, BlockStreamError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let pending = this.pending.get_or_insert_with(|| {
+            next_block(
+                this.client.clone(),
+                this.cursor,
+                this.head,
+                this.finality.clone(),
+                this.with_chunks,
+                this.poll_interval,
+            )
+            .boxed()
+        });
+
+        let (cursor, head, result) = futures::ready!(pending.poll_unpin(cx));
+        this.pending = None;
+        this.cursor = cursor;
+        this.head = head;
+
+        Poll::Ready(Some(result))
+    }
+}
+
+impl std::fmt::Debug for BlockStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BlockStream")
+            .field("client", &self.client)
+            .field("cursor", &self.cursor)
+            .field("head", &self.head)
+            .field("finality", &self.finality)
+            .field("with_chunks", &self.with_chunks)
+            .field("poll_interval", &self.poll_interval)
+            .finish()
+    }
+}
+
+/// Fetch the first block at or above `cursor`, returning the updated cursor and known head.
+async fn next_block(
+    client: JsonRpcClient,
+    mut cursor: BlockHeight,
+    mut head: Option<BlockHeight>,
+    finality: Finality,
+    with_chunks: bool,
+    poll_interval: Duration,
+) -> Step {
+    loop {
+        if head.map_or(true, |head| cursor > head) {
+            let latest = client
+                .call(methods::block::RpcBlockRequest {
+                    block_reference: BlockReference::Finality(finality.clone()),
+                })
+                .await;
+
+            match latest {
+                Ok(latest) => head = Some(latest.header.height),
+                Err(err) => return (cursor, head, Err(BlockStreamError::BlockError(err))),
+            }
+
+            if head.map_or(true, |head| cursor > head) {
+                tokio::time::sleep(poll_interval).await;
+                continue;
+            }
+        }
+
+        let block = client
+            .call(methods::block::RpcBlockRequest {
+                block_reference: BlockReference::BlockId(BlockId::Height(cursor)),
+            })
+            .await;
+
+        let block = match block {
+            Ok(block) => block,
+            Err(err) => match err.handler_error() {
+                Some(RpcBlockError::UnknownBlock { .. }) => {
+                    log::debug!("no block at #{}, skipping", cursor);
+                    cursor += 1;
+                    continue;
+                }
+                _ => return (cursor, head, Err(BlockStreamError::BlockError(err))),
+            },
+        };
+
+        let chunks = if with_chunks {
+            let chunks = futures::future::try_join_all(block.chunks.iter().map(|chunk| {
+                client.call(methods::chunk::RpcChunkRequest {
+                    chunk_reference: ChunkReference::ChunkHash {
+                        chunk_id: chunk.chunk_hash,
+                    },
+                })
+            }))
+            .await;
+
+            match chunks {
+                Ok(chunks) => chunks,
+                Err(err) => return (cursor, head, Err(BlockStreamError::ChunkError(err))),
+            }
+        } else {
+            vec![]
+        };
+
+        return (cursor + 1, head, Ok(StreamedBlock { block, chunks }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use futures::StreamExt;
+    use near_primitives::hash::CryptoHash;
+    use reqwest::StatusCode;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::testing::fixtures;
+    use crate::transport::{Transport, TransportFuture, TransportRequest, TransportResponse};
+
+    fn block(height: BlockHeight) -> Value {
+        fixtures::block(height, vec![chunk_header(height)])
+    }
+
+    fn chunk_header(height: BlockHeight) -> Value {
+        fixtures::chunk_header(height, CryptoHash::default())
+    }
+
+    /// A chain whose final head is at #13, with no block at #11.
+    /// Fails the first request for #12, to exercise resuming.
+    #[derive(Debug, Default)]
+    struct Chain {
+        failed: AtomicBool,
+    }
+
+    impl Transport for Chain {
+        fn send(&self, request: TransportRequest) -> TransportFuture<'_> {
+            let request = serde_json::from_slice::<Value>(&request.body).unwrap();
+            let params = &request["params"];
+
+            let result = match request["method"].as_str() {
+                Some("block") if params["finality"] == "final" => Ok(block(13)),
+                Some("block") => match params["block_id"].as_u64() {
+                    Some(11) => Err(json!({
+                        "code": -32000,
+                        "message": "Server error",
+                        "name": "HANDLER_ERROR",
+                        "cause": { "name": "UNKNOWN_BLOCK", "info": {} },
+                        "data": "DB Not Found Error: BLOCK HEIGHT: 11",
+                    })),
+                    Some(12) if !self.failed.swap(true, Ordering::SeqCst) => Err(json!({
+                        "code": -32000,
+                        "message": "Server error",
+                        "name": "HANDLER_ERROR",
+                        "cause": { "name": "NOT_SYNCED_YET", "info": {} },
+                    })),
+                    Some(height) => Ok(block(height)),
+                    None => panic!("unexpected block request: {}", params),
+                },
+                Some("chunk") => {
+                    let mut chunk = json!({
+                        "author": "alice.near",
+                        "header": chunk_header(0),
+                        "transactions": [],
+                        "receipts": [],
+                    });
+                    chunk["header"]["chunk_hash"] = params["chunk_id"].clone();
+                    Ok(chunk)
+                }
+                method => panic!("unexpected method: {:?}", method),
+            };
+
+            let message = match result {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": "dontcare", "result": result }),
+                Err(error) => json!({ "jsonrpc": "2.0", "id": "dontcare", "error": error }),
+            };
+
+            Box::pin(async move {
+                Ok(TransportResponse::new(
+                    StatusCode::OK,
+                    message.to_string().into(),
+                ))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn skips_unknown_blocks_and_resumes() {
+        let client =
+            JsonRpcClient::with_transport(Chain::default()).connect("http://localhost:3030");
+
+        let mut blocks = client.blocks_from(10).with_chunks(true);
+
+        let block = blocks.next().await.unwrap().expect("#10 must be fetched");
+        assert_eq!(block.block.header.height, 10);
+        assert_eq!(block.chunks.len(), 1);
+        assert_eq!(
+            block.chunks[0].header.chunk_hash,
+            block.block.chunks[0].chunk_hash
+        );
+
+        // #11 is skipped, and #12 fails
+        assert!(matches!(
+            blocks.next().await,
+            Some(Err(BlockStreamError::BlockError(_)))
+        ));
+        assert_eq!(blocks.cursor(), 12);
+
+        // resuming from the cursor, on a fresh stream
+        let mut blocks = client.blocks_from(blocks.cursor());
+        for height in [12, 13] {
+            let block = blocks.next().await.unwrap().expect("block must be fetched");
+            assert_eq!(block.block.header.height, height);
+            assert!(block.chunks.is_empty());
+        }
+        assert_eq!(blocks.cursor(), 14);
+    }
+}
@@ -0,0 +1,160 @@
+//! A `tracing` span around every method call.
+//!
+//! Batches get a span of their own, as a call to `batch`.
+use tracing::field::Empty;
+use tracing::Span;
+
+use crate::errors::JsonRpcError;
+use crate::middleware::CallInfo;
+
+pub(crate) fn span(call: &CallInfo, server_addr: &str) -> Span {
+    tracing::info_span!(
+        "rpc.call",
+        "rpc.method" = call.method_name(),
+        "server.address" = server_addr,
+        "http.status" = Empty,
+        "rpc.attempts" = Empty,
+        "request.size" = Empty,
+        "response.size" = Empty,
+        "latency_ms" = Empty,
+        "error.class" = Empty,
+    )
+}
+
+pub(crate) fn record<T, E>(span: &Span, call: &CallInfo, result: &Result<T, JsonRpcError<E>>) {
+    if let Some(status) = call.status() {
+        span.record("http.status", status.as_u16());
+    }
+    if let Some(size) = call.request_size() {
+        span.record("request.size", size);
+    }
+    if let Some(size) = call.response_size() {
+        span.record("response.size", size);
+    }
+    span.record("rpc.attempts", call.attempt());
+    span.record("latency_ms", call.elapsed().as_secs_f64() * 1000.0);
+    if let Err(err) = result {
+        span.record("error.class", err.class().as_str());
+    }
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use std::collections::HashMap;
+    use std::fmt;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use reqwest::StatusCode;
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata};
+
+    use crate::methods;
+    use crate::testing::MockTransport;
+
+    type Fields = HashMap<&'static str, String>;
+
+    /// Keeps the fields recorded on every span, by span name.
+    #[derive(Default)]
+    struct Spans {
+        next_id: AtomicU64,
+        spans: Mutex<HashMap<u64, (&'static str, Fields)>>,
+    }
+
+    struct Recorder<'a>(&'a mut Fields);
+
+    impl Visit for Recorder<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0.insert(field.name(), format!("{:?}", value));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name(), value.to_string());
+        }
+    }
+
+    impl Spans {
+        fn named(&self, name: &str) -> Vec<Fields> {
+            let spans = self.spans.lock().unwrap();
+            let mut spans = spans
+                .iter()
+                .filter(|(_, (span_name, _))| *span_name == name)
+                .collect::<Vec<_>>();
+            spans.sort_by_key(|(id, _)| **id);
+            spans
+                .into_iter()
+                .map(|(_, (_, fields))| fields.clone())
+                .collect()
+        }
+    }
+
+    impl tracing::Subscriber for Spans {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let id = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+            let mut fields = Fields::new();
+            span.record(&mut Recorder(&mut fields));
+            self.spans
+                .lock()
+                .unwrap()
+                .insert(id, (span.metadata().name(), fields));
+            Id::from_u64(id)
+        }
+
+        fn record(&self, span: &Id, values: &Record<'_>) {
+            if let Some((_, fields)) = self.spans.lock().unwrap().get_mut(&span.into_u64()) {
+                values.record(&mut Recorder(fields));
+            }
+        }
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, _event: &Event<'_>) {}
+
+        fn enter(&self, _span: &Id) {}
+
+        fn exit(&self, _span: &Id) {}
+    }
+
+    #[tokio::test]
+    async fn records_calls_on_their_span() {
+        let spans = Arc::new(Spans::default());
+        let _guard = tracing::subscriber::set_default(spans.clone());
+
+        let mock = MockTransport::new();
+        mock.on("health").times(1).respond(());
+        mock.on("health")
+            .respond_status(StatusCode::SERVICE_UNAVAILABLE);
+        let client = mock.client();
+
+        client
+            .call(methods::health::RpcHealthRequest)
+            .await
+            .unwrap();
+        client
+            .call(methods::health::RpcHealthRequest)
+            .await
+            .unwrap_err();
+
+        let spans = spans.named("rpc.call");
+        assert_eq!(spans.len(), 2);
+        for span in &spans {
+            assert_eq!(span["rpc.method"], "health");
+            assert_eq!(span["server.address"], "http://mock.near");
+            assert_eq!(span["rpc.attempts"], "1");
+            assert!(span["request.size"].parse::<usize>().unwrap() > 0);
+            assert!(span.contains_key("response.size"));
+            assert!(span["latency_ms"].parse::<f64>().unwrap() >= 0.0);
+        }
+
+        assert_eq!(spans[0]["http.status"], "200");
+        assert!(!spans[0].contains_key("error.class"));
+
+        assert_eq!(spans[1]["http.status"], "503");
+        assert_eq!(spans[1]["error.class"], "status");
+    }
+}
@@ -0,0 +1,483 @@
+//! Mocking the RPC server in tests.
+//!
+//! A [`MockTransport`] answers requests in-process, from responses registered per method name and
+//! optionally per params, and records every request it receives, so tests can check the exact payloads
+//! a [`JsonRpcClient`] sends without touching the network.
+//!
+//! Mocks are matched in the order they were registered. A mock limited with [`MockBuilder::times`]
+//! stops matching once it's been used up, which makes it easy to script a sequence of responses.
+//! Responses can be held back with [`MockBuilder::delay`] to exercise timeouts and concurrency limits,
+//! and carry headers set with [`MockBuilder::with_response_header`].
+//! Requests that no mock matches are answered with a `METHOD_NOT_FOUND` error, like a real server
+//! would for a method it doesn't know.
+//!
+//! This module is only available with the `testing` feature.
+//!
+//! ## Example
+//!
+//! ```
+//! use near_jsonrpc_client::{methods, testing::MockTransport};
+//! use near_jsonrpc_client::errors::{JsonRpcError, JsonRpcServerError};
+//! use near_primitives::types::{BlockReference, Finality};
+//! use serde_json::json;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mock = MockTransport::new();
+//! mock.on("block").respond_error(json!({
+//!     "code": -32000,
+//!     "message": "Server error",
+//!     "name": "HANDLER_ERROR",
+//!     "cause": { "name": "UNKNOWN_BLOCK", "info": {} },
+//! }));
+//!
+//! let client = mock.client();
+//! let response = client
+//!     .call(methods::block::RpcBlockRequest {
+//!         block_reference: BlockReference::Finality(Finality::Final),
+//!     })
+//!     .await;
+//!
+//! assert!(matches!(
+//!     response,
+//!     Err(JsonRpcError::ServerError(JsonRpcServerError::HandlerError(
+//!         methods::block::RpcBlockError::UnknownBlock { .. }
+//!     )))
+//! ));
+//!
+//! let requests = mock.requests();
+//! assert_eq!(requests[0].method, "block");
+//! assert_eq!(requests[0].params, json!({ "finality": "final" }));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## Cassettes
+//!
+//! A [`RecordingTransport`] wraps another transport, usually a [`reqwest::Client`], and writes every
+//! exchange to a JSON-lines cassette, with secret headers redacted. A [`ReplayTransport`] serves the
+//! cassette back, so a test recorded once against a live network runs offline afterwards.
+//!
+//! ```no_run
+//! use near_jsonrpc_client::testing::{RecordingTransport, ReplayTransport};
+//! use near_jsonrpc_client::{methods, JsonRpcClient};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let cassette = "tests/cassettes/status.jsonl";
+//!
+//! let client = if std::env::var_os("RECORD").is_some() {
+//!     JsonRpcClient::with_transport(RecordingTransport::new(reqwest::Client::new(), cassette)?)
+//! } else {
+//!     JsonRpcClient::with_transport(ReplayTransport::open(cassette)?)
+//! }
+//! .connect("https://rpc.mainnet.near.org");
+//!
+//! let status = client.call(methods::status::RpcStatusRequest).await?;
+//! # Ok(())
+//! # }
+//! ```
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use crate::transport::{Transport, TransportFuture, TransportRequest, TransportResponse};
+use crate::JsonRpcClient;
+
+mod cassette;
+pub use cassette::*;
+
+#[cfg(test)]
+pub(crate) mod fixtures;
+
+/// A request received by a [`MockTransport`].
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    /// The method called.
+    pub method: String,
+    /// The params the method was called with.
+    pub params: Value,
+    /// The complete JSON RPC payload.
+    pub payload: Value,
+    /// Headers configured on the client.
+    pub headers: HeaderMap,
+}
+
+/// A transport that answers requests with canned responses.
+///
+/// See the [`testing`](self) module documentation for more information.
+#[derive(Debug, Clone, Default)]
+pub struct MockTransport {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    mocks: Vec<Mock>,
+    requests: Vec<RecordedRequest>,
+    in_flight: usize,
+    max_in_flight: usize,
+}
+
+#[derive(Debug)]
+struct Mock {
+    method_name: String,
+    params: Params,
+    remaining: Option<usize>,
+    response: Response,
+    delay: Duration,
+    headers: HeaderMap,
+}
+
+enum Params {
+    Any,
+    Exact(Value),
+    Matching(Box<dyn Fn(&Value) -> bool + Send + Sync>),
+}
+
+impl fmt::Debug for Params {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Params::Any => f.write_str("Any"),
+            Params::Exact(params) => f.debug_tuple("Exact").field(params).finish(),
+            Params::Matching(_) => f.write_str("Matching(..)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Response {
+    Result(Value),
+    Error(Value),
+    Status(StatusCode),
+}
+
+/// A mock being registered.
+///
+/// Nothing is registered until one of the `respond` methods is called.
+#[derive(Debug)]
+#[must_use = "a mock is only registered once a response is set"]
+pub struct MockBuilder<'a> {
+    transport: &'a MockTransport,
+    method_name: String,
+    params: Params,
+    times: Option<usize>,
+    delay: Duration,
+    headers: HeaderMap,
+}
+
+/// What a mock answered a single request with.
+struct Answer {
+    response: Response,
+    delay: Duration,
+    headers: HeaderMap,
+}
+
+impl MockTransport {
+    /// Create a transport with no mocks registered.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a client that sends its requests to this transport.
+    pub fn client(&self) -> JsonRpcClient {
+        JsonRpcClient::with_transport(self.clone()).connect("http://mock.near")
+    }
+
+    /// Register a mock for calls to `method_name`.
+    pub fn on(&self, method_name: &str) -> MockBuilder<'_> {
+        MockBuilder {
+            transport: self,
+            method_name: method_name.to_string(),
+            params: Params::Any,
+            times: None,
+            delay: Duration::ZERO,
+            headers: HeaderMap::new(),
+        }
+    }
+
+    /// The requests received so far, in order.
+    ///
+    /// Requests sent in a batch are recorded individually.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Forget the requests received so far.
+    pub fn clear_requests(&self) {
+        self.state.lock().unwrap().requests.clear();
+    }
+
+    /// The most requests that were awaiting a response at the same time.
+    pub fn max_in_flight(&self) -> usize {
+        self.state.lock().unwrap().max_in_flight
+    }
+
+    fn answer(&self, payload: &Value, headers: &HeaderMap) -> Answer {
+        let method = payload["method"].as_str().unwrap_or_default().to_string();
+        let params = payload["params"].clone();
+
+        let mut state = self.state.lock().unwrap();
+        state.requests.push(RecordedRequest {
+            method: method.clone(),
+            params: params.clone(),
+            payload: payload.clone(),
+            headers: headers.clone(),
+        });
+
+        let mock = state.mocks.iter_mut().find(|mock| {
+            mock.method_name == method
+                && mock.remaining != Some(0)
+                && match &mock.params {
+                    Params::Any => true,
+                    Params::Exact(expected) => *expected == params,
+                    Params::Matching(matches) => matches(&params),
+                }
+        });
+
+        match mock {
+            Some(mock) => {
+                if let Some(remaining) = &mut mock.remaining {
+                    *remaining -= 1;
+                }
+                Answer {
+                    response: mock.response.clone(),
+                    delay: mock.delay,
+                    headers: mock.headers.clone(),
+                }
+            }
+            None => Answer {
+                response: Response::Error(json!({
+                    "code": -32601,
+                    "message": "Method not found",
+                    "data": method,
+                    "name": "REQUEST_VALIDATION_ERROR",
+                    "cause": { "name": "METHOD_NOT_FOUND", "info": { "method_name": method } },
+                })),
+                delay: Duration::ZERO,
+                headers: HeaderMap::new(),
+            },
+        }
+    }
+}
+
+impl<'a> MockBuilder<'a> {
+    /// Only match calls with exactly these params.
+    pub fn with_params(mut self, params: Value) -> Self {
+        self.params = Params::Exact(params);
+        self
+    }
+
+    /// Only match calls whose params satisfy the predicate.
+    pub fn matching(mut self, predicate: impl Fn(&Value) -> bool + Send + Sync + 'static) -> Self {
+        self.params = Params::Matching(Box::new(predicate));
+        self
+    }
+
+    /// Only match the next `times` calls.
+    ///
+    /// By default, a mock matches any number of calls.
+    pub fn times(mut self, times: usize) -> Self {
+        self.times = Some(times);
+        self
+    }
+
+    /// Hold the response back for `delay`.
+    ///
+    /// Requests sent in a batch are answered once the slowest of their mocks is done.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Add a header to the response.
+    pub fn with_response_header(mut self, name: HeaderName, value: &str) -> Self {
+        let value = HeaderValue::from_str(value).expect("mock header value must be valid");
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Respond with a successful result.
+    pub fn respond(self, result: impl serde::Serialize) {
+        let result = serde_json::to_value(result).expect("mock result must be serializable");
+        self.register(Response::Result(result));
+    }
+
+    /// Respond with a JSON RPC error object.
+    pub fn respond_error(self, error: Value) {
+        self.register(Response::Error(error));
+    }
+
+    /// Respond with an empty body and the specified status code.
+    pub fn respond_status(self, status: StatusCode) {
+        self.register(Response::Status(status));
+    }
+
+    fn register(self, response: Response) {
+        self.transport.state.lock().unwrap().mocks.push(Mock {
+            method_name: self.method_name,
+            params: self.params,
+            remaining: self.times,
+            response,
+            delay: self.delay,
+            headers: self.headers,
+        });
+    }
+}
+
+impl Transport for MockTransport {
+    fn send(&self, request: TransportRequest) -> TransportFuture<'_> {
+        let payload = match serde_json::from_slice::<Value>(&request.body) {
+            Ok(payload) => payload,
+            Err(_) => {
+                let response = TransportResponse::new(StatusCode::BAD_REQUEST, vec![]);
+                return Box::pin(async move { Ok(response) });
+            }
+        };
+
+        let message = |payload: &Value, response| match response {
+            Response::Result(result) => {
+                Ok(json!({ "jsonrpc": "2.0", "id": payload["id"], "result": result }))
+            }
+            Response::Error(error) => {
+                Ok(json!({ "jsonrpc": "2.0", "id": payload["id"], "error": error }))
+            }
+            Response::Status(status) => Err(status),
+        };
+
+        let (mut delay, mut headers) = (Duration::ZERO, HeaderMap::new());
+        let mut answer = |payload: &Value| {
+            let answer = self.answer(payload, &request.headers);
+            delay = delay.max(answer.delay);
+            headers.extend(answer.headers);
+            message(payload, answer.response)
+        };
+        let body = match &payload {
+            Value::Array(payloads) => payloads
+                .iter()
+                .map(&mut answer)
+                .collect::<Result<Vec<_>, _>>()
+                .map(Value::Array),
+            payload => answer(payload),
+        };
+
+        let mut response = match body {
+            Ok(body) => TransportResponse::new(StatusCode::OK, body.to_string().into()),
+            Err(status) => TransportResponse::new(status, vec![]),
+        };
+        response.headers = headers;
+
+        let state = self.state.clone();
+        Box::pin(async move {
+            {
+                let mut state = state.lock().unwrap();
+                state.in_flight += 1;
+                state.max_in_flight = state.max_in_flight.max(state.in_flight);
+            }
+            tokio::time::sleep(delay).await;
+            state.lock().unwrap().in_flight -= 1;
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::types::{BlockReference, Finality};
+
+    use super::*;
+    use crate::errors::*;
+    use crate::methods;
+
+    fn block_request() -> methods::block::RpcBlockRequest {
+        methods::block::RpcBlockRequest {
+            block_reference: BlockReference::Finality(Finality::Final),
+        }
+    }
+
+    #[tokio::test]
+    async fn records_payloads() {
+        let mock = MockTransport::new();
+        mock.on("health").respond(());
+
+        let client = mock.client();
+        client
+            .call(methods::health::RpcHealthRequest)
+            .await
+            .unwrap();
+        let _ = client.call(block_request()).await;
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].method, "health");
+        let mut expected = methods::to_json(&block_request()).unwrap();
+        expected["id"] = requests[1].payload["id"].clone();
+        assert_eq!(requests[1].payload, expected);
+
+        mock.clear_requests();
+        assert!(mock.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn scripted_responses() {
+        let mock = MockTransport::new();
+        mock.on("block")
+            .with_params(json!({ "finality": "optimistic" }))
+            .respond_status(StatusCode::BAD_GATEWAY);
+        mock.on("block")
+            .times(1)
+            .respond_status(StatusCode::SERVICE_UNAVAILABLE);
+        mock.on("block").respond_error(json!({
+            "code": -32000,
+            "message": "Server error",
+            "name": "HANDLER_ERROR",
+            "cause": { "name": "UNKNOWN_BLOCK", "info": {} },
+        }));
+
+        let client = mock.client();
+
+        let response = client.call(block_request()).await;
+        assert!(
+            matches!(
+                response,
+                Err(JsonRpcError::ServerError(
+                    JsonRpcServerError::ResponseStatusError(
+                        JsonRpcServerResponseStatusError::ServiceUnavailable
+                    )
+                ))
+            ),
+            "expected a ServiceUnavailable error, found [{:?}]",
+            response
+        );
+
+        let response = client.call(block_request()).await;
+        assert!(
+            matches!(
+                response,
+                Err(JsonRpcError::ServerError(JsonRpcServerError::HandlerError(
+                    methods::block::RpcBlockError::UnknownBlock { .. }
+                )))
+            ),
+            "expected an UnknownBlock error, found [{:?}]",
+            response
+        );
+
+        let response = client.call(methods::health::RpcHealthRequest).await;
+        assert!(
+            matches!(
+                response,
+                Err(JsonRpcError::ServerError(
+                    JsonRpcServerError::RequestValidationError(
+                        near_jsonrpc_primitives::errors::RpcRequestValidationErrorKind::MethodNotFound { .. }
+                    )
+                ))
+            ),
+            "expected a MethodNotFound error, found [{:?}]",
+            response
+        );
+    }
+}
@@ -0,0 +1,386 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use reqwest::StatusCode;
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::auth::ApiKey;
+use crate::errors::{JsonRpcTransportSendError, RpcTransportError};
+use crate::transport::{Transport, TransportFuture, TransportRequest, TransportResponse};
+
+/// The value recorded in place of secret headers.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// A request and its response, as recorded on a cassette.
+///
+/// For a batch, `method` lists the methods called, separated by commas, and `params` is an array
+/// of their params.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Exchange {
+    /// The method called.
+    pub method: String,
+    /// The params the method was called with.
+    pub params: Value,
+    /// The complete JSON RPC payload.
+    pub payload: Value,
+    /// Headers sent with the request, with secrets redacted.
+    pub request_headers: BTreeMap<String, String>,
+    /// The status code returned by the server.
+    pub status: u16,
+    /// Headers returned by the server, with secrets redacted.
+    pub headers: BTreeMap<String, String>,
+    /// The raw response body, base64-encoded if it isn't valid UTF-8.
+    pub body: String,
+    /// Whether or not `body` is base64-encoded.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub base64: bool,
+}
+
+/// Returned by a [`ReplayTransport`] for requests that weren't recorded.
+#[derive(Debug, Error)]
+#[error("no recorded exchange left for [{method}] with params [{params}]")]
+pub struct UnrecordedRequest {
+    pub method: String,
+    pub params: Value,
+}
+
+/// A transport that records every exchange with the wrapped transport to a JSON-lines cassette.
+///
+/// The `x-api-key` and `Authorization` headers, as well as any header marked as sensitive,
+/// are redacted before being written. Failing to record an exchange is logged, and doesn't
+/// fail the call.
+///
+/// See the [`testing`](super) module documentation for more information.
+#[derive(Debug)]
+pub struct RecordingTransport<T> {
+    inner: T,
+    cassette: Mutex<File>,
+    redacted: Vec<HeaderName>,
+}
+
+impl<T: Transport> RecordingTransport<T> {
+    /// Record exchanges to the file at `path`, replacing its contents.
+    pub fn new(inner: T, path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            inner,
+            cassette: Mutex::new(File::create(path)?),
+            redacted: vec![HeaderName::from_static(ApiKey::HEADER_NAME), AUTHORIZATION],
+        })
+    }
+
+    /// Also redact the specified header.
+    pub fn redact_header(mut self, name: HeaderName) -> Self {
+        self.redacted.push(name);
+        self
+    }
+
+    fn record(
+        &self,
+        request: &TransportRequest,
+        response: &TransportResponse,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let payload = serde_json::from_slice::<Value>(&request.body)?;
+        let (method, params) = describe(&payload);
+        let (body, base64) = match String::from_utf8(response.body.clone()) {
+            Ok(body) => (body, false),
+            Err(err) => (near_primitives::serialize::to_base64(err.as_bytes()), true),
+        };
+        let exchange = Exchange {
+            method,
+            params,
+            payload,
+            request_headers: self.headers(&request.headers),
+            status: response.status.as_u16(),
+            headers: self.headers(&response.headers),
+            body,
+            base64,
+        };
+
+        let mut line = serde_json::to_vec(&exchange)?;
+        line.push(b'\n');
+        self.cassette.lock().unwrap().write_all(&line)?;
+        Ok(())
+    }
+
+    fn headers(&self, headers: &HeaderMap) -> BTreeMap<String, String> {
+        headers
+            .iter()
+            .map(|(name, value)| {
+                let value = if value.is_sensitive() || self.redacted.contains(name) {
+                    REDACTED.to_string()
+                } else {
+                    String::from_utf8_lossy(value.as_bytes()).into_owned()
+                };
+                (name.to_string(), value)
+            })
+            .collect()
+    }
+}
+
+impl<T: Transport> Transport for RecordingTransport<T> {
+    fn send(&self, request: TransportRequest) -> TransportFuture<'_> {
+        Box::pin(async move {
+            let response = self.inner.send(request.clone()).await?;
+            if let Err(err) = self.record(&request, &response) {
+                log::warn!("failed to record exchange: {}", err);
+            }
+            Ok(response)
+        })
+    }
+}
+
+/// A transport that serves the exchanges recorded on a cassette.
+///
+/// Each request is answered with the first exchange not served yet whose payload matches, ignoring
+/// request ids, which are rewritten in the response. Requests that don't match any exchange fail
+/// with an [`UnrecordedRequest`] error.
+///
+/// See the [`testing`](super) module documentation for more information.
+#[derive(Debug)]
+pub struct ReplayTransport {
+    exchanges: Mutex<Vec<Option<Exchange>>>,
+}
+
+impl ReplayTransport {
+    /// Load the cassette at `path`.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let exchanges = BufReader::new(File::open(path)?)
+            .lines()
+            .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+            .map(|line| Ok(serde_json::from_str(&line?)?))
+            .collect::<io::Result<Vec<Exchange>>>()?;
+        Ok(Self::from_exchanges(exchanges))
+    }
+
+    /// Serve the specified exchanges.
+    pub fn from_exchanges(exchanges: Vec<Exchange>) -> Self {
+        Self {
+            exchanges: Mutex::new(exchanges.into_iter().map(Some).collect()),
+        }
+    }
+
+    /// The number of exchanges not served yet.
+    pub fn remaining(&self) -> usize {
+        self.exchanges.lock().unwrap().iter().flatten().count()
+    }
+
+    fn replay(&self, payload: &Value) -> Option<TransportResponse> {
+        let mut exchanges = self.exchanges.lock().unwrap();
+        let exchange = exchanges
+            .iter_mut()
+            .find(|exchange| {
+                matches!(exchange, Some(exchange) if without_ids(&exchange.payload) == without_ids(payload))
+            })?
+            .take()?;
+
+        let mut ids = vec![];
+        collect_ids(&exchange.payload, payload, &mut ids);
+        let body = match serde_json::from_str::<Value>(&exchange.body) {
+            _ if exchange.base64 => {
+                near_primitives::serialize::from_base64(&exchange.body).unwrap_or_default()
+            }
+            Ok(mut body) => {
+                replace_ids(&mut body, &ids);
+                body.to_string().into()
+            }
+            Err(_) => exchange.body.into(),
+        };
+
+        let mut response = TransportResponse::new(
+            StatusCode::from_u16(exchange.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            body,
+        );
+        response.headers = exchange
+            .headers
+            .iter()
+            .filter_map(|(name, value)| {
+                Some((
+                    HeaderName::try_from(name).ok()?,
+                    HeaderValue::try_from(value).ok()?,
+                ))
+            })
+            .collect();
+        Some(response)
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn send(&self, request: TransportRequest) -> TransportFuture<'_> {
+        let payload = serde_json::from_slice::<Value>(&request.body).unwrap_or_default();
+        let response = self.replay(&payload).ok_or_else(|| {
+            let (method, params) = describe(&payload);
+            RpcTransportError::SendError(JsonRpcTransportSendError::TransportSendError(Box::new(
+                UnrecordedRequest { method, params },
+            )))
+        });
+
+        Box::pin(async move { response })
+    }
+}
+
+/// The method name and params of a payload, or of every payload in a batch.
+fn describe(payload: &Value) -> (String, Value) {
+    match payload {
+        Value::Array(payloads) => (
+            payloads
+                .iter()
+                .map(|payload| payload["method"].as_str().unwrap_or_default())
+                .collect::<Vec<_>>()
+                .join(","),
+            payloads
+                .iter()
+                .map(|payload| payload["params"].clone())
+                .collect(),
+        ),
+        payload => (
+            payload["method"].as_str().unwrap_or_default().to_string(),
+            payload["params"].clone(),
+        ),
+    }
+}
+
+fn without_ids(payload: &Value) -> Value {
+    let mut payload = payload.clone();
+    match &mut payload {
+        Value::Array(payloads) => payloads.iter_mut().for_each(|payload| {
+            payload.as_object_mut().map(|payload| payload.remove("id"));
+        }),
+        Value::Object(payload) => {
+            payload.remove("id");
+        }
+        _ => {}
+    }
+    payload
+}
+
+/// Pair the ids of the recorded payload with those of the current one.
+fn collect_ids(recorded: &Value, current: &Value, ids: &mut Vec<(Value, Value)>) {
+    match (recorded, current) {
+        (Value::Array(recorded), Value::Array(current)) => recorded
+            .iter()
+            .zip(current)
+            .for_each(|(recorded, current)| collect_ids(recorded, current, ids)),
+        (recorded, current) => ids.push((recorded["id"].clone(), current["id"].clone())),
+    }
+}
+
+fn replace_ids(body: &mut Value, ids: &[(Value, Value)]) {
+    match body {
+        Value::Array(messages) => messages
+            .iter_mut()
+            .for_each(|message| replace_ids(message, ids)),
+        Value::Object(message) => {
+            if let Some(id) = message.get_mut("id") {
+                if let Some((_, current)) = ids.iter().find(|(recorded, _)| recorded == id) {
+                    *id = current.clone();
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::testing::MockTransport;
+    use crate::{auth, methods, JsonRpcClient};
+
+    fn cassette(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!(
+            "near-jsonrpc-client-{}-{}.jsonl",
+            name,
+            std::process::id()
+        ))
+    }
+
+    #[tokio::test]
+    async fn records_and_replays() {
+        let path = cassette("records_and_replays");
+
+        let mock = MockTransport::new();
+        mock.on("health").respond(());
+
+        let client = JsonRpcClient::with_transport(RecordingTransport::new(mock, &path).unwrap())
+            .connect("http://localhost:3030")
+            .header(auth::ApiKey::new("0ff1ce").unwrap())
+            .header(auth::Authorization::bearer("s3cr3t").unwrap());
+        client
+            .call(methods::health::RpcHealthRequest)
+            .await
+            .unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains("0ff1ce"));
+        assert!(!contents.contains("s3cr3t"));
+
+        let exchanges = contents
+            .lines()
+            .map(|line| serde_json::from_str::<Exchange>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(exchanges.len(), 1);
+        assert_eq!(exchanges[0].method, "health");
+        assert_eq!(exchanges[0].request_headers["x-api-key"], REDACTED);
+        assert_eq!(exchanges[0].request_headers["authorization"], REDACTED);
+
+        let replay = Arc::new(ReplayTransport::open(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+        let client = JsonRpcClient::with_transport(replay.clone()).connect("http://localhost:3030");
+
+        client
+            .call(methods::health::RpcHealthRequest)
+            .await
+            .unwrap();
+        assert_eq!(replay.remaining(), 0);
+
+        // every exchange is only served once
+        let response = client.call(methods::health::RpcHealthRequest).await;
+        assert!(
+            matches!(
+                response,
+                Err(crate::errors::JsonRpcError::TransportError(
+                    RpcTransportError::SendError(JsonRpcTransportSendError::TransportSendError(_))
+                ))
+            ),
+            "expected an unrecorded request, found [{:?}]",
+            response
+        );
+    }
+
+    #[test]
+    fn records_binary_bodies_as_base64() {
+        let path = cassette("records_binary_bodies_as_base64");
+        let recording = RecordingTransport::new(MockTransport::new(), &path).unwrap();
+
+        let request = TransportRequest::new(
+            "http://localhost:3030",
+            HeaderMap::new(),
+            br#"{"jsonrpc":"2.0","id":"dontcare","method":"health","params":[]}"#.to_vec(),
+        );
+        let body = vec![0xde, 0xad, 0xbe, 0xef];
+        recording
+            .record(
+                &request,
+                &TransportResponse::new(StatusCode::BAD_GATEWAY, body.clone()),
+            )
+            .unwrap();
+
+        let replay = ReplayTransport::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let exchange = replay.exchanges.lock().unwrap()[0].clone().unwrap();
+        assert!(exchange.base64);
+
+        let payload = serde_json::from_slice(&request.body).unwrap();
+        let response = replay.replay(&payload).unwrap();
+        assert_eq!(response.status, StatusCode::BAD_GATEWAY);
+        assert_eq!(response.body, body);
+    }
+}
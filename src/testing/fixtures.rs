@@ -0,0 +1,74 @@
+//! JSON views of chain data, for the tests of this crate.
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{BlockHeight, StateRoot};
+use serde_json::{json, Value};
+
+/// A block at `height`, with the given chunks, and every other field zeroed.
+pub(crate) fn block(height: BlockHeight, chunks: Vec<Value>) -> Value {
+    json!({
+        "author": "alice.near",
+        "header": {
+            "height": height,
+            "prev_height": height - 1,
+            "epoch_id": CryptoHash::default(),
+            "next_epoch_id": CryptoHash::default(),
+            "hash": CryptoHash::hash_bytes(&height.to_le_bytes()),
+            "prev_hash": CryptoHash::default(),
+            "prev_state_root": CryptoHash::default(),
+            "block_body_hash": null,
+            "chunk_receipts_root": CryptoHash::default(),
+            "chunk_headers_root": CryptoHash::default(),
+            "chunk_tx_root": CryptoHash::default(),
+            "outcome_root": CryptoHash::default(),
+            "chunks_included": chunks.len(),
+            "challenges_root": CryptoHash::default(),
+            "timestamp": 0,
+            "timestamp_nanosec": "0",
+            "random_value": CryptoHash::default(),
+            "validator_proposals": [],
+            "chunk_mask": vec![true; chunks.len()],
+            "gas_price": "0",
+            "block_ordinal": null,
+            "rent_paid": "0",
+            "validator_reward": "0",
+            "total_supply": "0",
+            "challenges_result": [],
+            "last_final_block": CryptoHash::default(),
+            "last_ds_final_block": CryptoHash::default(),
+            "next_bp_hash": CryptoHash::default(),
+            "block_merkle_root": CryptoHash::default(),
+            "epoch_sync_data_hash": null,
+            "approvals": [],
+            "signature": near_crypto::Signature::empty(near_crypto::KeyType::ED25519),
+            "latest_protocol_version": 73,
+            "chunk_endorsements": null,
+        },
+        "chunks": chunks,
+    })
+}
+
+/// The header of a chunk included at `height`, applied on top of `prev_state_root`.
+pub(crate) fn chunk_header(height: BlockHeight, prev_state_root: StateRoot) -> Value {
+    json!({
+        "chunk_hash": CryptoHash::hash_bytes(&height.to_be_bytes()),
+        "prev_block_hash": CryptoHash::default(),
+        "outcome_root": CryptoHash::default(),
+        "prev_state_root": prev_state_root,
+        "encoded_merkle_root": CryptoHash::default(),
+        "encoded_length": 0,
+        "height_created": height,
+        "height_included": height,
+        "shard_id": 0,
+        "gas_used": 0,
+        "gas_limit": 0,
+        "rent_paid": "0",
+        "validator_reward": "0",
+        "balance_burnt": "0",
+        "outgoing_receipts_root": CryptoHash::default(),
+        "tx_root": CryptoHash::default(),
+        "validator_proposals": [],
+        "congestion_info": null,
+        "bandwidth_requests": null,
+        "signature": near_crypto::Signature::empty(near_crypto::KeyType::ED25519),
+    })
+}
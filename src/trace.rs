@@ -0,0 +1,669 @@
+//! Tracing the execution of a transaction across contracts.
+//!
+//! [`EXPERIMENTAL_tx_status`](crate::methods::EXPERIMENTAL_tx_status) returns the outcomes of all receipts
+//! of a transaction as a flat list. [`JsonRpcClient::trace_transaction`] assembles them into a
+//! [`TransactionTrace`], the tree of receipts the transaction spawned, following the `receipt_ids` of
+//! every outcome. Each [`ReceiptNode`] carries its [`ReceiptView`], with the predecessor, receiver,
+//! actions and deposits, and its outcome, with the gas and tokens burnt, logs and status.
+//!
+//! Receipts the server doesn't include in the response are fetched individually with
+//! [`EXPERIMENTAL_receipt`](crate::methods::EXPERIMENTAL_receipt). Refund receipts, sent by `system`,
+//! are part of the tree, which is why the transaction is waited on until it's
+//! [`Final`](near_primitives::views::TxExecutionStatus::Final).
+//!
+//! A receipt is spawned by exactly one outcome, so this is a tree. Receipts joining the results of
+//! several others through data dependencies appear under the receipt that created them. Outcomes the
+//! server returns that no outcome of the tree spawned are kept as
+//! [`orphans`](TransactionTrace::orphans), rather than dropped.
+//!
+//! The trace renders as indented text with its [`Display`](std::fmt::Display) implementation, and as JSON
+//! with [`TransactionTrace::to_json`].
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use near_jsonrpc_client::JsonRpcClient;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+//! let client = JsonRpcClient::connect("https://archival-rpc.mainnet.near.org");
+//!
+//! let trace = client
+//!     .trace_transaction(
+//!         "9FtHUFBQsZ2MG77K3x3MJ9wjX3UT8zE1TczCrhZEcG8U".parse()?,
+//!         "miraclx.near".parse()?,
+//!     )
+//!     .await?;
+//!
+//! println!("{}", trace);
+//!
+//! for node in trace.failures() {
+//!     println!("{} failed: {:?}", node.receipt_id, node.failure());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use near_primitives::errors::TxExecutionError;
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{AccountId, Balance, Gas};
+use near_primitives::views::{
+    ActionView, ExecutionOutcomeView, ExecutionOutcomeWithIdView, ExecutionStatusView,
+    FinalExecutionOutcomeView, FinalExecutionOutcomeViewEnum, FinalExecutionStatus,
+    ReceiptEnumView, ReceiptView, TxExecutionStatus,
+};
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::errors::*;
+use crate::methods::tx::{RpcTransactionError, TransactionInfo};
+use crate::methods::EXPERIMENTAL_receipt::RpcReceiptError;
+use crate::{methods, JsonRpcClient};
+
+/// Potential errors returned while tracing a transaction.
+#[derive(Debug, Error)]
+pub enum TransactionTraceError {
+    /// The transaction status couldn't be fetched.
+    #[error("error while fetching transaction status: [{0}]")]
+    TransactionError(JsonRpcError<RpcTransactionError>),
+    /// The server didn't return the execution outcome of the transaction.
+    #[error("transaction has no execution outcome yet")]
+    MissingOutcome,
+    /// A receipt missing from the transaction status couldn't be fetched.
+    #[error("error while fetching receipt: [{0}]")]
+    ReceiptError(JsonRpcError<RpcReceiptError>),
+}
+
+/// The receipts spawned by a transaction, as a tree.
+///
+/// See the [`trace`](self) module documentation for more information.
+#[derive(Debug, Clone)]
+pub struct TransactionTrace {
+    /// The hash of the transaction.
+    pub transaction_hash: CryptoHash,
+    /// The account that signed the transaction.
+    pub signer_id: AccountId,
+    /// The account the transaction was sent to.
+    pub receiver_id: AccountId,
+    /// The overall status of the transaction.
+    pub status: FinalExecutionStatus,
+    /// The outcome of converting the transaction into its first receipt.
+    pub outcome: ExecutionOutcomeView,
+    /// The receipts spawned by the transaction, in the order they were created.
+    pub receipts: Vec<ReceiptNode>,
+    /// The trees of receipts whose outcomes were returned, but aren't reachable from the transaction,
+    /// in the order the server returned them.
+    pub orphans: Vec<ReceiptNode>,
+}
+
+/// A receipt in a [`TransactionTrace`].
+#[derive(Debug, Clone)]
+pub struct ReceiptNode {
+    /// The ID of the receipt.
+    pub receipt_id: CryptoHash,
+    /// The receipt, `None` if the server couldn't find it.
+    pub receipt: Option<ReceiptView>,
+    /// The outcome of executing the receipt, `None` if it hasn't been executed yet.
+    pub outcome: Option<ExecutionOutcomeWithIdView>,
+    /// The receipts spawned by this receipt, in the order they were created.
+    pub children: Vec<ReceiptNode>,
+}
+
+impl JsonRpcClient {
+    /// Trace the receipts spawned by a transaction.
+    ///
+    /// See the [`trace`](crate::trace) module documentation for more information.
+    pub async fn trace_transaction(
+        &self,
+        tx_hash: CryptoHash,
+        sender_account_id: AccountId,
+    ) -> Result<TransactionTrace, TransactionTraceError> {
+        let response = self
+            .call(
+                methods::EXPERIMENTAL_tx_status::RpcTransactionStatusRequest {
+                    transaction_info: TransactionInfo::TransactionId {
+                        tx_hash,
+                        sender_account_id,
+                    },
+                    wait_until: TxExecutionStatus::Final,
+                },
+            )
+            .await
+            .map_err(TransactionTraceError::TransactionError)?;
+
+        let (outcome, receipts) = match response.final_execution_outcome {
+            Some(FinalExecutionOutcomeViewEnum::FinalExecutionOutcomeWithReceipt(outcome)) => {
+                (outcome.final_outcome, outcome.receipts)
+            }
+            Some(FinalExecutionOutcomeViewEnum::FinalExecutionOutcome(outcome)) => {
+                (outcome, vec![])
+            }
+            None => return Err(TransactionTraceError::MissingOutcome),
+        };
+
+        let mut trace = TransactionTrace::new(outcome, receipts);
+
+        let missing = trace
+            .nodes()
+            .filter(|node| node.receipt.is_none())
+            .map(|node| node.receipt_id)
+            .collect::<Vec<_>>();
+        let fetched =
+            futures::future::try_join_all(missing.into_iter().map(|receipt_id| async move {
+                let response = self
+                    .call(methods::EXPERIMENTAL_receipt::RpcReceiptRequest {
+                        receipt_reference:
+                            near_jsonrpc_primitives::types::receipts::ReceiptReference {
+                                receipt_id,
+                            },
+                    })
+                    .await;
+                match response {
+                    Ok(receipt) => Ok(Some(receipt)),
+                    Err(err) if err.is_not_found() => Ok(None),
+                    Err(err) => Err(TransactionTraceError::ReceiptError(err)),
+                }
+            }))
+            .await?;
+
+        let mut fetched = fetched
+            .into_iter()
+            .flatten()
+            .map(|receipt| (receipt.receipt_id, receipt))
+            .collect::<HashMap<_, _>>();
+        fill_receipts(&mut trace.receipts, &mut fetched);
+        fill_receipts(&mut trace.orphans, &mut fetched);
+
+        Ok(trace)
+    }
+}
+
+impl TransactionTrace {
+    /// Assemble the tree of receipts from the outcome of a transaction and the receipts known so far.
+    ///
+    /// Receipts not in `receipts` are left as `None` in their node.
+    pub fn new(outcome: FinalExecutionOutcomeView, receipts: Vec<ReceiptView>) -> Self {
+        let order = outcome
+            .receipts_outcome
+            .iter()
+            .map(|outcome| outcome.id)
+            .collect::<Vec<_>>();
+        let mut outcomes = outcome
+            .receipts_outcome
+            .into_iter()
+            .map(|outcome| (outcome.id, outcome))
+            .collect::<HashMap<_, _>>();
+        let mut receipts = receipts
+            .into_iter()
+            .map(|receipt| (receipt.receipt_id, receipt))
+            .collect::<HashMap<_, _>>();
+
+        let children = outcome
+            .transaction_outcome
+            .outcome
+            .receipt_ids
+            .iter()
+            .map(|receipt_id| ReceiptNode::build(*receipt_id, &mut outcomes, &mut receipts))
+            .collect();
+
+        // the outcomes left are rooted at those no other outcome left spawned,
+        // or, going in circles, at the first one returned
+        let spawned = outcomes
+            .values()
+            .flat_map(|outcome| outcome.outcome.receipt_ids.iter().copied())
+            .collect::<HashSet<_>>();
+        let (roots, rest) = order
+            .into_iter()
+            .partition::<Vec<_>, _>(|receipt_id| !spawned.contains(receipt_id));
+        let mut orphans = vec![];
+        for receipt_id in roots.into_iter().chain(rest) {
+            if outcomes.contains_key(&receipt_id) {
+                orphans.push(ReceiptNode::build(receipt_id, &mut outcomes, &mut receipts));
+            }
+        }
+
+        Self {
+            transaction_hash: outcome.transaction.hash,
+            signer_id: outcome.transaction.signer_id,
+            receiver_id: outcome.transaction.receiver_id,
+            status: outcome.status,
+            outcome: outcome.transaction_outcome.outcome,
+            receipts: children,
+            orphans,
+        }
+    }
+
+    /// All receipts in the trace, depth first, orphans last.
+    pub fn nodes(&self) -> impl Iterator<Item = &ReceiptNode> {
+        let mut stack = self
+            .receipts
+            .iter()
+            .chain(&self.orphans)
+            .rev()
+            .collect::<Vec<_>>();
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.children.iter().rev());
+            Some(node)
+        })
+    }
+
+    /// The receipts that failed.
+    pub fn failures(&self) -> impl Iterator<Item = &ReceiptNode> {
+        self.nodes().filter(|node| node.is_failure())
+    }
+
+    /// The gas burnt by the transaction and all its receipts, orphans included.
+    pub fn total_gas_burnt(&self) -> Gas {
+        self.outcome.gas_burnt + self.nodes().map(ReceiptNode::gas_burnt).sum::<Gas>()
+    }
+
+    /// The tokens burnt by the transaction and all its receipts, orphans included, in yoctoNEAR.
+    pub fn total_tokens_burnt(&self) -> Balance {
+        self.outcome.tokens_burnt + self.nodes().map(ReceiptNode::tokens_burnt).sum::<Balance>()
+    }
+
+    /// Render the trace as JSON.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "transaction_hash": self.transaction_hash,
+            "signer_id": self.signer_id,
+            "receiver_id": self.receiver_id,
+            "status": self.status,
+            "gas_burnt": self.outcome.gas_burnt,
+            "tokens_burnt": self.outcome.tokens_burnt.to_string(),
+            "total_gas_burnt": self.total_gas_burnt(),
+            "total_tokens_burnt": self.total_tokens_burnt().to_string(),
+            "receipts": self.receipts.iter().map(ReceiptNode::to_json).collect::<Vec<_>>(),
+            "orphans": self.orphans.iter().map(ReceiptNode::to_json).collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl ReceiptNode {
+    fn build(
+        receipt_id: CryptoHash,
+        outcomes: &mut HashMap<CryptoHash, ExecutionOutcomeWithIdView>,
+        receipts: &mut HashMap<CryptoHash, ReceiptView>,
+    ) -> Self {
+        // outcomes are taken out of the map, so malformed responses can't send this in circles
+        let outcome = outcomes.remove(&receipt_id);
+        let children = outcome
+            .iter()
+            .flat_map(|outcome| outcome.outcome.receipt_ids.iter())
+            .map(|receipt_id| Self::build(*receipt_id, outcomes, receipts))
+            .collect();
+
+        Self {
+            receipt_id,
+            receipt: receipts.remove(&receipt_id),
+            outcome,
+            children,
+        }
+    }
+
+    /// The account that created the receipt.
+    pub fn predecessor_id(&self) -> Option<&AccountId> {
+        self.receipt.as_ref().map(|receipt| &receipt.predecessor_id)
+    }
+
+    /// The account the receipt was executed on.
+    pub fn receiver_id(&self) -> Option<&AccountId> {
+        match (&self.receipt, &self.outcome) {
+            (Some(receipt), _) => Some(&receipt.receiver_id),
+            (None, Some(outcome)) => Some(&outcome.outcome.executor_id),
+            (None, None) => None,
+        }
+    }
+
+    /// The actions of the receipt, empty for data receipts.
+    pub fn actions(&self) -> &[ActionView] {
+        match &self.receipt {
+            Some(ReceiptView {
+                receipt: ReceiptEnumView::Action { actions, .. },
+                ..
+            }) => actions,
+            _ => &[],
+        }
+    }
+
+    /// The tokens attached to the actions of the receipt, in yoctoNEAR.
+    pub fn deposit(&self) -> Balance {
+        self.actions()
+            .iter()
+            .map(|action| match action {
+                ActionView::Transfer { deposit } | ActionView::FunctionCall { deposit, .. } => {
+                    *deposit
+                }
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// The gas burnt executing the receipt.
+    pub fn gas_burnt(&self) -> Gas {
+        self.outcome
+            .as_ref()
+            .map_or(0, |outcome| outcome.outcome.gas_burnt)
+    }
+
+    /// The tokens burnt executing the receipt, in yoctoNEAR.
+    pub fn tokens_burnt(&self) -> Balance {
+        self.outcome
+            .as_ref()
+            .map_or(0, |outcome| outcome.outcome.tokens_burnt)
+    }
+
+    /// The status of the receipt, `None` if it hasn't been executed yet.
+    pub fn status(&self) -> Option<&ExecutionStatusView> {
+        self.outcome.as_ref().map(|outcome| &outcome.outcome.status)
+    }
+
+    /// The error the receipt failed with, if it failed.
+    pub fn failure(&self) -> Option<&TxExecutionError> {
+        match self.status() {
+            Some(ExecutionStatusView::Failure(err)) => Some(err),
+            _ => None,
+        }
+    }
+
+    /// Whether or not the receipt failed.
+    pub fn is_failure(&self) -> bool {
+        self.failure().is_some()
+    }
+
+    /// Whether or not the receipt refunds unused gas or deposits, these are sent by `system`.
+    pub fn is_refund(&self) -> bool {
+        self.predecessor_id()
+            .map_or(false, |predecessor_id| predecessor_id == "system")
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "receipt_id": self.receipt_id,
+            "predecessor_id": self.predecessor_id(),
+            "receiver_id": self.receiver_id(),
+            "actions": self.actions(),
+            "deposit": self.deposit().to_string(),
+            "gas_burnt": self.gas_burnt(),
+            "tokens_burnt": self.tokens_burnt().to_string(),
+            "status": self.status(),
+            "logs": self.outcome.as_ref().map(|outcome| &outcome.outcome.logs),
+            "failed": self.is_failure(),
+            "refund": self.is_refund(),
+            "children": self.children.iter().map(Self::to_json).collect::<Vec<_>>(),
+        })
+    }
+
+    fn render(&self, f: &mut fmt::Formatter<'_>, prefix: &str, last: bool) -> fmt::Result {
+        let unknown = || "?".to_string();
+        write!(
+            f,
+            "{}{} {} {} -> {}",
+            prefix,
+            if last { "└──" } else { "├──" },
+            self.receipt_id,
+            self.predecessor_id()
+                .map_or_else(unknown, ToString::to_string),
+            self.receiver_id().map_or_else(unknown, ToString::to_string),
+        )?;
+
+        if self.is_refund() {
+            write!(f, " refund")?;
+        }
+        let actions = self.actions().iter().map(action_name).collect::<Vec<_>>();
+        if !actions.is_empty() {
+            write!(f, " [{}]", actions.join(", "))?;
+        }
+        if self.deposit() > 0 {
+            write!(f, " deposit {}", near(self.deposit()))?;
+        }
+        match self.status() {
+            None | Some(ExecutionStatusView::Unknown) => write!(f, " pending")?,
+            Some(ExecutionStatusView::Failure(err)) => write!(
+                f,
+                " burnt {} ({}) FAILED: {}",
+                tgas(self.gas_burnt()),
+                near(self.tokens_burnt()),
+                err
+            )?,
+            Some(_) => write!(
+                f,
+                " burnt {} ({}) ok",
+                tgas(self.gas_burnt()),
+                near(self.tokens_burnt())
+            )?,
+        }
+        writeln!(f)?;
+
+        let prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+        for (i, child) in self.children.iter().enumerate() {
+            child.render(f, &prefix, i == self.children.len() - 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TransactionTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} {} -> {} burnt {} ({}) in total, {:?}",
+            self.transaction_hash,
+            self.signer_id,
+            self.receiver_id,
+            tgas(self.total_gas_burnt()),
+            near(self.total_tokens_burnt()),
+            self.status
+        )?;
+        for (i, node) in self.receipts.iter().enumerate() {
+            node.render(f, "", i == self.receipts.len() - 1)?;
+        }
+        if !self.orphans.is_empty() {
+            writeln!(f, "unreachable from the transaction:")?;
+            for (i, node) in self.orphans.iter().enumerate() {
+                node.render(f, "", i == self.orphans.len() - 1)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Fill in the receipts of the nodes that don't have one yet.
+fn fill_receipts(nodes: &mut [ReceiptNode], receipts: &mut HashMap<CryptoHash, ReceiptView>) {
+    for node in nodes {
+        if node.receipt.is_none() {
+            node.receipt = receipts.remove(&node.receipt_id);
+        }
+        fill_receipts(&mut node.children, receipts);
+    }
+}
+
+fn action_name(action: &ActionView) -> String {
+    match action {
+        ActionView::FunctionCall {
+            method_name, gas, ..
+        } => format!("FunctionCall({}, {})", method_name, tgas(*gas)),
+        ActionView::CreateAccount => "CreateAccount".to_string(),
+        ActionView::DeployContract { .. } => "DeployContract".to_string(),
+        ActionView::Transfer { .. } => "Transfer".to_string(),
+        ActionView::Stake { .. } => "Stake".to_string(),
+        ActionView::AddKey { .. } => "AddKey".to_string(),
+        ActionView::DeleteKey { .. } => "DeleteKey".to_string(),
+        ActionView::DeleteAccount { beneficiary_id } => {
+            format!("DeleteAccount({})", beneficiary_id)
+        }
+        ActionView::Delegate { .. } => "Delegate".to_string(),
+        // variants behind near-primitives features
+        #[allow(unreachable_patterns)]
+        _ => "Action".to_string(),
+    }
+}
+
+fn tgas(gas: Gas) -> String {
+    format!("{:.3} Tgas", gas as f64 / 1e12)
+}
+
+fn near(yocto: Balance) -> String {
+    const ONE_NEAR: Balance = 10u128.pow(24);
+    let fraction = format!("{:024}", yocto % ONE_NEAR);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        format!("{} NEAR", yocto / ONE_NEAR)
+    } else {
+        format!("{}.{} NEAR", yocto / ONE_NEAR, fraction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::MockTransport;
+
+    fn outcome(
+        id: CryptoHash,
+        executor_id: &str,
+        receipt_ids: Vec<CryptoHash>,
+        status: Value,
+        gas_burnt: Gas,
+    ) -> Value {
+        json!({
+            "proof": [],
+            "block_hash": CryptoHash::default(),
+            "id": id,
+            "outcome": {
+                "logs": [],
+                "receipt_ids": receipt_ids,
+                "gas_burnt": gas_burnt,
+                "tokens_burnt": (gas_burnt as u128 * 100_000_000).to_string(),
+                "executor_id": executor_id,
+                "status": status,
+            },
+        })
+    }
+
+    fn receipt(id: CryptoHash, predecessor_id: &str, receiver_id: &str, actions: Value) -> Value {
+        json!({
+            "predecessor_id": predecessor_id,
+            "receiver_id": receiver_id,
+            "receipt_id": id,
+            "receipt": {
+                "Action": {
+                    "signer_id": "alice.near",
+                    "signer_public_key": "ed25519:6DSjZ8mvsRZDvFqFxo8tCKePG96omXW7eVYVSySmDk8e",
+                    "gas_price": "100000000",
+                    "output_data_receivers": [],
+                    "input_data_ids": [],
+                    "actions": actions,
+                },
+            },
+        })
+    }
+
+    #[tokio::test]
+    async fn traces_receipt_tree() {
+        let tx_hash = CryptoHash::hash_bytes(b"tx");
+        let (call, cross, refund, stray) = (
+            CryptoHash::hash_bytes(b"call"),
+            CryptoHash::hash_bytes(b"cross"),
+            CryptoHash::hash_bytes(b"refund"),
+            CryptoHash::hash_bytes(b"stray"),
+        );
+        let failure = json!({ "Failure": { "ActionError": { "index": 0, "kind": { "FunctionCallError": { "ExecutionError": "Smart contract panicked: nope" } } } } });
+
+        let mock = MockTransport::new();
+        mock.on("EXPERIMENTAL_tx_status").respond(json!({
+            "final_execution_status": "FINAL",
+            "status": failure,
+            "transaction": {
+                "signer_id": "alice.near",
+                "public_key": "ed25519:6DSjZ8mvsRZDvFqFxo8tCKePG96omXW7eVYVSySmDk8e",
+                "nonce": 1,
+                "receiver_id": "app.near",
+                "actions": [],
+                "signature": near_crypto::Signature::empty(near_crypto::KeyType::ED25519),
+                "hash": tx_hash,
+            },
+            "transaction_outcome": outcome(tx_hash, "alice.near", vec![call], json!({ "SuccessReceiptId": call }), 10),
+            "receipts_outcome": [
+                outcome(refund, "alice.near", vec![], json!({ "SuccessValue": "" }), 0),
+                outcome(call, "app.near", vec![cross, refund], json!({ "SuccessReceiptId": cross }), 20),
+                outcome(cross, "token.near", vec![], failure.clone(), 30),
+                outcome(stray, "app.near", vec![], json!({ "SuccessValue": "" }), 40),
+            ],
+            "receipts": [
+                receipt(call, "alice.near", "app.near", json!([{ "FunctionCall": { "method_name": "swap", "args": "", "gas": 100_000_000_000_000u64, "deposit": "1000000000000000000000000" } }])),
+                receipt(refund, "system", "alice.near", json!([{ "Transfer": { "deposit": "500" } }])),
+            ],
+        }));
+        mock.on("EXPERIMENTAL_receipt").respond(receipt(
+            cross,
+            "app.near",
+            "token.near",
+            json!([{ "FunctionCall": { "method_name": "ft_transfer", "args": "", "gas": 30_000_000_000_000u64, "deposit": "1" } }]),
+        ));
+
+        let trace = mock
+            .client()
+            .trace_transaction(tx_hash, "alice.near".parse().unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(trace.receipts.len(), 1);
+        let root = &trace.receipts[0];
+        assert_eq!(root.receipt_id, call);
+        assert_eq!(root.deposit(), 10u128.pow(24));
+        assert_eq!(
+            root.children
+                .iter()
+                .map(|node| node.receipt_id)
+                .collect::<Vec<_>>(),
+            vec![cross, refund]
+        );
+
+        let cross_node = &root.children[0];
+        assert_eq!(cross_node.predecessor_id().unwrap(), "app.near");
+        assert!(cross_node.is_failure());
+        assert!(root.children[1].is_refund());
+
+        assert_eq!(
+            trace
+                .orphans
+                .iter()
+                .map(|node| node.receipt_id)
+                .collect::<Vec<_>>(),
+            vec![stray]
+        );
+        assert_eq!(trace.total_gas_burnt(), 100);
+        assert_eq!(
+            trace
+                .failures()
+                .map(|node| node.receipt_id)
+                .collect::<Vec<_>>(),
+            vec![cross]
+        );
+
+        let text = trace.to_string();
+        assert!(
+            text.contains("FunctionCall(ft_transfer, 30.000 Tgas)"),
+            "{}",
+            text
+        );
+        assert!(text.contains("deposit 1 NEAR"), "{}", text);
+        assert!(text.contains("FAILED"), "{}", text);
+        assert!(
+            text.contains(&format!("unreachable from the transaction:\n└── {}", stray)),
+            "{}",
+            text
+        );
+
+        let json = trace.to_json();
+        assert_eq!(json["receipts"][0]["children"][0]["failed"], true);
+        assert_eq!(json["receipts"][0]["children"][1]["refund"], true);
+        assert_eq!(json["orphans"][0]["receipt_id"], json!(stray));
+    }
+}
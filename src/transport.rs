@@ -0,0 +1,209 @@
+//! Pluggable transports.
+//!
+//! A [`JsonRpcClient`](crate::JsonRpcClient) doesn't care how request payloads reach the server,
+//! it serializes the method call, hands the payload to a [`Transport`] and parses whatever comes back.
+//!
+//! By default, requests are sent over HTTP with [`reqwest::Client`], which implements [`Transport`].
+//! Any other type implementing the trait can be used in its place, like an in-process mock,
+//! a WebSocket connection, a Unix-socket sidecar or a recording proxy.
+//!
+//! ## Example
+//!
+//! ```
+//! use near_jsonrpc_client::{methods, transport, JsonRpcClient};
+//! use near_jsonrpc_client::transport::{Transport, TransportRequest, TransportResponse};
+//!
+//! #[derive(Debug)]
+//! struct AlwaysHealthy;
+//!
+//! impl Transport for AlwaysHealthy {
+//!     fn send(&self, _request: TransportRequest) -> transport::TransportFuture<'_> {
+//!         Box::pin(async {
+//!             Ok(TransportResponse::new(
+//!                 reqwest::StatusCode::OK,
+//!                 br#"{"jsonrpc":"2.0","id":"dontcare","result":null}"#.to_vec(),
+//!             ))
+//!         })
+//!     }
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = JsonRpcClient::with_transport(AlwaysHealthy).connect("http://localhost:3030");
+//!
+//! let response = client.call(methods::health::RpcHealthRequest).await?;
+//!
+//! assert!(matches!(response, methods::health::RpcHealthResponse));
+//! # Ok(())
+//! # }
+//! ```
+use std::{fmt, future::Future, pin::Pin, sync::Arc};
+
+use reqwest::{header::HeaderMap, StatusCode};
+
+use crate::errors::{JsonRpcTransportRecvError, JsonRpcTransportSendError, RpcTransportError};
+
+/// The future returned by [`Transport::send`].
+pub type TransportFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<TransportResponse, RpcTransportError>> + Send + 'a>>;
+
+/// A trait for types capable of delivering serialized JSON RPC payloads to a server.
+pub trait Transport: fmt::Debug + Send + Sync + 'static {
+    /// Send the request payload to the server and return its raw response.
+    ///
+    /// Non-OK status codes should be returned as part of the [`TransportResponse`],
+    /// the client is responsible for interpreting them.
+    fn send(&self, request: TransportRequest) -> TransportFuture<'_>;
+}
+
+/// A serialized JSON RPC request, ready to be sent.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct TransportRequest {
+    /// The address of the server the client is connected to.
+    pub server_addr: String,
+    /// Headers configured on the client.
+    pub headers: HeaderMap,
+    /// The serialized JSON RPC payload.
+    pub body: Vec<u8>,
+}
+
+impl TransportRequest {
+    pub(crate) fn new(server_addr: &str, headers: HeaderMap, body: Vec<u8>) -> Self {
+        Self {
+            server_addr: server_addr.to_string(),
+            headers,
+            body,
+        }
+    }
+}
+
+/// The raw response to a [`TransportRequest`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct TransportResponse {
+    /// The status code returned by the server.
+    pub status: StatusCode,
+    /// Headers returned by the server.
+    pub headers: HeaderMap,
+    /// The raw response body.
+    pub body: Vec<u8>,
+}
+
+impl TransportResponse {
+    /// Create a new response with the specified status code and body, and no headers.
+    pub fn new(status: StatusCode, body: Vec<u8>) -> Self {
+        Self {
+            status,
+            headers: HeaderMap::new(),
+            body,
+        }
+    }
+}
+
+impl<T: Transport> Transport for Arc<T> {
+    fn send(&self, request: TransportRequest) -> TransportFuture<'_> {
+        T::send(self, request)
+    }
+}
+
+impl Transport for reqwest::Client {
+    fn send(&self, request: TransportRequest) -> TransportFuture<'_> {
+        Box::pin(async move {
+            let response = self
+                .post(&request.server_addr)
+                .headers(request.headers)
+                .body(request.body)
+                .send()
+                .await
+                .map_err(|err| {
+                    RpcTransportError::SendError(JsonRpcTransportSendError::PayloadSendError(err))
+                })?;
+
+            let status = response.status();
+            let headers = response.headers().clone();
+            let body = response.bytes().await.map_err(|err| {
+                RpcTransportError::RecvError(JsonRpcTransportRecvError::PayloadRecvError(err))
+            })?;
+
+            Ok(TransportResponse {
+                status,
+                headers,
+                body: body.to_vec(),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{errors::*, methods, JsonRpcClient};
+
+    #[derive(Debug)]
+    struct Canned {
+        status: StatusCode,
+        body: &'static str,
+        seen: Arc<Mutex<Vec<TransportRequest>>>,
+    }
+
+    impl Transport for Canned {
+        fn send(&self, request: TransportRequest) -> TransportFuture<'_> {
+            self.seen.lock().unwrap().push(request);
+            Box::pin(async move { Ok(TransportResponse::new(self.status, self.body.into())) })
+        }
+    }
+
+    #[tokio::test]
+    async fn custom_transport_roundtrip() {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let client = JsonRpcClient::with_transport(Canned {
+            status: StatusCode::OK,
+            body: r#"{"jsonrpc":"2.0","id":"dontcare","result":null}"#,
+            seen: seen.clone(),
+        })
+        .connect("http://localhost:3030");
+
+        let response = client.call(methods::health::RpcHealthRequest).await;
+
+        assert!(
+            matches!(response, Ok(methods::health::RpcHealthResponse)),
+            "expected an Ok(RpcHealthResponse), found [{:?}]",
+            response
+        );
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 1);
+        assert_eq!(seen[0].server_addr, "http://localhost:3030");
+
+        let payload = serde_json::from_slice::<serde_json::Value>(&seen[0].body).unwrap();
+        assert_eq!(payload["method"], "health");
+    }
+
+    #[tokio::test]
+    async fn custom_transport_status() {
+        let client = JsonRpcClient::with_transport(Canned {
+            status: StatusCode::TOO_MANY_REQUESTS,
+            body: "",
+            seen: Default::default(),
+        })
+        .connect("http://localhost:3030");
+
+        let response = client.call(methods::health::RpcHealthRequest).await;
+
+        assert!(
+            matches!(
+                response,
+                Err(JsonRpcError::ServerError(
+                    JsonRpcServerError::ResponseStatusError(
+                        JsonRpcServerResponseStatusError::TooManyRequests { .. }
+                    )
+                ))
+            ),
+            "expected a TooManyRequests error, found [{:?}]",
+            response
+        );
+    }
+}
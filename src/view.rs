@@ -0,0 +1,385 @@
+//! Typed contract view calls.
+//!
+//! [`JsonRpcClient::view_function`] encodes the arguments, calls a view method on a contract, and decodes
+//! its result, returning the value along with the block it was computed at and the logs it emitted.
+//!
+//! Arguments and results are encoded as JSON by default. The [`Borsh`] codec, or any other type
+//! implementing [`Encode`] and [`Decode`], can be used instead with [`ViewFunction::encode_with`]
+//! and [`ViewFunction::decode_with`].
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use near_jsonrpc_client::JsonRpcClient;
+//! use serde::Deserialize;
+//! use serde_json::json;
+//!
+//! #[derive(Debug, Deserialize)]
+//! struct AccountStatus {
+//!     rating: f32,
+//!     given: u64,
+//!     received: u64,
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = JsonRpcClient::connect("https://rpc.testnet.near.org");
+//!
+//! let status = client
+//!     .view_function::<_, AccountStatus>(
+//!         "nosedive.testnet".parse()?,
+//!         "status",
+//!         &json!({ "account_id": "miraclx.testnet" }),
+//!     )
+//!     .await?;
+//!
+//! println!("{:?} as of #{}", status.value, status.block_height);
+//! # Ok(())
+//! # }
+//! ```
+use std::future::{Future, IntoFuture};
+use std::marker::PhantomData;
+use std::pin::Pin;
+
+use near_jsonrpc_primitives::types::query::QueryResponseKind;
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{AccountId, BlockHeight, BlockReference, Finality, FunctionArgs};
+use near_primitives::views::QueryRequest;
+use thiserror::Error;
+
+use crate::errors::JsonRpcError;
+use crate::{methods, JsonRpcClient};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Encodes values of type `T` into function arguments.
+pub trait Encode<T: ?Sized> {
+    /// Encode the value.
+    fn encode(&self, value: &T) -> Result<Vec<u8>, BoxError>;
+}
+
+/// Decodes function results into values of type `T`.
+pub trait Decode<T> {
+    /// Decode the bytes.
+    fn decode(&self, bytes: &[u8]) -> Result<T, BoxError>;
+}
+
+/// The JSON codec, for contracts built with `near-sdk`'s default serializer.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json;
+
+/// The Borsh codec.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Borsh;
+
+impl<T: serde::Serialize + ?Sized> Encode<T> for Json {
+    fn encode(&self, value: &T) -> Result<Vec<u8>, BoxError> {
+        Ok(serde_json::to_vec(value)?)
+    }
+}
+
+impl<T: serde::de::DeserializeOwned> Decode<T> for Json {
+    fn decode(&self, bytes: &[u8]) -> Result<T, BoxError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+impl<T: borsh::BorshSerialize + ?Sized> Encode<T> for Borsh {
+    fn encode(&self, value: &T) -> Result<Vec<u8>, BoxError> {
+        Ok(borsh::to_vec(value)?)
+    }
+}
+
+impl<T: borsh::BorshDeserialize> Decode<T> for Borsh {
+    fn decode(&self, bytes: &[u8]) -> Result<T, BoxError> {
+        Ok(borsh::from_slice(bytes)?)
+    }
+}
+
+/// Potential errors returned by a view call.
+#[derive(Debug, Error)]
+pub enum ViewFunctionError {
+    /// The arguments couldn't be encoded.
+    #[error("error while encoding arguments: [{0}]")]
+    ArgsEncodeError(BoxError),
+    /// The query failed, including when the contract panicked
+    /// (see [`RpcQueryError::ContractExecutionError`](methods::query::RpcQueryError::ContractExecutionError)).
+    #[error(transparent)]
+    QueryError(JsonRpcError<methods::query::RpcQueryError>),
+    /// The server responded to the query with something other than a call result.
+    #[error("unexpected response to the view call: [{0:?}]")]
+    UnexpectedQueryResponse(QueryResponseKind),
+    /// The contract returned a result that couldn't be decoded.
+    #[error("error while decoding result: [{source}]")]
+    ResultDecodeError {
+        source: BoxError,
+        /// The raw result returned by the contract.
+        result: Vec<u8>,
+    },
+}
+
+/// The decoded result of a view call.
+#[derive(Debug, Clone)]
+pub struct ViewFunctionResult<T> {
+    /// The value returned by the contract.
+    pub value: T,
+    /// The height of the block the call was executed at.
+    pub block_height: BlockHeight,
+    /// The hash of the block the call was executed at.
+    pub block_hash: CryptoHash,
+    /// Logs emitted by the contract.
+    pub logs: Vec<String>,
+}
+
+/// A pending view call.
+///
+/// Configure it with the builder methods, then `.await` it.
+///
+/// See the [`view`](self) module documentation for more information.
+#[derive(Debug)]
+pub struct ViewFunction<'a, A: ?Sized, O, E = Json, D = Json> {
+    client: JsonRpcClient,
+    account_id: AccountId,
+    method_name: String,
+    args: &'a A,
+    block_reference: BlockReference,
+    encoder: E,
+    decoder: D,
+    _output: PhantomData<fn() -> O>,
+}
+
+impl JsonRpcClient {
+    /// Call a view method on a contract, with typed arguments and result.
+    ///
+    /// See the [`view`](crate::view) module documentation for more information.
+    pub fn view_function<'a, A: ?Sized, O>(
+        &self,
+        account_id: AccountId,
+        method_name: impl Into<String>,
+        args: &'a A,
+    ) -> ViewFunction<'a, A, O> {
+        ViewFunction {
+            client: self.clone(),
+            account_id,
+            method_name: method_name.into(),
+            args,
+            block_reference: BlockReference::Finality(Finality::Final),
+            encoder: Json,
+            decoder: Json,
+            _output: PhantomData,
+        }
+    }
+}
+
+impl<'a, A: ?Sized, O, E, D> ViewFunction<'a, A, O, E, D> {
+    /// Set the block the call is executed at.
+    ///
+    /// Defaults to the latest final block.
+    pub fn block_reference(mut self, block_reference: BlockReference) -> Self {
+        self.block_reference = block_reference;
+        self
+    }
+
+    /// Encode the arguments with another codec.
+    pub fn encode_with<E2: Encode<A>>(self, encoder: E2) -> ViewFunction<'a, A, O, E2, D> {
+        ViewFunction {
+            client: self.client,
+            account_id: self.account_id,
+            method_name: self.method_name,
+            args: self.args,
+            block_reference: self.block_reference,
+            encoder,
+            decoder: self.decoder,
+            _output: PhantomData,
+        }
+    }
+
+    /// Decode the result with another codec.
+    pub fn decode_with<D2: Decode<O>>(self, decoder: D2) -> ViewFunction<'a, A, O, E, D2> {
+        ViewFunction {
+            client: self.client,
+            account_id: self.account_id,
+            method_name: self.method_name,
+            args: self.args,
+            block_reference: self.block_reference,
+            encoder: self.encoder,
+            decoder,
+            _output: PhantomData,
+        }
+    }
+}
+
+impl<'a, A, O, E, D> IntoFuture for ViewFunction<'a, A, O, E, D>
+where
+    A: ?Sized,
+    O: Send + 'static,
+    E: Encode<A>,
+    D: Decode<O> + Send + 'static,
+{
+    type Output = Result<ViewFunctionResult<O>, ViewFunctionError>;
+    type IntoFuture = Pin<Box<dyn Future<Output = Self::Output> + Send>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        // encode eagerly, so the future doesn't borrow the arguments
+        let args = self.encoder.encode(self.args);
+        let Self {
+            client,
+            account_id,
+            method_name,
+            block_reference,
+            decoder,
+            ..
+        } = self;
+
+        Box::pin(async move {
+            let args = args.map_err(ViewFunctionError::ArgsEncodeError)?;
+
+            let response = client
+                .call(methods::query::RpcQueryRequest {
+                    block_reference,
+                    request: QueryRequest::CallFunction {
+                        account_id,
+                        method_name,
+                        args: FunctionArgs::from(args),
+                    },
+                })
+                .await
+                .map_err(ViewFunctionError::QueryError)?;
+
+            let call_result = match response.kind {
+                QueryResponseKind::CallResult(call_result) => call_result,
+                kind => return Err(ViewFunctionError::UnexpectedQueryResponse(kind)),
+            };
+
+            let value = match decoder.decode(&call_result.result) {
+                Ok(value) => value,
+                Err(source) => {
+                    return Err(ViewFunctionError::ResultDecodeError {
+                        source,
+                        result: call_result.result,
+                    })
+                }
+            };
+
+            Ok(ViewFunctionResult {
+                value,
+                block_height: response.block_height,
+                block_hash: response.block_hash,
+                logs: call_result.logs,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::transport::{Transport, TransportFuture, TransportRequest, TransportResponse};
+
+    /// A contract that returns its arguments, as long as they're valid UTF-8.
+    #[derive(Debug)]
+    struct Echo;
+
+    impl Transport for Echo {
+        fn send(&self, request: TransportRequest) -> TransportFuture<'_> {
+            let request = serde_json::from_slice::<Value>(&request.body).unwrap();
+            let params = &request["params"];
+            assert_eq!(params["request_type"], "call_function");
+            assert_eq!(params["method_name"], "echo");
+
+            let args =
+                serde_json::from_value::<FunctionArgs>(params["args_base64"].clone()).unwrap();
+            let result = match std::str::from_utf8(&args) {
+                Ok(_) => json!({
+                    "result": args.to_vec(),
+                    "logs": ["echoed"],
+                    "block_height": 7,
+                    "block_hash": CryptoHash::default(),
+                }),
+                Err(_) => json!({
+                    "error": "wasm execution failed with error: FunctionCallError(HostError(GuestPanic { panic_msg: \"invalid utf-8\" }))",
+                    "logs": [],
+                    "block_height": 7,
+                    "block_hash": CryptoHash::default(),
+                }),
+            };
+
+            let message = json!({ "jsonrpc": "2.0", "id": "dontcare", "result": result });
+            Box::pin(async move {
+                Ok(TransportResponse::new(
+                    StatusCode::OK,
+                    message.to_string().into(),
+                ))
+            })
+        }
+    }
+
+    fn client() -> JsonRpcClient {
+        JsonRpcClient::with_transport(Echo).connect("http://localhost:3030")
+    }
+
+    #[tokio::test]
+    async fn json_roundtrip() {
+        let response = client()
+            .view_function::<_, Value>(
+                "echo.near".parse().unwrap(),
+                "echo",
+                &json!({ "greeting": "hello" }),
+            )
+            .await
+            .expect("view call must succeed");
+
+        assert_eq!(response.value, json!({ "greeting": "hello" }));
+        assert_eq!(response.block_height, 7);
+        assert_eq!(response.logs, ["echoed"]);
+    }
+
+    #[tokio::test]
+    async fn borsh_roundtrip() {
+        let response = client()
+            .view_function::<_, String>("echo.near".parse().unwrap(), "echo", "hello")
+            .encode_with(Borsh)
+            .decode_with(Json)
+            .await;
+
+        // a borsh string is length-prefixed, which isn't valid JSON
+        assert!(
+            matches!(response, Err(ViewFunctionError::ResultDecodeError { .. })),
+            "expected a ResultDecodeError, found [{:?}]",
+            response
+        );
+
+        let response = client()
+            .view_function::<_, String>("echo.near".parse().unwrap(), "echo", "hello")
+            .encode_with(Borsh)
+            .decode_with(Borsh)
+            .await
+            .expect("view call must succeed");
+
+        assert_eq!(response.value, "hello");
+    }
+
+    #[tokio::test]
+    async fn contract_errors_are_query_errors() {
+        let response = client()
+            .view_function::<_, ()>("echo.near".parse().unwrap(), "echo", &[0xffu8])
+            .encode_with(Borsh)
+            .await;
+
+        assert!(
+            matches!(
+                response,
+                Err(ViewFunctionError::QueryError(ref err))
+                    if matches!(
+                        err.handler_error(),
+                        Some(methods::query::RpcQueryError::ContractExecutionError { .. })
+                    )
+            ),
+            "expected a ContractExecutionError, found [{:?}]",
+            response
+        );
+    }
+}
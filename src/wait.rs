@@ -0,0 +1,349 @@
+//! Waiting for transactions to reach an execution status.
+//!
+//! Transactions submitted with [`broadcast_tx_async`](crate::methods::broadcast_tx_async) only return their hash,
+//! so finding out how they went means polling the [`tx`](crate::methods::tx) method until they get there.
+//! [`JsonRpcClient::wait_for_transaction`] does that for you, with a configurable backoff between polls and
+//! an overall deadline.
+//!
+//! Until the transaction reaches the requested status, `UnknownTransaction` responses, as well as any
+//! [retryable](crate::errors::JsonRpcError::is_retryable) error, are retried. When the deadline is exceeded,
+//! a [`WaitForTransactionError::TimeoutError`] is returned with the last status the server reported.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use near_jsonrpc_client::{methods, JsonRpcClient};
+//! use near_primitives::views::TxExecutionStatus;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let signed_transaction = unimplemented!();
+//! let client = JsonRpcClient::connect("https://rpc.testnet.near.org");
+//!
+//! let tx_hash = client
+//!     .call(methods::broadcast_tx_async::RpcBroadcastTxAsyncRequest { signed_transaction })
+//!     .await?;
+//!
+//! let response = client
+//!     .wait_for_transaction(tx_hash, "miraclx.testnet".parse()?, TxExecutionStatus::Final)
+//!     .timeout(Duration::from_secs(120))
+//!     .await?;
+//!
+//! println!("{:#?}", response.final_execution_outcome);
+//! # Ok(())
+//! # }
+//! ```
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::AccountId;
+use near_primitives::views::TxExecutionStatus;
+use thiserror::Error;
+
+use crate::errors::*;
+use crate::methods::tx::{RpcTransactionError, RpcTransactionResponse, TransactionInfo};
+use crate::{methods, JsonRpcClient};
+
+/// Potential errors returned while waiting for a transaction.
+#[derive(Debug, Error)]
+pub enum WaitForTransactionError {
+    /// The transaction didn't reach the requested status before the deadline.
+    #[error(
+        "transaction didn't reach [{wait_until:?}] within {timeout:?}, last observed status: [{last_status:?}]"
+    )]
+    TimeoutError {
+        /// The status the transaction was expected to reach.
+        wait_until: TxExecutionStatus,
+        /// The last status reported by the server, `None` if the transaction was never found.
+        last_status: Option<TxExecutionStatus>,
+        /// The overall deadline that was exceeded.
+        timeout: Duration,
+    },
+    /// The transaction status couldn't be determined.
+    #[error(transparent)]
+    TransactionError(JsonRpcError<RpcTransactionError>),
+}
+
+/// A pending wait for a transaction to reach an execution status.
+///
+/// Configure it with the builder methods, then `.await` it.
+///
+/// See the [`wait`](self) module documentation for more information.
+#[derive(Debug, Clone)]
+pub struct WaitForTransaction {
+    client: JsonRpcClient,
+    tx_hash: CryptoHash,
+    sender_account_id: AccountId,
+    wait_until: TxExecutionStatus,
+    poll_interval: Duration,
+    max_poll_interval: Duration,
+    multiplier: f64,
+    timeout: Duration,
+}
+
+impl JsonRpcClient {
+    /// Wait for a transaction to reach the specified execution status.
+    ///
+    /// See the [`wait`](crate::wait) module documentation for more information.
+    pub fn wait_for_transaction(
+        &self,
+        tx_hash: CryptoHash,
+        sender_account_id: AccountId,
+        wait_until: TxExecutionStatus,
+    ) -> WaitForTransaction {
+        WaitForTransaction {
+            client: self.clone(),
+            tx_hash,
+            sender_account_id,
+            wait_until,
+            poll_interval: Duration::from_secs(1),
+            max_poll_interval: Duration::from_secs(5),
+            multiplier: 1.5,
+            timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+impl WaitForTransaction {
+    /// Set the delay before the second poll.
+    ///
+    /// Defaults to 1s.
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Set the upper bound for the delay between polls.
+    ///
+    /// Defaults to 5s.
+    pub fn max_poll_interval(mut self, interval: Duration) -> Self {
+        self.max_poll_interval = interval;
+        self
+    }
+
+    /// Set the factor by which the delay grows after every poll.
+    ///
+    /// Defaults to 1.5.
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// Set the overall deadline, measured from the first poll.
+    ///
+    /// Defaults to 60s.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    async fn wait(self) -> Result<RpcTransactionResponse, WaitForTransactionError> {
+        let started_at = Instant::now();
+        let mut interval = self.poll_interval;
+        let mut last_status = None;
+
+        loop {
+            let timed_out = || WaitForTransactionError::TimeoutError {
+                wait_until: self.wait_until.clone(),
+                last_status: last_status.clone(),
+                timeout: self.timeout,
+            };
+
+            let remaining = self
+                .timeout
+                .checked_sub(started_at.elapsed())
+                .ok_or_else(timed_out)?;
+
+            let request = methods::tx::RpcTransactionStatusRequest {
+                transaction_info: TransactionInfo::TransactionId {
+                    tx_hash: self.tx_hash,
+                    sender_account_id: self.sender_account_id.clone(),
+                },
+                // report the current status right away, so it can be tracked
+                wait_until: TxExecutionStatus::None,
+            };
+
+            let response = tokio::time::timeout(remaining, self.client.call(request))
+                .await
+                .map_err(|_| timed_out())?;
+
+            match response {
+                Ok(response) if has_reached(&response.final_execution_status, &self.wait_until) => {
+                    return Ok(response);
+                }
+                Ok(response) => {
+                    log::debug!(
+                        "transaction [{}] is at [{:?}], waiting for [{:?}]",
+                        self.tx_hash,
+                        response.final_execution_status,
+                        self.wait_until
+                    );
+                    last_status = Some(response.final_execution_status);
+                }
+                Err(err) if is_pending(&err) => {
+                    log::debug!("transaction [{}] is pending: [{}]", self.tx_hash, err);
+                }
+                Err(err) => return Err(WaitForTransactionError::TransactionError(err)),
+            }
+
+            let remaining = self.timeout.saturating_sub(started_at.elapsed());
+            tokio::time::sleep(interval.min(remaining)).await;
+
+            interval = Duration::try_from_secs_f64(interval.as_secs_f64() * self.multiplier)
+                .unwrap_or(self.max_poll_interval)
+                .min(self.max_poll_interval);
+        }
+    }
+}
+
+impl IntoFuture for WaitForTransaction {
+    type Output = Result<RpcTransactionResponse, WaitForTransactionError>;
+    type IntoFuture = Pin<Box<dyn Future<Output = Self::Output> + Send>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(self.wait())
+    }
+}
+
+/// Whether or not a transaction at the `current` status has reached the `target` status.
+///
+/// Statuses aren't totally ordered, a transaction can be executed before or after its block is finalized.
+pub fn has_reached(current: &TxExecutionStatus, target: &TxExecutionStatus) -> bool {
+    use TxExecutionStatus::*;
+    match target {
+        None => true,
+        Included => !matches!(current, None),
+        ExecutedOptimistic => matches!(current, ExecutedOptimistic | Executed | Final),
+        IncludedFinal => matches!(current, IncludedFinal | Executed | Final),
+        Executed => matches!(current, Executed | Final),
+        Final => matches!(current, Final),
+    }
+}
+
+/// Whether or not the error means the transaction is still on its way.
+fn is_pending(err: &JsonRpcError<RpcTransactionError>) -> bool {
+    err.is_not_found() || err.is_retryable()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use reqwest::StatusCode;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::transport::{Transport, TransportFuture, TransportRequest, TransportResponse};
+
+    /// Reports the scripted statuses in order, `None` being an unknown transaction.
+    /// The last status is repeated forever.
+    #[derive(Debug)]
+    struct Progress {
+        statuses: Mutex<Vec<Option<&'static str>>>,
+    }
+
+    impl Transport for Progress {
+        fn send(&self, request: TransportRequest) -> TransportFuture<'_> {
+            let request = serde_json::from_slice::<Value>(&request.body).unwrap();
+            assert_eq!(request["params"]["wait_until"], "NONE");
+
+            let mut statuses = self.statuses.lock().unwrap();
+            let status = if statuses.len() > 1 {
+                statuses.remove(0)
+            } else {
+                statuses[0]
+            };
+
+            let message = match status {
+                Some(status) => json!({
+                    "jsonrpc": "2.0",
+                    "id": "dontcare",
+                    "result": { "final_execution_status": status },
+                }),
+                None => json!({
+                    "jsonrpc": "2.0",
+                    "id": "dontcare",
+                    "error": {
+                        "code": -32000,
+                        "message": "Server error",
+                        "name": "HANDLER_ERROR",
+                        "cause": {
+                            "name": "UNKNOWN_TRANSACTION",
+                            "info": { "requested_transaction_hash": CryptoHash::default() },
+                        },
+                    },
+                }),
+            };
+
+            Box::pin(async move {
+                Ok(TransportResponse::new(
+                    StatusCode::OK,
+                    message.to_string().into(),
+                ))
+            })
+        }
+    }
+
+    fn wait_for(
+        statuses: Vec<Option<&'static str>>,
+        wait_until: TxExecutionStatus,
+    ) -> WaitForTransaction {
+        JsonRpcClient::with_transport(Progress {
+            statuses: Mutex::new(statuses),
+        })
+        .connect("http://localhost:3030")
+        .wait_for_transaction(
+            CryptoHash::default(),
+            "alice.near".parse().unwrap(),
+            wait_until,
+        )
+        .poll_interval(Duration::from_millis(1))
+    }
+
+    #[tokio::test]
+    async fn waits_for_status() {
+        let response = wait_for(
+            vec![None, Some("INCLUDED"), Some("EXECUTED"), Some("FINAL")],
+            TxExecutionStatus::Executed,
+        )
+        .await
+        .expect("transaction must be executed");
+
+        assert_eq!(response.final_execution_status, TxExecutionStatus::Executed);
+    }
+
+    #[tokio::test]
+    async fn times_out_with_last_status() {
+        let err = wait_for(vec![None, Some("INCLUDED")], TxExecutionStatus::Final)
+            .timeout(Duration::from_millis(50))
+            .await
+            .expect_err("transaction must not be finalized");
+
+        assert!(
+            matches!(
+                err,
+                WaitForTransactionError::TimeoutError {
+                    last_status: Some(TxExecutionStatus::Included),
+                    ..
+                }
+            ),
+            "expected a TimeoutError at Included, found [{:?}]",
+            err
+        );
+    }
+
+    #[test]
+    fn partially_ordered_statuses() {
+        use TxExecutionStatus::*;
+
+        assert!(has_reached(&Final, &Executed));
+        assert!(has_reached(&Executed, &IncludedFinal));
+        assert!(!has_reached(&ExecutedOptimistic, &IncludedFinal));
+        assert!(!has_reached(&IncludedFinal, &ExecutedOptimistic));
+        assert!(!has_reached(&None, &Included));
+    }
+}